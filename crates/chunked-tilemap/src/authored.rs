@@ -0,0 +1,290 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+  bundle::{ChunkedTilemap, ChunkAnchor},
+  chunk_data::ChunkData,
+  chunks::local_tile_index_to_global,
+  random::SeededRng,
+  stamps::Stamp,
+  tiled::{GidMapping, TiledMap},
+};
+
+/// Keeps the blend rolls apart from the rolls of the generator of the same layer.
+const AUTHORED_ID: u64 = 0x6175_7468_6f72_6564;
+
+/// Rectangle of the world whose tiles come from authored data instead of the generator.
+///
+/// With a `blend` band, tiles up to `blend` tiles inside the edge are picked from the authored data or from the
+/// generated chunk at random, the authored tiles getting more likely further inside, so the seam is not visible.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuthoredRegion{
+  /// North-west corner, as a global tile index.
+  pub min: IVec2,
+  pub size: UVec2,
+  /// Rows from north to south, `None` is an empty tile.
+  pub tiles: Vec<Option<u32>>,
+  pub blend: u32,
+}
+
+impl AuthoredRegion{
+  pub fn new(min: IVec2, size: UVec2)->AuthoredRegion{
+    AuthoredRegion{
+      min,
+      size,
+      tiles: vec![None; (size.x*size.y) as usize],
+      blend: 0,
+    }
+  }
+
+  /// Covers the footprint of a stamp layer placed at `position`, tiles the stamp leaves out are empty.
+  pub fn from_stamp(stamp: &Stamp, layer: &str, position: IVec2)->AuthoredRegion{
+    let (min, _) = stamp.footprint(position);
+    let mut region = AuthoredRegion::new(min, stamp.size());
+    for (global_tile_index, tile) in stamp.tiles(layer, position){
+      region.set(global_tile_index, Some(tile));
+    }
+    region
+  }
+
+  /// Covers a layer of a Tiled map, whose tile (0, 0) lands on `position`.
+  pub fn from_tiled(map: &TiledMap, layer: &str, mapping: &GidMapping, position: IVec2)->AuthoredRegion{
+    AuthoredRegion::from_stamp(&map.to_stamp(mapping), layer, position)
+  }
+
+  /// Saves an area of a tilemap, from `min` to `max` included. Tiles of chunks which are not loaded are empty.
+  pub fn from_tilemap(tilemap: &ChunkedTilemap, min: IVec2, max: IVec2)->AuthoredRegion{
+    let mut region = AuthoredRegion::new(min, (max - min + IVec2::ONE).max(IVec2::ZERO).as_uvec2());
+    for y in min.y..=max.y{
+      for x in min.x..=max.x{
+        let global_tile_index = IVec2::new(x, y);
+        region.set(global_tile_index, tilemap.generated_tile(global_tile_index).flatten());
+      }
+    }
+    region
+  }
+
+  pub fn with_blend(mut self, blend: u32)->AuthoredRegion{
+    self.blend = blend;
+    self
+  }
+
+  /// South-east corner, included.
+  pub fn max(&self)->IVec2{
+    self.min + self.size.as_ivec2() - IVec2::ONE
+  }
+
+  pub fn contains(&self, global_tile_index: IVec2)->bool{
+    global_tile_index.cmpge(self.min).all() && global_tile_index.cmple(self.max()).all()
+  }
+
+  fn index(&self, global_tile_index: IVec2)->Option<usize>{
+    let position = global_tile_index - self.min;
+    self.contains(global_tile_index).then_some((position.y*self.size.x as i32 + position.x) as usize)
+  }
+
+  pub fn get(&self, global_tile_index: IVec2)->Option<u32>{
+    self.index(global_tile_index).and_then(|index| self.tiles[index])
+  }
+
+  /// Sets a tile, returns `false` if it is outside of the region.
+  pub fn set(&mut self, global_tile_index: IVec2, tile: Option<u32>)->bool{
+    match self.index(global_tile_index){
+      Some(index) => {
+        self.tiles[index] = tile;
+        true
+      }
+      None => false
+    }
+  }
+
+  /// Authored tile if it wins over the generated one, `Some(None)` being an authored empty tile.
+  /// The blend band is dithered with the seeds of the generated layer, see `random::layer_id`.
+  pub fn tile(&self, global_tile_index: IVec2, world_seed: u64, layer_id: u64)->Option<Option<u32>>{
+    if !self.contains(global_tile_index) {
+      return None;
+    }
+    let max = self.max();
+    let distance = (global_tile_index.x - self.min.x)
+      .min(max.x - global_tile_index.x)
+      .min(global_tile_index.y - self.min.y)
+      .min(max.y - global_tile_index.y) as u32;
+    if distance < self.blend {
+      let chance = (distance + 1) as f64/(self.blend + 1) as f64;
+      if !SeededRng::for_tile(world_seed, layer_id ^ AUTHORED_ID, global_tile_index).gen_bool(chance) {
+        return None;
+      }
+    }
+    Some(self.get(global_tile_index))
+  }
+
+  /// Whether every tile of a chunk comes from the region, past its blend band.
+  pub fn covers(&self, chunk_index: IVec2, chunk_size: UVec2, anchor: ChunkAnchor)->bool{
+    let blend = self.blend as i32;
+    let corners = [
+      IVec2::ZERO,
+      chunk_size.as_ivec2() - IVec2::ONE,
+    ];
+    corners.iter().all(|&local_tile_index|{
+      let global_tile_index = local_tile_index_to_global(chunk_index, chunk_size, local_tile_index, anchor);
+      global_tile_index.cmpge(self.min + blend).all() && global_tile_index.cmple(self.max() - blend).all()
+    })
+  }
+}
+
+/// Authored regions of a tilemap, chunks are served from them and generated only where they don't cover
+/// the whole chunk. Later regions go over earlier ones.
+#[derive(Component, Debug, Clone, Default)]
+pub struct AuthoredRegions{
+  pub regions: Vec<AuthoredRegion>,
+}
+
+impl AuthoredRegions{
+  pub fn new()->AuthoredRegions{
+    AuthoredRegions::default()
+  }
+
+  pub fn with_region(mut self, region: AuthoredRegion)->AuthoredRegions{
+    self.regions.push(region);
+    self
+  }
+
+  /// Puts the authored tiles of a chunk over its generated data.
+  pub fn apply(&self, chunk_index: IVec2, anchor: ChunkAnchor, world_seed: u64, layer_id: u64, data: &mut ChunkData){
+    for region in self.regions.iter(){
+      for y in 0..data.size.y{
+        for x in 0..data.size.x{
+          let global_tile_index = local_tile_index_to_global(chunk_index, data.size, IVec2::new(x as i32, y as i32), anchor);
+          if let Some(tile) = region.tile(global_tile_index, world_seed, layer_id) {
+            data.set(UVec2::new(x, y), tile);
+          }
+        }
+      }
+    }
+  }
+
+  /// Data of a chunk, `generate` is only called if the authored regions don't cover it.
+  pub fn serve(&self, chunk_index: IVec2, chunk_size: UVec2, anchor: ChunkAnchor, world_seed: u64, layer_id: u64, generate: impl FnOnce()->ChunkData)->ChunkData{
+    let covered = self.regions.iter().any(|region| region.covers(chunk_index, chunk_size, anchor));
+    let mut data = if covered { ChunkData::new(chunk_size) } else { generate() };
+    self.apply(chunk_index, anchor, world_seed, layer_id, &mut data);
+    data
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use rstest::rstest;
+  use crate::{bundle::{ChunkedTilemap, ChunkAnchor}, chunk_data::ChunkData, chunks::local_tile_index_to_global, random::layer_id, stamps::Stamp};
+  use super::{AuthoredRegion, AuthoredRegions};
+
+  const GRASS: u32 = 1;
+  const FLOOR: u32 = 2;
+  const CHUNK_SIZE: UVec2 = UVec2{x: 4, y: 4};
+  const WORLD_SEED: u64 = 42;
+
+  fn floor(min: IVec2, size: UVec2)->AuthoredRegion{
+    let mut region = AuthoredRegion::new(min, size);
+    region.tiles.fill(Some(FLOOR));
+    region
+  }
+
+  fn chunk(regions: &AuthoredRegions, chunk_index: IVec2)->(ChunkData, bool){
+    let mut generated = false;
+    let data = regions.serve(chunk_index, CHUNK_SIZE, ChunkAnchor::Corner, WORLD_SEED, layer_id("ground"), ||{
+      generated = true;
+      ChunkData::filled(CHUNK_SIZE, GRASS)
+    });
+    (data, generated)
+  }
+
+  fn global(chunk_index: IVec2, x: i32, y: i32)->IVec2{
+    local_tile_index_to_global(chunk_index, CHUNK_SIZE, IVec2::new(x, y), ChunkAnchor::Corner)
+  }
+
+  #[test]
+  fn serve_test(){
+    // chunk (0, 0) and the west column of chunk (1, 0)
+    let min = global(IVec2::ZERO, 0, 3);
+    let regions = AuthoredRegions::new().with_region(floor(min, UVec2::new(5, 4)));
+    let (data, generated) = chunk(&regions, IVec2::ZERO);
+    assert!(!generated);
+    assert_eq!(data, ChunkData::filled(CHUNK_SIZE, FLOOR));
+    let (data, generated) = chunk(&regions, IVec2::X);
+    assert!(generated);
+    assert_eq!(data.iter().filter(|&(_, tile)| tile == FLOOR).count(), 4);
+    assert!(data.iter().all(|(position, tile)| (tile == FLOOR) == (position.x == 0)));
+    let (data, generated) = chunk(&regions, IVec2::new(-1, 0));
+    assert!(generated);
+    assert_eq!(data, ChunkData::filled(CHUNK_SIZE, GRASS));
+  }
+
+  #[rstest]
+  #[case(0)]
+  #[case(2)]
+  #[case(4)]
+  fn blend_test(
+    #[case] blend: u32,
+  ){
+    let region = floor(IVec2::new(-20, -20), UVec2::new(40, 40)).with_blend(blend);
+    let ring = |distance: i32| (0..40 - 2*distance).map(move |i| IVec2::new(-20 + distance + i, -20 + distance));
+    for distance in 0..blend as i32{
+      let authored = ring(distance).filter(|&tile| region.tile(tile, WORLD_SEED, layer_id("ground")).is_some()).count();
+      assert!(authored > 0 || distance == 0, "{distance}");
+      assert!(authored < 40 - 2*distance as usize, "{distance}");
+    }
+    assert!(ring(blend as i32).all(|tile| region.tile(tile, WORLD_SEED, layer_id("ground")) == Some(Some(FLOOR))));
+    assert_eq!(region.tile(IVec2::new(-21, 0), WORLD_SEED, layer_id("ground")), None);
+    assert!(region.covers(IVec2::ZERO, CHUNK_SIZE, ChunkAnchor::Corner));
+    assert_eq!(region.covers(IVec2::new(-5, 0), CHUNK_SIZE, ChunkAnchor::Corner), blend == 0);
+  }
+
+  #[test]
+  fn blend_follows_the_seed_test(){
+    let region = floor(IVec2::new(-20, -20), UVec2::new(40, 40)).with_blend(4);
+    let band = |world_seed: u64, layer: &str|->Vec<bool>{
+      (-20..20).map(|x| region.tile(IVec2::new(x, -19), world_seed, layer_id(layer)).is_some()).collect()
+    };
+    assert_eq!(band(WORLD_SEED, "ground"), band(WORLD_SEED, "ground"));
+    assert_ne!(band(WORLD_SEED, "ground"), band(WORLD_SEED + 1, "ground"));
+    assert_ne!(band(WORLD_SEED, "ground"), band(WORLD_SEED, "walls"));
+  }
+
+  #[test]
+  fn later_regions_win_test(){
+    let regions = AuthoredRegions::new()
+      .with_region(floor(global(IVec2::ZERO, 0, 3), CHUNK_SIZE))
+      .with_region(AuthoredRegion::new(global(IVec2::ZERO, 1, 2), UVec2::new(2, 2)));
+    let (data, generated) = chunk(&regions, IVec2::ZERO);
+    assert!(!generated);
+    // the second region is authored as empty
+    assert_eq!(data.iter().count(), 12);
+  }
+
+  #[test]
+  fn from_stamp_test(){
+    let stamp = Stamp::new()
+      .with_anchor(IVec2::new(1, 0))
+      .with_layer("ground", vec![vec![Some(FLOOR), None, Some(FLOOR)]]);
+    let region = AuthoredRegion::from_stamp(&stamp, "ground", IVec2::new(10, 5));
+    assert_eq!(region.min, IVec2::new(9, 5));
+    assert_eq!(region.max(), IVec2::new(11, 5));
+    assert_eq!(region.tile(IVec2::new(9, 5), WORLD_SEED, layer_id("ground")), Some(Some(FLOOR)));
+    assert_eq!(region.tile(IVec2::new(10, 5), WORLD_SEED, layer_id("ground")), Some(None));
+    assert_eq!(region.tile(IVec2::new(12, 5), WORLD_SEED, layer_id("ground")), None);
+  }
+
+  #[test]
+  fn from_tilemap_test(){
+    let mut tilemap = ChunkedTilemap{
+      chunk_size: CHUNK_SIZE,
+      ..Default::default()
+    };
+    tilemap.generated.insert(IVec2::ZERO, ChunkData::filled(CHUNK_SIZE, FLOOR));
+    let min = tilemap.local_tile_index_to_global(IVec2::ZERO, IVec2::new(2, 2));
+    let region = AuthoredRegion::from_tilemap(&tilemap, min, min + IVec2::new(3, 0));
+    assert_eq!(region.size, UVec2::new(4, 1));
+    assert_eq!(region.tiles, vec![Some(FLOOR), Some(FLOOR), None, None]);
+  }
+}
@@ -0,0 +1,237 @@
+use bevy::{prelude::*, utils::{HashMap, HashSet}};
+use bevy_ecs_tilemap::tiles::{TilePos, TileStorage, TileTexture};
+
+use crate::{bundle::ChunkedTilemap, fill_chunk::FillChunkEvent, tiles::{Direction, SetTileEvent}, TilemapChunk};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AutotileMode{
+  /// 16 tiles, by which of the cardinal neighbors share the terrain, see `four_bit_mask`.
+  #[default]
+  FourBit,
+  /// 47 tiles ("blob" tileset), diagonal neighbors count as well, see `blob_mask`.
+  Blob,
+}
+
+impl AutotileMode{
+  pub fn tile_count(&self)->u32{
+    match self{
+      AutotileMode::FourBit => 16,
+      AutotileMode::Blob => 47,
+    }
+  }
+
+  /// Index of the tile in a terrain's tileset, `same` tells whether the neighbor in a direction shares the terrain.
+  pub fn index(&self, same: impl Fn(Direction)->bool)->u32{
+    match self{
+      AutotileMode::FourBit => four_bit_mask(same) as u32,
+      AutotileMode::Blob => BLOB_INDEXES[blob_mask(same) as usize] as u32,
+    }
+  }
+}
+
+/// North 1, East 2, South 4, West 8.
+pub fn four_bit_mask(same: impl Fn(Direction)->bool)->u8{
+  Direction::CARDINAL.iter().enumerate()
+    .filter(|&(_, &direction)| same(direction))
+    .fold(0, |mask, (bit, _)| mask | 1 << bit)
+}
+
+/// Bit `1 << direction as usize` for every neighbor sharing the terrain, i.e. North 1, North-East 2, East 4 and so on.
+/// Diagonal neighbors only count when both sides next to them do, which leaves 47 different masks.
+/// Tiles of a blob tileset are ordered by ascending mask.
+pub fn blob_mask(same: impl Fn(Direction)->bool)->u8{
+  let mask = Direction::ALL.iter()
+    .filter(|&&direction| same(direction))
+    .fold(0, |mask, &direction| mask | 1 << direction as usize);
+  reduce_blob_mask(mask)
+}
+
+const fn reduce_blob_mask(mask: u8)->u8{
+  let mut reduced = mask & 0b0101_0101;
+  let mut corner = 1;
+  while corner < 8 {
+    let sides = 1 << (corner - 1) | 1 << ((corner + 1) % 8);
+    if mask & 1 << corner != 0 && mask & sides == sides {
+      reduced |= 1 << corner;
+    }
+    corner += 2;
+  }
+  reduced
+}
+
+const BLOB_INDEXES: [u8; 256] = blob_indexes();
+
+const fn blob_indexes()->[u8; 256]{
+  let mut indexes = [0; 256];
+  let mut count = 0;
+  let mut mask = 0;
+  while mask < 256 {
+    if reduce_blob_mask(mask as u8) == mask as u8 {
+      indexes[mask] = count;
+      count += 1;
+    }
+    mask += 1;
+  }
+  indexes
+}
+
+/// Picks the textures of a `ChunkedTilemap`'s tiles from the terrains its chunks were filled with.
+///
+/// Chunks hold terrain ids (see `ChunkedTilemap::generated`), tiles of every terrain listed in `terrains`
+/// get the texture of their tileset (starting at the given texture index) matching which neighbors share the terrain,
+/// other terrains are used as textures as they are. Neighbors in chunks that are not filled yet count as the same
+/// terrain, tiles along the border get re-evaluated once those chunks are filled, or when tiles get set by `SetTileEvent`.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Autotile{
+  pub mode: AutotileMode,
+  pub terrains: HashMap<u32, u32>,
+  pending: HashSet<IVec2>,
+}
+
+impl Autotile{
+  pub fn new(mode: AutotileMode)->Autotile{
+    Autotile{
+      mode,
+      ..Default::default()
+    }
+  }
+
+  pub fn with_terrain(mut self, terrain: u32, first_texture: u32)->Autotile{
+    self.terrains.insert(terrain, first_texture);
+    self
+  }
+
+  /// Texture of a tile, `None` if it is empty or its chunk has no data.
+  pub fn texture(&self, tilemap: &ChunkedTilemap, global_tile_index: IVec2)->Option<u32>{
+    let terrain = tilemap.generated_tile(global_tile_index)??;
+    let first_texture = match self.terrains.get(&terrain){
+      Some(&first_texture) => first_texture,
+      None => return Some(terrain)
+    };
+    let same = |direction: Direction| match tilemap.generated_tile(global_tile_index + direction.offset()){
+      Some(tile) => tile == Some(terrain),
+      None => true,
+    };
+    Some(first_texture + self.mode.index(same))
+  }
+}
+
+pub fn mark_autotiles(
+  mut er_fill_chunk: EventReader<FillChunkEvent>,
+  mut er_set_tile: EventReader<SetTileEvent>,
+  mut q_tilemaps: Query<(&ChunkedTilemap, &mut Autotile)>,
+){
+  for event in er_fill_chunk.iter(){
+    let tilemap = q_tilemaps.iter_mut()
+      .find(|(tilemap, _)| tilemap.chunk_entities.get(&event.chunk_index) == Some(&event.chunk_entity));
+    if let Some((tilemap, mut autotile)) = tilemap {
+      // the chunk itself and the tiles around it
+      let size = tilemap.chunk_size.as_ivec2();
+      for y in -1..=size.y{
+        for x in -1..=size.x{
+          autotile.pending.insert(tilemap.local_tile_index_to_global(event.chunk_index, IVec2::new(x, y)));
+        }
+      }
+    }
+  }
+  for event in er_set_tile.iter(){
+    if let Ok((_, mut autotile)) = q_tilemaps.get_mut(event.tilemap_entity) {
+      autotile.pending.insert(event.global_tile_index);
+      for direction in Direction::ALL{
+        autotile.pending.insert(event.global_tile_index + direction.offset());
+      }
+    }
+  }
+}
+
+pub fn apply_autotiles(
+  mut q_tilemaps: Query<(&ChunkedTilemap, &mut Autotile)>,
+  q_storages: Query<&TileStorage, With<TilemapChunk>>,
+  mut q_textures: Query<&mut TileTexture>,
+){
+  for (tilemap, mut autotile) in q_tilemaps.iter_mut(){
+    if autotile.pending.is_empty() {
+      continue;
+    }
+    let pending: Vec<IVec2> = autotile.pending.drain().collect();
+    for global_tile_index in pending{
+      let texture = match autotile.texture(tilemap, global_tile_index){
+        Some(texture) => texture,
+        None => continue
+      };
+      // every loaded repetition of the tile in a wrapping world
+      for (chunk_index, local_tile_index) in tilemap.loaded_tiles(global_tile_index){
+        let entity = tilemap.chunk_entities.get(&chunk_index)
+          .and_then(|&chunk_entity| q_storages.get(chunk_entity).ok())
+          .and_then(|storage| storage.get(&TilePos{x: local_tile_index.x as u32, y: local_tile_index.y as u32}));
+        match entity.and_then(|entity| q_textures.get_mut(entity).ok()){
+          Some(mut tile_texture) => {
+            if tile_texture.0 != texture {
+              tile_texture.0 = texture;
+            }
+          }
+          // spawned tiles get registered a frame later
+          None => {
+            autotile.pending.insert(global_tile_index);
+          }
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use rstest::rstest;
+  use crate::{bundle::ChunkedTilemap, chunk_data::ChunkData, tiles::Direction};
+  use super::{Autotile, AutotileMode, blob_mask, four_bit_mask, BLOB_INDEXES};
+
+  #[rstest]
+  #[case(&[], 0, 0)]
+  #[case(&[Direction::North, Direction::West], 9, 0b0100_0001)]
+  #[case(&[Direction::NorthEast, Direction::SouthWest], 0, 0)]
+  #[case(&[Direction::North, Direction::NorthEast, Direction::East], 3, 0b0000_0111)]
+  #[case(&[Direction::North, Direction::NorthEast, Direction::South], 5, 0b0001_0001)]
+  #[case(&Direction::ALL, 15, 0b1111_1111)]
+  fn mask_test(
+    #[case] same: &[Direction],
+    #[case] four_bit: u8,
+    #[case] blob: u8,
+  ){
+    assert_eq!(four_bit_mask(|direction| same.contains(&direction)), four_bit);
+    assert_eq!(blob_mask(|direction| same.contains(&direction)), blob);
+  }
+
+  #[test]
+  fn blob_indexes_test(){
+    let mut indexes: Vec<u8> = (0..=255u8)
+      .filter(|&mask| blob_mask(|direction| mask & 1 << direction as usize != 0) == mask)
+      .map(|mask| BLOB_INDEXES[mask as usize])
+      .collect();
+    indexes.dedup();
+    assert_eq!(indexes, (0..47).collect::<Vec<u8>>());
+    assert_eq!(AutotileMode::Blob.index(|_| true), 46);
+  }
+
+  #[test]
+  fn texture_across_chunks_test(){
+    let mut tilemap = ChunkedTilemap{
+      chunk_size: UVec2::new(4, 4),
+      ..Default::default()
+    };
+    let mut chunk = ChunkData::filled(tilemap.chunk_size, 1);
+    chunk.set(UVec2::new(0, 1), Some(2));
+    tilemap.generated.insert(IVec2::ZERO, ChunkData::filled(tilemap.chunk_size, 1));
+    tilemap.generated.insert(IVec2::new(1, 0), chunk);
+    let autotile = Autotile::new(AutotileMode::FourBit).with_terrain(1, 100);
+    // east edge of chunk (0, 0), next to the other terrain in chunk (1, 0)
+    let edge = tilemap.local_tile_index_to_global(IVec2::ZERO, IVec2::new(3, 1));
+    assert_eq!(autotile.texture(&tilemap, edge), Some(100 + 1 + 4 + 8));
+    // neighbors in unloaded chunks count as the same terrain
+    assert_eq!(autotile.texture(&tilemap, tilemap.local_tile_index_to_global(IVec2::ZERO, IVec2::new(0, 0))), Some(115));
+    // other terrains are used as they are
+    assert_eq!(autotile.texture(&tilemap, edge + Direction::East.offset()), Some(2));
+    assert_eq!(autotile.texture(&tilemap, IVec2::new(-20, 0)), None);
+  }
+}
@@ -0,0 +1,243 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+  bundle::ChunkAnchor,
+  chunks::local_tile_index_to_global,
+  noise::{Fbm, Noise, NoiseFn},
+  random::{SeededRng, layer_id, tile_seed},
+  variants::TileVariants,
+};
+
+/// Point in climate space, every component is in about `[-1, 1]`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Climate{
+  pub temperature: f64,
+  pub moisture: f64,
+  pub elevation: f64,
+}
+
+impl Climate{
+  pub fn new(temperature: f64, moisture: f64, elevation: f64)->Climate{
+    Climate{temperature, moisture, elevation}
+  }
+
+  pub fn distance(&self, other: &Climate)->f64{
+    let (t, m, e) = (self.temperature - other.temperature, self.moisture - other.moisture, self.elevation - other.elevation);
+    (t*t + m*m + e*e).sqrt()
+  }
+}
+
+/// Index of a biome in `BiomeMap::biomes`.
+pub type BiomeId = usize;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Biome{
+  pub name: String,
+  /// Tiles closest to this climate belong to the biome.
+  pub climate: Climate,
+  pub ground_tiles: TileVariants,
+  /// Chance of a tile getting one of `tree_tiles`.
+  pub decoration_density: f64,
+  pub tree_tiles: TileVariants,
+}
+
+/// Turns global tile indexes into biomes by sampling temperature, moisture and elevation noise.
+///
+/// Tiles whose climate is about as close to several biomes (within `blend`) pick one of them at random,
+/// weighted by closeness, so biome borders get dithered instead of running along straight lines.
+#[derive(Debug, Clone)]
+pub struct BiomeMap{
+  pub biomes: Vec<Biome>,
+  /// Noise samples per tile.
+  pub frequency: f64,
+  /// Width of the border band, in climate space.
+  pub blend: f64,
+  world_seed: u64,
+  temperature: Fbm<Noise>,
+  moisture: Fbm<Noise>,
+  elevation: Fbm<Noise>,
+}
+
+impl BiomeMap{
+  pub fn new(world_seed: u64, biomes: Vec<Biome>)->BiomeMap{
+    let field = |name: &str| Noise::simplex(tile_seed(world_seed, layer_id(name), IVec2::ZERO)).fbm(4);
+    BiomeMap{
+      biomes,
+      frequency: 0.02,
+      blend: 0.1,
+      world_seed,
+      temperature: field("temperature"),
+      moisture: field("moisture"),
+      elevation: field("elevation"),
+    }
+  }
+
+  pub fn with_frequency(mut self, frequency: f64)->BiomeMap{
+    self.frequency = frequency;
+    self
+  }
+
+  pub fn with_blend(mut self, blend: f64)->BiomeMap{
+    self.blend = blend;
+    self
+  }
+
+  pub fn biome(&self, biome_id: BiomeId)->&Biome{
+    &self.biomes[biome_id]
+  }
+
+  pub fn climate(&self, global_tile_index: IVec2)->Climate{
+    let (x, y) = (global_tile_index.x as f64*self.frequency, global_tile_index.y as f64*self.frequency);
+    Climate::new(self.temperature.get(x, y), self.moisture.get(x, y), self.elevation.get(x, y))
+  }
+
+  /// Biomes the climate blends between, with weights in `(0, 1]`. The closest biome always has weight 1.
+  pub fn weights(&self, climate: &Climate)->Vec<(BiomeId, f64)>{
+    let distances: Vec<f64> = self.biomes.iter().map(|biome| biome.climate.distance(climate)).collect();
+    let closest = distances.iter().copied().fold(f64::INFINITY, f64::min);
+    distances.into_iter().enumerate().filter_map(|(biome_id, distance)|{
+      let weight = if distance <= closest {
+        1.
+      } else if self.blend > 0. {
+        1. - (distance - closest)/self.blend
+      } else {
+        0.
+      };
+      (weight > 0.).then_some((biome_id, weight))
+    }).collect()
+  }
+
+  /// Picks the biome of a tile with the given climate, see `BiomeMap::weights`.
+  pub fn select(&self, climate: &Climate, global_tile_index: IVec2)->Option<BiomeId>{
+    let weights = self.weights(climate);
+    if weights.len() < 2 {
+      return weights.first().map(|&(biome_id, _)| biome_id);
+    }
+    let total: f64 = weights.iter().map(|(_, weight)| weight).sum();
+    let mut roll = self.tile_rng("biome", global_tile_index).gen::<f64>()*total;
+    for &(biome_id, weight) in weights.iter(){
+      if roll < weight {
+        return Some(biome_id);
+      }
+      roll -= weight;
+    }
+    weights.last().map(|&(biome_id, _)| biome_id)
+  }
+
+  pub fn biome_at(&self, global_tile_index: IVec2)->Option<BiomeId>{
+    self.select(&self.climate(global_tile_index), global_tile_index)
+  }
+
+  /// Ground tile of the biome at a tile.
+  pub fn ground_tile(&self, global_tile_index: IVec2)->Option<u32>{
+    let biome = self.biome(self.biome_at(global_tile_index)?);
+    biome.ground_tiles.pick(self.world_seed, layer_id("ground"), global_tile_index)
+  }
+
+  /// Tree of the biome at a tile, if the tile gets one.
+  pub fn tree_tile(&self, global_tile_index: IVec2)->Option<u32>{
+    let biome = self.biome(self.biome_at(global_tile_index)?);
+    if self.tile_rng("decoration", global_tile_index).gen::<f64>() >= biome.decoration_density {
+      return None;
+    }
+    biome.tree_tiles.pick(self.world_seed, layer_id("trees"), global_tile_index)
+  }
+
+  /// Biomes of every tile of a chunk, indexed like `ChunkData::tiles`.
+  pub fn sample_chunk(&self, chunk_index: IVec2, chunk_size: UVec2, anchor: ChunkAnchor)->Vec<Option<BiomeId>>{
+    let mut biomes = Vec::with_capacity((chunk_size.x*chunk_size.y) as usize);
+    for y in 0..chunk_size.y as i32{
+      for x in 0..chunk_size.x as i32{
+        biomes.push(self.biome_at(local_tile_index_to_global(chunk_index, chunk_size, IVec2::new(x, y), anchor)));
+      }
+    }
+    biomes
+  }
+
+  fn tile_rng(&self, purpose: &str, global_tile_index: IVec2)->SeededRng{
+    SeededRng::for_tile(self.world_seed, layer_id(purpose), global_tile_index)
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use crate::{bundle::ChunkAnchor, variants::TileVariants};
+  use super::{Biome, BiomeMap, Climate};
+
+  fn biome(name: &str, climate: Climate, ground: u32)->Biome{
+    Biome{
+      name: name.to_string(),
+      climate,
+      ground_tiles: TileVariants::uniform([ground]),
+      decoration_density: 0.5,
+      tree_tiles: TileVariants::uniform([ground*10]),
+    }
+  }
+
+  fn biomes()->BiomeMap{
+    BiomeMap::new(3, vec![
+      biome("desert", Climate::new(1., -1., 0.), 1),
+      biome("forest", Climate::new(0., 1., 0.), 2),
+      biome("tundra", Climate::new(-1., 0., 0.), 3),
+    ]).with_blend(0.2)
+  }
+
+  #[test]
+  fn closest_biome_outside_of_borders_test(){
+    let map = biomes();
+    for x in 0..50{
+      let tile = IVec2::new(x, 0);
+      assert_eq!(map.select(&Climate::new(0.9, -0.8, 0.), tile), Some(0));
+      assert_eq!(map.select(&Climate::new(0.1, 0.9, 0.3), tile), Some(1));
+      assert_eq!(map.select(&Climate::new(-0.9, 0., -0.2), tile), Some(2));
+    }
+  }
+
+  #[test]
+  fn borders_are_dithered_test(){
+    let map = biomes();
+    // halfway between desert and forest
+    let climate = Climate::new(0.5, 0., 0.);
+    assert_eq!(map.weights(&climate).len(), 2);
+    let picks: Vec<_> = (0..100).map(|x| map.select(&climate, IVec2::new(x, 7)).unwrap()).collect();
+    assert!(picks.contains(&0));
+    assert!(picks.contains(&1));
+    assert!(!picks.contains(&2));
+  }
+
+  #[test]
+  fn no_blend_test(){
+    let map = biomes().with_blend(0.);
+    assert_eq!(map.weights(&Climate::new(0.5, 0.01, 0.)), vec![(1, 1.)]);
+  }
+
+  #[test]
+  fn biome_tiles_test(){
+    let map = biomes();
+    let mut trees = 0;
+    for x in -20..20{
+      let tile = IVec2::new(x, x*3);
+      let biome = map.biome(map.biome_at(tile).unwrap());
+      assert_eq!(map.ground_tile(tile), Some(biome.ground_tiles.variants[0].tile));
+      if let Some(tree) = map.tree_tile(tile) {
+        assert_eq!(tree, biome.tree_tiles.variants[0].tile);
+        trees += 1;
+      }
+      assert_eq!(map.biome_at(tile), biomes().biome_at(tile));
+    }
+    assert!(trees > 0 && trees < 40);
+  }
+
+  #[test]
+  fn sample_chunk_test(){
+    let map = biomes();
+    let chunk_size = UVec2::new(3, 2);
+    let biomes = map.sample_chunk(IVec2::new(1, -1), chunk_size, ChunkAnchor::Center);
+    let global = crate::chunks::local_tile_index_to_global(IVec2::new(1, -1), chunk_size, IVec2::new(2, 1), ChunkAnchor::Center);
+    assert_eq!(biomes[5], map.biome_at(global));
+  }
+}
@@ -1,4 +1,8 @@
-use bevy::{prelude::*, utils::HashSet};
+use bevy::{prelude::*, utils::{HashSet, HashMap}};
+
+use crate::chunk_data::ChunkData;
+use crate::teleport::Teleport;
+use crate::chunks::{get_chunk_at_position, get_chunk_center, get_tile_at_position, get_tile_position, global_tile_index_to_local, local_tile_index_to_global, wrap_global_tile_index};
 
 #[derive(Default, Component, Clone, Reflect)]
 #[reflect(Component)]
@@ -9,7 +13,308 @@ pub struct ChunkedTilemap{
   pub center: Vec2,
   pub current_chunk: IVec2,
   pub chunks: HashSet<IVec2>,
-  pub texture_handle: Handle<Image>
+  pub texture_handle: Handle<Image>,
+  pub anchor: ChunkAnchor,
+  #[reflect(ignore)]
+  pub chunk_entities: HashMap<IVec2, Entity>,
+  /// Data the loaded chunks were filled with.
+  #[reflect(ignore)]
+  pub generated: HashMap<IVec2, ChunkData>,
+  /// Chunks outside of the bounds are never spawned (or repeat the ones inside on wrapped axes),
+  /// the world is infinite without them.
+  #[reflect(ignore)]
+  pub bounds: Option<ChunkBounds>,
+  /// Loaded chunks by the chunk they stand for (see `wrap_chunk`), several of them when the range is larger than
+  /// a wrapping world. Kept by `insert_chunk` and `remove_chunk`.
+  #[reflect(ignore)]
+  pub repetitions: HashMap<IVec2, Vec<IVec2>>,
+  /// Chunk sitting where chunk `(0, 0)` would, moved by the floating origin (see `floating_origin`).
+  /// `center` and the chunk transforms are relative to it, chunk and tile indexes are not.
+  pub origin: IVec2,
+  /// Set while streaming catches up with a jump of the center, see `teleport`.
+  #[reflect(ignore)]
+  pub teleport: Option<Teleport>,
+}
+
+impl ChunkedTilemap{
+  pub fn tile_at_position(&self, position: Vec2)->IVec2{
+    get_tile_at_position(position, self.chunk_size, self.tile_size, self.anchor) + self.origin_tile()
+  }
+
+  pub fn tile_position(&self, global_tile_index: IVec2)->Vec2{
+    get_tile_position(global_tile_index - self.origin_tile(), self.chunk_size, self.tile_size, self.anchor)
+  }
+
+  pub fn chunk_at_position(&self, position: Vec2)->IVec2{
+    get_chunk_at_position(position, self.chunk_size, self.tile_size, self.anchor) + self.origin
+  }
+
+  /// Translation of a chunk, see `get_chunk_center`.
+  pub fn chunk_position(&self, chunk_index: IVec2)->Vec2{
+    get_chunk_center(self.chunk_size, self.tile_size, chunk_index - self.origin, self.anchor)
+  }
+
+  /// Offset between global tile indexes and the tiles at the same place without the floating origin.
+  pub fn origin_tile(&self)->IVec2{
+    self.origin*self.chunk_size.as_ivec2()
+  }
+
+  pub fn global_tile_index_to_local(&self, global_tile_index: IVec2)->(IVec2, IVec2){
+    global_tile_index_to_local(global_tile_index, self.chunk_size, self.anchor)
+  }
+
+  pub fn local_tile_index_to_global(&self, chunk_index: IVec2, local_tile_index: IVec2)->IVec2{
+    local_tile_index_to_global(chunk_index, self.chunk_size, local_tile_index, self.anchor)
+  }
+
+  /// Tile the chunk holding a global tile index was filled with, `None` if that chunk has no data (yet).
+  pub fn generated_tile(&self, global_tile_index: IVec2)->Option<Option<u32>>{
+    let (chunk_index, local_tile_index) = self.loaded_tile(global_tile_index)?;
+    let data = self.generated.get(&chunk_index)?;
+    Some(data.get(local_tile_index.as_uvec2()))
+  }
+
+  /// Index a chunk is generated and saved under, the same for every repetition of it in a wrapping world.
+  pub fn wrap_chunk(&self, chunk_index: IVec2)->IVec2{
+    match self.bounds{
+      Some(bounds) => bounds.wrap(chunk_index),
+      None => chunk_index,
+    }
+  }
+
+  /// Global tile index a tile is generated and saved under, see `wrap_chunk`.
+  pub fn wrap_tile(&self, global_tile_index: IVec2)->IVec2{
+    match self.bounds{
+      Some(bounds) => wrap_global_tile_index(global_tile_index, self.chunk_size, self.anchor, &bounds),
+      None => global_tile_index,
+    }
+  }
+
+  /// Every loaded repetition of a chunk index in a wrapping world, or the chunk itself if it is loaded.
+  pub fn loaded_chunks(&self, chunk_index: IVec2)->impl Iterator<Item=IVec2> + '_{
+    let repetitions = self.repetitions.get(&self.wrap_chunk(chunk_index));
+    let loaded = (repetitions.is_none() && self.chunks.contains(&chunk_index)).then_some(chunk_index);
+    loaded.into_iter().chain(repetitions.into_iter().flatten().copied())
+  }
+
+  /// Loaded chunk standing for a chunk index, either that very chunk or another repetition of it (see `loaded_chunks`).
+  pub fn loaded_chunk(&self, chunk_index: IVec2)->Option<IVec2>{
+    if self.chunks.contains(&chunk_index) {
+      return Some(chunk_index);
+    }
+    self.loaded_chunks(chunk_index).next()
+  }
+
+  /// Loaded chunk (see `loaded_chunk`) and local tile index of a global tile.
+  pub fn loaded_tile(&self, global_tile_index: IVec2)->Option<(IVec2, IVec2)>{
+    let (chunk_index, local_tile_index) = self.global_tile_index_to_local(global_tile_index);
+    Some((self.loaded_chunk(chunk_index)?, local_tile_index))
+  }
+
+  /// Every loaded repetition (see `loaded_chunks`) and local tile index of a global tile, edits go to all of them.
+  pub fn loaded_tiles(&self, global_tile_index: IVec2)->impl Iterator<Item=(IVec2, IVec2)> + '_{
+    let (chunk_index, local_tile_index) = self.global_tile_index_to_local(global_tile_index);
+    self.loaded_chunks(chunk_index).map(move |chunk_index| (chunk_index, local_tile_index))
+  }
+
+  pub fn insert_chunk(&mut self, chunk_index: IVec2, chunk_entity: Entity){
+    self.chunks.insert(chunk_index);
+    self.chunk_entities.insert(chunk_index, chunk_entity);
+    let wrapped = self.wrap_chunk(chunk_index);
+    self.repetitions.entry(wrapped).or_default().push(chunk_index);
+  }
+
+  /// Forgets a loaded chunk and its data, returns its entity.
+  pub fn remove_chunk(&mut self, chunk_index: IVec2)->Option<Entity>{
+    self.chunks.remove(&chunk_index);
+    self.generated.remove(&chunk_index);
+    let wrapped = self.wrap_chunk(chunk_index);
+    if let Some(repetitions) = self.repetitions.get_mut(&wrapped) {
+      repetitions.retain(|&loaded| loaded != chunk_index);
+      if repetitions.is_empty() {
+        self.repetitions.remove(&wrapped);
+      }
+    }
+    self.chunk_entities.remove(&chunk_index)
+  }
+
+  /// Whether a chunk is around the current chunk and inside of the bounds, i.e. stays loaded.
+  pub fn in_range(&self, chunk_index: IVec2)->bool{
+    let range = (chunk_index - self.current_chunk).abs();
+    range.x <= self.range && range.y <= self.range && self.contains_chunk(chunk_index)
+  }
+
+  pub fn contains_chunk(&self, chunk_index: IVec2)->bool{
+    match self.bounds{
+      Some(bounds) => bounds.contains(chunk_index),
+      None => true,
+    }
+  }
+
+  pub fn contains_tile(&self, global_tile_index: IVec2)->bool{
+    self.contains_chunk(self.global_tile_index_to_local(global_tile_index).0)
+  }
+
+  /// Nearest tile inside of the bounds, wrapped axes are left as they are.
+  pub fn clamp_tile(&self, global_tile_index: IVec2)->IVec2{
+    match (self.tile_bounds(), self.bounds){
+      (Some((min, max)), Some(bounds)) => IVec2::new(
+        if bounds.wrap.x { global_tile_index.x } else { global_tile_index.x.clamp(min.x, max.x) },
+        if bounds.wrap.y { global_tile_index.y } else { global_tile_index.y.clamp(min.y, max.y) },
+      ),
+      _ => global_tile_index,
+    }
+  }
+
+  /// North-west and south-east tiles of the bounds.
+  pub fn tile_bounds(&self)->Option<(IVec2, IVec2)>{
+    let bounds = self.bounds?;
+    let size = self.chunk_size.as_ivec2();
+    Some((
+      self.local_tile_index_to_global(bounds.min, IVec2::new(0, size.y - 1)),
+      self.local_tile_index_to_global(bounds.max, IVec2::new(size.x - 1, 0)),
+    ))
+  }
+
+  /// Smallest and largest corner of the bounds, in tilemap space.
+  pub fn world_bounds(&self)->Option<(Vec2, Vec2)>{
+    let (north_west, south_east) = self.tile_bounds()?;
+    let (a, b) = (self.tile_position(north_west), self.tile_position(south_east));
+    Some((a.min(b) - self.tile_size/2., a.max(b) + self.tile_size/2.))
+  }
+
+  /// Keeps a position (of a loader, or a camera whose view reaches `half_extents` around it) inside of the bounds.
+  /// A view larger than the bounds gets centered on them, wrapped axes are left as they are.
+  pub fn clamp_position(&self, position: Vec2, half_extents: Vec2)->Vec2{
+    let (min, max, wrap) = match (self.world_bounds(), self.bounds){
+      (Some((min, max)), Some(bounds)) => (min, max, bounds.wrap),
+      _ => return position,
+    };
+    let clamp = |position: f32, min: f32, max: f32, half_extent: f32, wrap: bool|{
+      if wrap {
+        position
+      } else if max - min <= 2.*half_extent {
+        (min + max)/2.
+      } else {
+        position.clamp(min + half_extent, max - half_extent)
+      }
+    };
+    Vec2::new(
+      clamp(position.x, min.x, max.x, half_extents.x, wrap.x),
+      clamp(position.y, min.y, max.y, half_extents.y, wrap.y),
+    )
+  }
+
+  /// Despawns every loaded chunk, streaming spawns (and generates) them again.
+  pub fn despawn_chunks(&mut self, commands: &mut Commands){
+    for (_, chunk) in self.chunk_entities.drain(){
+      commands.entity(chunk).despawn_recursive();
+    }
+    self.chunks.clear();
+    self.generated.clear();
+    self.repetitions.clear();
+  }
+}
+
+/// Where chunk `(0, 0)` sits relative to the tilemap origin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect_value(PartialEq)]
+pub enum ChunkAnchor{
+  /// Chunk `(0, 0)` is centered on the origin.
+  Center,
+  /// Top-left corner of chunk `(0, 0)` is at the origin, chunk indexes grow right and down.
+  Corner,
+}
+
+impl Default for ChunkAnchor{
+  fn default()->ChunkAnchor{
+    ChunkAnchor::Center
+  }
+}
+
+/// What streaming does when the center leaves the bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutsideBounds{
+  /// The current chunk follows the center, only the chunks in range that are inside the bounds get spawned.
+  #[default]
+  Empty,
+  /// The current chunk stays on the nearest chunk inside the bounds.
+  Clamp,
+}
+
+/// Chunk indexes of a finite world, `min` and `max` included.
+///
+/// On wrapped axes the world repeats instead: chunks past an edge are generated and saved as the ones on the
+/// other side (see `wrap`), but rendered where they are, next to the center.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkBounds{
+  pub min: IVec2,
+  pub max: IVec2,
+  pub outside: OutsideBounds,
+  pub wrap: BVec2,
+}
+
+impl ChunkBounds{
+  pub fn new(min: IVec2, max: IVec2)->ChunkBounds{
+    ChunkBounds{
+      min: min.min(max),
+      max: min.max(max),
+      outside: OutsideBounds::Empty,
+      wrap: BVec2::new(false, false),
+    }
+  }
+
+  pub fn with_wrap(mut self, x: bool, y: bool)->ChunkBounds{
+    self.wrap = BVec2::new(x, y);
+    self
+  }
+
+  /// Size in chunks.
+  pub fn size(&self)->IVec2{
+    self.max - self.min + IVec2::ONE
+  }
+
+  pub fn with_outside(mut self, outside: OutsideBounds)->ChunkBounds{
+    self.outside = outside;
+    self
+  }
+
+  /// Wrapped axes contain every chunk index.
+  pub fn contains(&self, chunk_index: IVec2)->bool{
+    let wrapped = self.wrap(chunk_index);
+    wrapped.cmpge(self.min).all() && wrapped.cmple(self.max).all()
+  }
+
+  /// Nearest chunk inside of the bounds, wrapped axes are left as they are.
+  pub fn clamp(&self, chunk_index: IVec2)->IVec2{
+    IVec2::new(
+      if self.wrap.x { chunk_index.x } else { chunk_index.x.clamp(self.min.x, self.max.x) },
+      if self.wrap.y { chunk_index.y } else { chunk_index.y.clamp(self.min.y, self.max.y) },
+    )
+  }
+
+  /// Chunk inside of the bounds a chunk index stands for on wrapped axes.
+  pub fn wrap(&self, chunk_index: IVec2)->IVec2{
+    let size = self.size();
+    IVec2::new(
+      if self.wrap.x { self.min.x + (chunk_index.x - self.min.x).rem_euclid(size.x) } else { chunk_index.x },
+      if self.wrap.y { self.min.y + (chunk_index.y - self.min.y).rem_euclid(size.y) } else { chunk_index.y },
+    )
+  }
+
+  /// Shortest offset from one chunk index to another, going around wrapped axes.
+  pub fn offset(&self, from: IVec2, to: IVec2)->IVec2{
+    let size = self.size();
+    let shortest = |offset: i32, size: i32, wrap: bool|{
+      if !wrap {
+        return offset;
+      }
+      let offset = offset.rem_euclid(size);
+      if offset > size/2 { offset - size } else { offset }
+    };
+    let offset = to - from;
+    IVec2::new(shortest(offset.x, size.x, self.wrap.x), shortest(offset.y, size.y, self.wrap.y))
+  }
 }
 
 #[derive(Default, Component)]
@@ -21,4 +326,122 @@ pub struct ChunkedTilemapBundle{
   pub name: Name,
   #[bundle]
   pub spatial: SpatialBundle
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use rstest::rstest;
+  use super::{ChunkAnchor, ChunkBounds, ChunkedTilemap};
+
+  fn tilemap(bounds: Option<ChunkBounds>)->ChunkedTilemap{
+    ChunkedTilemap{
+      chunk_size: UVec2::new(4, 4),
+      tile_size: Vec2::new(10., 10.),
+      anchor: ChunkAnchor::Corner,
+      bounds,
+      ..Default::default()
+    }
+  }
+
+  #[rstest]
+  #[case(IVec2::new(0, 0), true)]
+  #[case(IVec2::new(1, 1), true)]
+  #[case(IVec2::new(2, 1), false)]
+  #[case(IVec2::new(0, -1), false)]
+  fn contains_chunk_test(
+    #[case] chunk_index: IVec2,
+    #[case] expected: bool,
+  ){
+    assert_eq!(tilemap(Some(ChunkBounds::new(IVec2::ONE, IVec2::ZERO))).contains_chunk(chunk_index), expected);
+    assert!(tilemap(None).contains_chunk(chunk_index));
+  }
+
+  #[test]
+  fn tile_bounds_test(){
+    let tilemap = tilemap(Some(ChunkBounds::new(IVec2::ZERO, IVec2::ONE)));
+    assert_eq!(tilemap.tile_bounds(), Some((IVec2::new(0, 0), IVec2::new(7, 7))));
+    assert_eq!(tilemap.clamp_tile(IVec2::new(-5, 20)), IVec2::new(0, 7));
+    assert!(tilemap.contains_tile(IVec2::new(7, 0)));
+    assert!(!tilemap.contains_tile(IVec2::new(8, 0)));
+    assert_eq!(tilemap.world_bounds(), Some((Vec2::new(0., -80.), Vec2::new(80., 0.))));
+  }
+
+  #[rstest]
+  #[case(Vec2::new(100., 10.), Vec2::new(20., 20.), Vec2::new(60., -20.))]
+  #[case(Vec2::new(30., -30.), Vec2::new(20., 20.), Vec2::new(30., -30.))]
+  #[case(Vec2::new(0., -100.), Vec2::new(50., 10.), Vec2::new(40., -70.))]
+  fn clamp_position_test(
+    #[case] position: Vec2,
+    #[case] half_extents: Vec2,
+    #[case] expected: Vec2,
+  ){
+    assert_eq!(tilemap(Some(ChunkBounds::new(IVec2::ZERO, IVec2::ONE))).clamp_position(position, half_extents), expected);
+    assert_eq!(tilemap(None).clamp_position(position, half_extents), position);
+  }
+
+  #[rstest]
+  #[case(IVec2::ZERO)]
+  #[case(IVec2::new(10_000_000, -10_000_000))]
+  fn origin_test(
+    #[case] origin: IVec2,
+  ){
+    let mut tilemap = tilemap(None);
+    tilemap.origin = origin;
+    let chunk_index = origin + IVec2::new(2, -1);
+    let position = tilemap.chunk_position(chunk_index);
+    // placed as if the origin chunk was chunk (0, 0)
+    assert_eq!(position, super::get_chunk_center(tilemap.chunk_size, tilemap.tile_size, IVec2::new(2, -1), tilemap.anchor));
+    assert_eq!(tilemap.chunk_at_position(position), chunk_index);
+    for local_tile_index in [IVec2::ZERO, IVec2::new(3, 1)]{
+      let global_tile_index = tilemap.local_tile_index_to_global(chunk_index, local_tile_index);
+      let position = tilemap.tile_position(global_tile_index);
+      assert!(position.abs().max_element() < 200., "{position}");
+      assert_eq!(tilemap.tile_at_position(position), global_tile_index);
+      assert_eq!(tilemap.tile_at_position(position + Vec2::new(4.9, -4.9)), global_tile_index);
+    }
+  }
+
+  #[test]
+  fn wrap_test(){
+    let bounds = ChunkBounds::new(IVec2::ZERO, IVec2::new(3, 1)).with_wrap(true, false);
+    assert_eq!(bounds.wrap(IVec2::new(5, 0)), IVec2::new(1, 0));
+    assert_eq!(bounds.wrap(IVec2::new(-1, 1)), IVec2::new(3, 1));
+    assert_eq!(bounds.wrap(IVec2::new(0, 5)), IVec2::new(0, 5));
+    assert_eq!(bounds.offset(IVec2::ZERO, IVec2::new(3, 0)), IVec2::new(-1, 0));
+    assert_eq!(bounds.offset(IVec2::ZERO, IVec2::new(0, 3)), IVec2::new(0, 3));
+    assert!(bounds.contains(IVec2::new(10, 1)));
+    assert!(!bounds.contains(IVec2::new(0, 2)));
+    assert_eq!(bounds.clamp(IVec2::new(10, 5)), IVec2::new(10, 1));
+
+    let mut tilemap = tilemap(Some(bounds));
+    tilemap.insert_chunk(IVec2::new(4, 0), Entity::from_raw(1));
+    assert_eq!(tilemap.loaded_chunk(IVec2::ZERO), Some(IVec2::new(4, 0)));
+    assert_eq!(tilemap.loaded_chunk(IVec2::X), None);
+    let global_tile_index = tilemap.local_tile_index_to_global(IVec2::new(4, 0), IVec2::new(1, 3));
+    assert_eq!(tilemap.wrap_tile(global_tile_index), tilemap.local_tile_index_to_global(IVec2::ZERO, IVec2::new(1, 3)));
+    assert_eq!(tilemap.loaded_tile(tilemap.wrap_tile(global_tile_index)), Some((IVec2::new(4, 0), IVec2::new(1, 3))));
+  }
+
+  #[test]
+  fn repetitions_test(){
+    // a range of 3 shows 7 chunks of a world 4 chunks wide
+    let bounds = ChunkBounds::new(IVec2::ZERO, IVec2::new(3, 1)).with_wrap(true, false);
+    let mut tilemap = tilemap(Some(bounds));
+    for x in -3..=3{
+      tilemap.insert_chunk(IVec2::new(x, 0), Entity::from_raw((x + 3) as u32));
+    }
+    let mut loaded: Vec<IVec2> = tilemap.loaded_chunks(IVec2::new(5, 0)).collect();
+    loaded.sort_by_key(|chunk_index| chunk_index.x);
+    assert_eq!(loaded, vec![IVec2::new(-3, 0), IVec2::new(1, 0)]);
+    assert_eq!(tilemap.loaded_chunk(IVec2::new(1, 0)), Some(IVec2::new(1, 0)));
+    let global_tile_index = tilemap.local_tile_index_to_global(IVec2::new(-1, 0), IVec2::new(2, 2));
+    assert_eq!(tilemap.loaded_tiles(global_tile_index).count(), 2);
+
+    assert_eq!(tilemap.remove_chunk(IVec2::new(-3, 0)), Some(Entity::from_raw(0)));
+    assert_eq!(tilemap.loaded_chunks(IVec2::new(5, 0)).collect::<Vec<_>>(), vec![IVec2::new(1, 0)]);
+    assert_eq!(tilemap.remove_chunk(IVec2::new(1, 0)), Some(Entity::from_raw(4)));
+    assert_eq!(tilemap.loaded_chunk(IVec2::new(5, 0)), None);
+    assert!(!tilemap.repetitions.contains_key(&IVec2::new(1, 0)));
+  }
+}
@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{generation::{ChunkGenerator, GenerationContext}, random::SeededRng};
+
+/// Caves from cellular automata smoothing of random walls, as a pass of a `GenerationPipeline`.
+///
+/// Every smoothing step looks at the 8 neighbors of a tile, so a chunk is smoothed together with an apron of
+/// `iterations` tiles around it, whose initial walls come from per-tile seeds. Border tiles come out the same
+/// no matter which of the chunks sharing them gets generated first.
+#[derive(Debug, Clone)]
+pub struct CaveGenerator{
+  pub wall: Option<u32>,
+  pub floor: Option<u32>,
+  /// Chance of a tile starting as a wall.
+  pub fill: f64,
+  pub iterations: u32,
+  /// Floor turns into a wall with at least this many neighboring walls.
+  pub birth: u32,
+  /// Wall stays a wall with at least this many neighboring walls.
+  pub survival: u32,
+}
+
+impl CaveGenerator{
+  pub fn new(wall: Option<u32>, floor: Option<u32>)->CaveGenerator{
+    CaveGenerator{
+      wall,
+      floor,
+      fill: 0.45,
+      iterations: 4,
+      birth: 5,
+      survival: 4,
+    }
+  }
+
+  pub fn with_fill(mut self, fill: f64)->CaveGenerator{
+    self.fill = fill;
+    self
+  }
+
+  pub fn with_iterations(mut self, iterations: u32)->CaveGenerator{
+    self.iterations = iterations;
+    self
+  }
+
+  pub fn with_rules(mut self, birth: u32, survival: u32)->CaveGenerator{
+    self.birth = birth;
+    self.survival = survival;
+    self
+  }
+
+  /// Walls of a rectangle of global tiles (rows from north to south) after smoothing,
+  /// `tile_rng` gives the random numbers of a tile, like `GenerationContext::tile_rng`.
+  pub fn walls(&self, tile_rng: impl Fn(IVec2)->SeededRng, min: IVec2, size: IVec2)->Vec<bool>{
+    let apron = self.iterations as i32;
+    let mut area_min = min - IVec2::splat(apron);
+    let mut area_size = size + IVec2::splat(2*apron);
+    let mut walls = vec![];
+    for y in 0..area_size.y{
+      for x in 0..area_size.x{
+        walls.push(tile_rng(area_min + IVec2::new(x, y)).gen_bool(self.fill.clamp(0., 1.)));
+      }
+    }
+    // every step loses a tile on each side, as their neighbors are not known
+    for _ in 0..self.iterations{
+      let mut smoothed = Vec::with_capacity(((area_size.x - 2)*(area_size.y - 2)).max(0) as usize);
+      for y in 1..area_size.y - 1{
+        for x in 1..area_size.x - 1{
+          let mut neighbors = 0;
+          for dy in -1..=1{
+            for dx in -1..=1{
+              if (dx != 0 || dy != 0) && walls[((y + dy)*area_size.x + x + dx) as usize] {
+                neighbors += 1;
+              }
+            }
+          }
+          let wall = walls[(y*area_size.x + x) as usize];
+          smoothed.push(neighbors >= if wall { self.survival } else { self.birth });
+        }
+      }
+      walls = smoothed;
+      area_min += IVec2::ONE;
+      area_size -= IVec2::splat(2);
+    }
+    walls
+  }
+}
+
+impl ChunkGenerator for CaveGenerator{
+  fn generate(&self, context: &mut GenerationContext){
+    let size = context.chunk_size.as_ivec2();
+    let min = context.local_to_global(UVec2::new(0, context.chunk_size.y - 1));
+    let walls = self.walls(|global_tile_index| context.tile_rng(global_tile_index), min, size);
+    for (i, wall) in walls.into_iter().enumerate(){
+      let global_tile_index = min + IVec2::new(i as i32 % size.x, i as i32 / size.x);
+      context.set_global(global_tile_index, if wall { self.wall } else { self.floor });
+    }
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use rstest::rstest;
+  use crate::{bundle::ChunkAnchor, chunks::local_tile_index_to_global, generation::GenerationPipeline, random::{SeededRng, layer_id}, spawn_around::generate_chunk_indexes};
+  use super::CaveGenerator;
+
+  const WALL: u32 = 1;
+  const FLOOR: u32 = 0;
+  const CHUNK_SIZE: UVec2 = UVec2{x: 8, y: 6};
+
+  fn pipeline(caves: CaveGenerator)->GenerationPipeline{
+    GenerationPipeline::new()
+      .with_seed(5, layer_id("caves"))
+      .with_pass(caves)
+  }
+
+  /// Tiles of a chunk generated from an empty cache, by global tile index.
+  fn chunk_tiles(caves: &CaveGenerator, chunk_index: IVec2)->Vec<(IVec2, u32)>{
+    let data = pipeline(caves.clone()).generate(chunk_index, CHUNK_SIZE, ChunkAnchor::Corner);
+    data.iter().map(|(local_tile_index, tile)|{
+      (local_tile_index_to_global(chunk_index, CHUNK_SIZE, local_tile_index.as_ivec2(), ChunkAnchor::Corner), tile)
+    }).collect()
+  }
+
+  #[rstest]
+  #[case(0)]
+  #[case(1)]
+  #[case(5)]
+  fn chunks_match_one_big_area_test(
+    #[case] iterations: u32,
+  ){
+    let caves = CaveGenerator::new(Some(WALL), Some(FLOOR)).with_iterations(iterations);
+    // smooths a single area of 5x5 chunks at once, with the seeds of the first pass
+    let min = local_tile_index_to_global(IVec2::new(-2, -2), CHUNK_SIZE, IVec2::new(0, CHUNK_SIZE.y as i32 - 1), ChunkAnchor::Corner);
+    let size = CHUNK_SIZE.as_ivec2()*5;
+    let whole = caves.walls(|global_tile_index| SeededRng::for_tile(5, layer_id("caves"), global_tile_index), min, size);
+    for chunk_index in generate_chunk_indexes(IVec2::ZERO, 2){
+      let tiles = chunk_tiles(&caves, chunk_index);
+      assert_eq!(tiles.len(), 48);
+      for (global_tile_index, tile) in tiles{
+        let position = global_tile_index - min;
+        let wall = whole[(position.y*size.x + position.x) as usize];
+        assert_eq!(tile, if wall { WALL } else { FLOOR }, "{global_tile_index}");
+      }
+    }
+  }
+
+  #[test]
+  fn smoothing_test(){
+    let count_walls = |caves: CaveGenerator|{
+      generate_chunk_indexes(IVec2::ZERO, 2).into_iter()
+        .flat_map(|chunk_index| chunk_tiles(&caves, chunk_index))
+        .filter(|&(_, tile)| tile == WALL)
+        .count()
+    };
+    let total = 25*48;
+    let noise = count_walls(CaveGenerator::new(Some(WALL), Some(FLOOR)).with_iterations(0)) as f64/total as f64;
+    assert!((noise - 0.45).abs() < 0.05, "{noise}");
+    let caves = count_walls(CaveGenerator::new(Some(WALL), Some(FLOOR))) as f64/total as f64;
+    assert!(caves > 0.1 && caves < 0.6, "{caves}");
+    assert_eq!(count_walls(CaveGenerator::new(Some(WALL), Some(FLOOR)).with_fill(1.)), total);
+  }
+
+  #[test]
+  fn empty_tiles_test(){
+    let caves = CaveGenerator::new(Some(WALL), None);
+    let data = pipeline(caves).generate(IVec2::new(3, -4), CHUNK_SIZE, ChunkAnchor::Corner);
+    assert!(data.iter().all(|(_, tile)| tile == WALL));
+    assert!(data.iter().count() < 48);
+  }
+}
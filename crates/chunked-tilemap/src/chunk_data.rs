@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::tiles::{TileBundle, TilePos, TileTexture};
+
+/// Tile textures of a single chunk before (or instead of) being spawned, indexed by local tile position.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ChunkData{
+  pub size: UVec2,
+  pub tiles: Vec<Option<u32>>,
+}
+
+impl ChunkData{
+  pub fn new(size: UVec2)->ChunkData{
+    ChunkData{
+      size,
+      tiles: vec![None; (size.x*size.y) as usize],
+    }
+  }
+
+  pub fn filled(size: UVec2, texture: u32)->ChunkData{
+    ChunkData{
+      size,
+      tiles: vec![Some(texture); (size.x*size.y) as usize],
+    }
+  }
+
+  pub fn from_bundles(size: UVec2, bundles: &[TileBundle])->ChunkData{
+    let mut data = ChunkData::new(size);
+    for bundle in bundles.iter(){
+      data.set(UVec2::new(bundle.position.x, bundle.position.y), Some(bundle.texture.0));
+    }
+    data
+  }
+
+  fn index(&self, local_tile_index: UVec2)->Option<usize>{
+    if local_tile_index.x < self.size.x && local_tile_index.y < self.size.y {
+      Some((local_tile_index.y*self.size.x + local_tile_index.x) as usize)
+    } else {
+      None
+    }
+  }
+
+  pub fn contains(&self, local_tile_index: IVec2)->bool{
+    local_tile_index.cmpge(IVec2::ZERO).all() && local_tile_index.cmplt(self.size.as_ivec2()).all()
+  }
+
+  pub fn get(&self, local_tile_index: UVec2)->Option<u32>{
+    self.index(local_tile_index).and_then(|index| self.tiles[index])
+  }
+
+  /// Sets a tile, returns `false` if the position is outside of the chunk.
+  pub fn set(&mut self, local_tile_index: UVec2, texture: Option<u32>)->bool{
+    match self.index(local_tile_index){
+      Some(index) => {
+        self.tiles[index] = texture;
+        true
+      }
+      None => false
+    }
+  }
+
+  /// Existing tiles as `(local tile index, texture)`.
+  pub fn iter(&self)->impl Iterator<Item=(UVec2, u32)> + '_{
+    let width = self.size.x.max(1);
+    self.tiles.iter().enumerate().filter_map(move |(index, texture)|{
+      texture.map(|texture| (UVec2::new(index as u32 % width, index as u32 / width), texture))
+    })
+  }
+
+  pub fn to_bundles(&self)->Vec<TileBundle>{
+    self.iter().map(|(position, texture)| TileBundle{
+      position: TilePos{x: position.x, y: position.y},
+      texture: TileTexture(texture),
+      ..Default::default()
+    }).collect()
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use super::ChunkData;
+
+  #[test]
+  fn set_and_get_test(){
+    let mut data = ChunkData::new(UVec2::new(3, 2));
+    assert!(data.set(UVec2::new(2, 1), Some(7)));
+    assert!(!data.set(UVec2::new(3, 0), Some(7)));
+    assert_eq!(data.get(UVec2::new(2, 1)), Some(7));
+    assert_eq!(data.get(UVec2::new(0, 0)), None);
+    assert_eq!(data.iter().collect::<Vec<_>>(), vec![(UVec2::new(2, 1), 7)]);
+  }
+
+  #[test]
+  fn bundles_roundtrip_test(){
+    let mut data = ChunkData::new(UVec2::new(4, 4));
+    data.set(UVec2::new(0, 3), Some(1));
+    data.set(UVec2::new(3, 0), Some(2));
+    assert_eq!(ChunkData::from_bundles(data.size, &data.to_bundles()), data);
+  }
+}
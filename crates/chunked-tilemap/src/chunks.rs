@@ -1,18 +1,21 @@
 use bevy::{prelude::*};
-use bevy_ecs_tilemap::{prelude::TilemapId};
+use bevy_ecs_tilemap::{prelude::TilemapId, tiles::{TilePos, TileStorage}};
 
-use crate::{spawn_chunk::{PrepareChunkEvent}, TilemapChunk, bundle::ChunkedTilemap};
+use crate::{spawn_chunk::{PrepareChunkEvent}, TilemapChunk, bundle::{ChunkedTilemap, ChunkAnchor, ChunkBounds, OutsideBounds}, layers::ChunkedTilemapLayer, teleport::{Teleport, TeleportSettings}};
 
 pub fn update_current_chunk(
-  mut q_tilemaps: Query<&mut ChunkedTilemap>,
+  teleport_settings: Res<TeleportSettings>,
+  mut q_tilemaps: Query<&mut ChunkedTilemap, Without<ChunkedTilemapLayer>>,
 ){
   for mut tilemap in q_tilemaps.iter_mut(){
-    let actually_current_chunk = get_chunk_at_position(
-      tilemap.center,
-      tilemap.chunk_size,
-      tilemap.tile_size,
-    );
+    let mut actually_current_chunk = tilemap.chunk_at_position(tilemap.center);
+    if let Some(bounds) = tilemap.bounds.filter(|bounds| bounds.outside == OutsideBounds::Clamp) {
+      actually_current_chunk = bounds.clamp(actually_current_chunk);
+    }
     if tilemap.current_chunk != actually_current_chunk{
+      if teleport_settings.is_teleport(tilemap.current_chunk, actually_current_chunk, tilemap.range) {
+        tilemap.teleport = Some(Teleport::new(tilemap.current_chunk));
+      }
       tilemap.current_chunk = actually_current_chunk;
       info!("current chunk changed {}", tilemap.current_chunk);
     }
@@ -32,107 +35,145 @@ pub fn nest_chunks(
   }
 }
 
-pub fn get_chunk_at_position(position: Vec2, chunk_size: UVec2, tile_size: Vec2,)->IVec2{
-  return IVec2::new(
-    (position.x/(tile_size.x*chunk_size.x as f32)).round() as i32,
-    (-position.y/(tile_size.y*chunk_size.y as f32)).round() as i32,
-  )
+pub fn register_tiles(
+  added: Query<(Entity, &TilePos, &TilemapId), Added<TilemapId>>,
+  mut q_storages: Query<&mut TileStorage>,
+){
+  for (entity, position, tilemap) in added.iter(){
+    if let Ok(mut storage) = q_storages.get_mut(tilemap.0){
+      if position.x < storage.size.x && position.y < storage.size.y{
+        storage.set(position, Some(entity));
+      }
+    }
+  }
 }
 
+pub fn get_chunk_at_position(position: Vec2, chunk_size: UVec2, tile_size: Vec2, anchor: ChunkAnchor)->IVec2{
+  let relative = Vec2::new(position.x, -position.y)/(tile_size*chunk_size.as_vec2());
+  match anchor{
+    ChunkAnchor::Center => relative.round().as_ivec2(),
+    ChunkAnchor::Corner => relative.floor().as_ivec2(),
+  }
+}
 
+/// Translation of the chunk's `TilemapBundle`, i.e. the center of its bottom-left tile.
 pub fn get_chunk_center(
   chunk_size: UVec2,
   tile_size: Vec2,
   relative_position: IVec2,
+  anchor: ChunkAnchor,
 )->Vec2{
-  Vec2::new(
-    -tile_size.x*((chunk_size.x-1)/2) as f32 + ((relative_position.x  as f32)*tile_size.x*chunk_size.x as f32),
-    -tile_size.y*((chunk_size.y-1)/2) as f32 + ((-relative_position.y as f32)*tile_size.y*chunk_size.y as f32)
-  )
+  let chunk_world_size = tile_size*chunk_size.as_vec2();
+  let origin = match anchor{
+    ChunkAnchor::Center => (tile_size-chunk_world_size)/2.,
+    ChunkAnchor::Corner => Vec2::new(tile_size.x/2., tile_size.y/2.-chunk_world_size.y),
+  };
+  origin + Vec2::new(relative_position.x as f32, -relative_position.y as f32)*chunk_world_size
+}
+
+/// Global index of the local tile `(0, 0)` of chunk `(0, 0)`.
+fn get_tile_offset(chunk_size: UVec2, anchor: ChunkAnchor)->IVec2{
+  match anchor{
+    ChunkAnchor::Center => IVec2::new(-(chunk_size.x as i32/2), chunk_size.y as i32/2),
+    ChunkAnchor::Corner => IVec2::new(0, chunk_size.y as i32-1),
+  }
 }
 
 pub fn local_tile_index_to_global(
   chunk_index: IVec2,
   chunk_size: UVec2,
-  local_tile_index: IVec2 //  relative to chunk
+  local_tile_index: IVec2, //  relative to chunk
+  anchor: ChunkAnchor,
 )->IVec2{
+  let offset = get_tile_offset(chunk_size, anchor);
   IVec2 {
-    x: local_tile_index.x-((chunk_size.x) as f32/2.).floor() as i32 + chunk_index.x*(chunk_size.x as i32),
-    y: -local_tile_index.y+((chunk_size.y) as f32/2.).floor() as i32  + chunk_index.y*(chunk_size.y as i32)
+    x: local_tile_index.x + offset.x + chunk_index.x*(chunk_size.x as i32),
+    y: -local_tile_index.y + offset.y + chunk_index.y*(chunk_size.y as i32)
   }
 }
 
+/// Splits a global tile index into the index of the chunk holding it and the local tile index inside that chunk.
+pub fn global_tile_index_to_local(
+  global_tile_index: IVec2,
+  chunk_size: UVec2,
+  anchor: ChunkAnchor,
+)->(IVec2, IVec2){
+  let size = chunk_size.as_ivec2();
+  let offset = get_tile_offset(chunk_size, anchor);
+  let x = global_tile_index.x - offset.x;
+  let y = offset.y - global_tile_index.y;
+  (
+    IVec2::new(x.div_euclid(size.x), -y.div_euclid(size.y)),
+    IVec2::new(x.rem_euclid(size.x), y.rem_euclid(size.y)),
+  )
+}
+
+/// Global tile index a tile stands for in a world wrapping around `bounds`.
+pub fn wrap_global_tile_index(global_tile_index: IVec2, chunk_size: UVec2, anchor: ChunkAnchor, bounds: &ChunkBounds)->IVec2{
+  let (chunk_index, local_tile_index) = global_tile_index_to_local(global_tile_index, chunk_size, anchor);
+  local_tile_index_to_global(bounds.wrap(chunk_index), chunk_size, local_tile_index, anchor)
+}
+
+/// Global index of the tile covering `position` (in tilemap space).
+pub fn get_tile_at_position(position: Vec2, chunk_size: UVec2, tile_size: Vec2, anchor: ChunkAnchor)->IVec2{
+  let origin = get_chunk_center(chunk_size, tile_size, IVec2::ZERO, anchor);
+  let local_tile_index = ((position-origin)/tile_size + 0.5).floor().as_ivec2();
+  local_tile_index_to_global(IVec2::ZERO, chunk_size, local_tile_index, anchor)
+}
+
+/// Center of the tile with the given global index (in tilemap space).
+pub fn get_tile_position(global_tile_index: IVec2, chunk_size: UVec2, tile_size: Vec2, anchor: ChunkAnchor)->Vec2{
+  let (chunk_index, local_tile_index) = global_tile_index_to_local(global_tile_index, chunk_size, anchor);
+  get_chunk_center(chunk_size, tile_size, chunk_index, anchor) + local_tile_index.as_vec2()*tile_size
+}
+
 #[cfg(test)]
 mod test{
   use bevy::prelude::*;
   use rstest::rstest;
-
-  // #[rstest]
-  // #[case(1, (0, 0), (0., 0.))]
-  // #[case(2, (0, 0), (-0., -0.))]
-  // #[case(3, (0, 0), (-32., -32.))]
-  // #[case(4, (0, 0), (-32., -32.))]
-  // #[case(5, (0, 0), (-64., -64.))]
-  // #[case(1, (-1, 0), (-32., 0.))]
-  // // #[case(1, (1, 1), (32., 32.))]
-  // // #[case(1, (0, 1), (0., 32.))]
-
-  // // #[case(3, (0, 1), (-32., -32.))]
-  // fn get_chunk_center_test(
-  //   #[case] chunk_size: i32,
-  //   #[case] relative_position: (i32, i32),
-  //   #[case] expected: (f32, f32),
-  // ){
-  //   assert_eq!(get_chunk_center(
-  //     chunk_size,
-  //     IVec2::from(relative_position),
-  //     32
-  //   ), Vec2::from(expected));
-  // }
+  use crate::bundle::ChunkAnchor;
 
   #[rstest]
-  #[case((0., 0.), (10., 10.), (5., 0.), true)]
-  #[case((0., 0.), (10., 10.), (-6., 5.), false)]
-  #[case((0., 0.), (10., 10.), (-5., 0.), true)]
-  #[case((0., 0.), (10., 10.), (7., 7.), false)]
-  #[case((0., 0.), (10., 10.), (5., 0.), true)]
-  #[case((0., 0.), (10., 10.), (50., 5.), false)]
-  #[case((0., 0.), (10., 10.), (-50., 5.), false)]
-
-  #[case((0., 0.), (10., 10.), (-50., 5.), false)]
-  #[case((0., 0.), (10., 10.), (5., 50.), false)]
-
-  #[case((-1., -1.), (10., 10.), (3., 3.), true)]
-  #[case((-1., -1.), (10., 10.), (5., 0.), false)]
-  #[case((-1., -1.), (10., 10.), (-50., 1.), false)]
-  fn is_point_in_rect_test(
-    #[case] rect_pos: (f32, f32),
-    #[case] rect_size: (f32, f32),
-    #[case] point: (f32, f32),
-    #[case] expected: bool,
+  #[case(1, (0, 0), ChunkAnchor::Center, (0., 0.))]
+  #[case(2, (0, 0), ChunkAnchor::Center, (-16., -16.))]
+  #[case(3, (0, 0), ChunkAnchor::Center, (-32., -32.))]
+  #[case(4, (0, 0), ChunkAnchor::Center, (-48., -48.))]
+  #[case(5, (0, 0), ChunkAnchor::Center, (-64., -64.))]
+  #[case(1, (-1, 0), ChunkAnchor::Center, (-32., 0.))]
+  #[case(4, (1, 1), ChunkAnchor::Center, (80., -176.))]
+  #[case(1, (0, 0), ChunkAnchor::Corner, (16., -16.))]
+  #[case(4, (0, 0), ChunkAnchor::Corner, (16., -112.))]
+  #[case(4, (-1, 1), ChunkAnchor::Corner, (-112., -240.))]
+  fn get_chunk_center_test(
+    #[case] chunk_size: u32,
+    #[case] relative_position: (i32, i32),
+    #[case] anchor: ChunkAnchor,
+    #[case] expected: (f32, f32),
   ){
-    assert_eq!(
-      crate::is_point_in_rect(
-        Vec2::from(rect_pos),
-        Vec2::from(rect_size),
-        Vec2::from(point),
-      ),
-      expected
-    );
+    assert_eq!(super::get_chunk_center(
+      UVec2::splat(chunk_size),
+      Vec2::new(32., 32.),
+      IVec2::from(relative_position),
+      anchor,
+    ), Vec2::from(expected));
   }
 
   #[rstest]
-  #[case((0., 0.), (0, 0))]
-  #[case((10., 10.), (0, 0))]
-  #[case((320., 0.), (1, 0))]
-  #[case((-320., 0.), (-1, 0))]
+  #[case((0., 0.), ChunkAnchor::Center, (0, 0))]
+  #[case((10., 10.), ChunkAnchor::Center, (0, 0))]
+  #[case((320., 0.), ChunkAnchor::Center, (1, 0))]
+  #[case((-320., 0.), ChunkAnchor::Center, (-1, 0))]
+  #[case((10., -10.), ChunkAnchor::Corner, (0, 0))]
+  #[case((10., 10.), ChunkAnchor::Corner, (0, -1))]
+  #[case((-10., -330.), ChunkAnchor::Corner, (-1, 1))]
   fn get_chunk_at_position_test(
     #[case] position: (f32, f32),
+    #[case] anchor: ChunkAnchor,
     #[case] expected: (i32, i32),
   ){
     assert_eq!(super::get_chunk_at_position(
       Vec2::from(position)
-      , UVec2::new(10, 10), Vec2::new(32., 32.)
+      , UVec2::new(10, 10), Vec2::new(32., 32.), anchor
     ), IVec2::from(expected));
   }
 
@@ -157,7 +198,59 @@ mod test{
       chunk_index,
       CHUNK_SIZE,
       tile_index,
+      ChunkAnchor::Center,
     );
     assert_eq!(local_index, IVec2::from(expected));
   }
+
+  #[rstest]
+  #[case(IVec2::new(0, 0), IVec2::new(0, 0), (0, 4))]
+  #[case(IVec2::new(0, 0), IVec2::new(0, 4), (0, 0))]
+  #[case(IVec2::new(-1, 1), IVec2::new(4, 4), (-1, 5))]
+  fn local_tile_index_to_global_corner_test(
+    #[case] chunk_index: IVec2,
+    #[case] tile_index: IVec2,
+    #[case] expected: (i32, i32),
+  ){
+    let local_index = super::local_tile_index_to_global(
+      chunk_index,
+      CHUNK_SIZE,
+      tile_index,
+      ChunkAnchor::Corner,
+    );
+    assert_eq!(local_index, IVec2::from(expected));
+  }
+
+  #[rstest]
+  #[case(UVec2::new(5, 5), ChunkAnchor::Center)]
+  #[case(UVec2::new(4, 4), ChunkAnchor::Center)]
+  #[case(UVec2::new(6, 3), ChunkAnchor::Center)]
+  #[case(UVec2::new(5, 5), ChunkAnchor::Corner)]
+  #[case(UVec2::new(4, 4), ChunkAnchor::Corner)]
+  fn tile_index_roundtrip_test(
+    #[case] chunk_size: UVec2,
+    #[case] anchor: ChunkAnchor,
+  ){
+    let tile_world_size = Vec2::new(32., 16.);
+    for chunk_y in -2..=2{
+      for chunk_x in -2..=2{
+        let chunk_index = IVec2::new(chunk_x, chunk_y);
+        let chunk_center = super::get_chunk_center(chunk_size, tile_world_size, chunk_index, anchor);
+        for y in 0..chunk_size.y as i32{
+          for x in 0..chunk_size.x as i32{
+            let local = IVec2::new(x, y);
+            let global = super::local_tile_index_to_global(chunk_index, chunk_size, local, anchor);
+            assert_eq!(super::global_tile_index_to_local(global, chunk_size, anchor), (chunk_index, local));
+
+            let position = chunk_center + local.as_vec2()*tile_world_size;
+            assert_eq!(super::get_tile_position(global, chunk_size, tile_world_size, anchor), position);
+            assert_eq!(super::get_chunk_at_position(position, chunk_size, tile_world_size, anchor), chunk_index);
+            for corner in [Vec2::new(-0.49, -0.49), Vec2::new(0.49, 0.49)]{
+              assert_eq!(super::get_tile_at_position(position + corner*tile_world_size, chunk_size, tile_world_size, anchor), global);
+            }
+          }
+        }
+      }
+    }
+  }
 }
\ No newline at end of file
@@ -1,19 +1,21 @@
 use bevy::{prelude::*};
 
-use crate::{TilemapChunk, bundle::ChunkedTilemap};
+use crate::{TilemapChunk, bundle::ChunkedTilemap, layers::ChunkedTilemapLayer, teleport::{Teleport, TeleportSettings}};
 
 pub fn despawn_outrange_chunks(
   mut commands: Commands,
+  teleport_settings: Res<TeleportSettings>,
   q_chunks: Query<(&Transform, Entity, &TilemapChunk), With<Children>>,
-  mut q_tilemaps: Query<(&mut ChunkedTilemap, &Children)>
+  mut q_tilemaps: Query<(&mut ChunkedTilemap, &Children), Without<ChunkedTilemapLayer>>
 ){
   for (mut tilemap, children) in q_tilemaps.iter_mut(){
+    let mut budget = Teleport::unload_budget(tilemap.teleport, &teleport_settings);
     for &children in children.iter(){
       if let Ok((_, entity, chunk)) =  q_chunks.get(children){    
-        let range = (chunk.0 - tilemap.current_chunk).abs();
-        if range.x > tilemap.range || range.y > tilemap.range {
+        if !tilemap.in_range(chunk.0) && budget > 0 {
+          budget -= 1;
           debug!("despawning chunk at {:?}-{:?}", chunk.0, entity);
-          tilemap.chunks.remove(&chunk.0);
+          tilemap.remove_chunk(chunk.0);
           commands.entity(entity).despawn_recursive();
         }
       }
@@ -1,6 +1,8 @@
 use bevy::prelude::*;
 use bevy_ecs_tilemap::{tiles::TileBundle, prelude::TilemapId};
 
+use crate::{bundle::ChunkedTilemap, chunk_data::ChunkData};
+
 pub struct FillChunkEvent{
   pub chunk_index: IVec2,
   pub chunk_entity: Entity,
@@ -8,9 +10,16 @@ pub struct FillChunkEvent{
 }
 pub fn fill_chunk(
   mut commands: Commands,
-  mut er_fill_chunk_event: EventReader<FillChunkEvent>
+  mut er_fill_chunk_event: EventReader<FillChunkEvent>,
+  mut q_tilemaps: Query<&mut ChunkedTilemap>,
 ){
   for event in er_fill_chunk_event.iter(){
+    let tilemap = q_tilemaps.iter_mut()
+      .find(|tilemap| tilemap.chunk_entities.get(&event.chunk_index) == Some(&event.chunk_entity));
+    if let Some(mut tilemap) = tilemap {
+      let data = ChunkData::from_bundles(tilemap.chunk_size, &event.bundles);
+      tilemap.generated.insert(event.chunk_index, data);
+    }
     debug!("filling chunk {:?}-{:?} with {:?} bundles", event.chunk_index, event.chunk_entity, event.bundles.len());
     let mut bundles = event.bundles.clone();
     for bundle in bundles.iter_mut(){
@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+
+use crate::{TilemapChunk, bundle::ChunkedTilemap, chunks::update_current_chunk, layers::ChunkedTilemapLayer};
+
+/// Keeps world coordinates small far away from the spawn. Once the center of a tilemap gets farther than `threshold`
+/// from the world origin, the world is shifted back by whole chunks: chunk transforms, `center`s, cameras and
+/// `FollowsOrigin` entities move, chunk and global tile indexes stay the same (see `ChunkedTilemap::origin`).
+///
+/// Every tilemap is shifted by the same translation, so tilemaps sharing a world need the same chunk size in pixels.
+pub struct FloatingOrigin{
+  pub threshold: f32,
+}
+
+impl Default for FloatingOrigin{
+  fn default()->FloatingOrigin{
+    FloatingOrigin{
+      threshold: 10_000.,
+    }
+  }
+}
+
+/// Top level entity moved along with the world when it gets recentered (the player, loaders), cameras are moved anyway.
+#[derive(Component, Default)]
+pub struct FollowsOrigin;
+
+/// Sent after the world got recentered, for positions kept outside of transforms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OriginShiftedEvent{
+  /// Tilemap whose center went past the threshold.
+  pub tilemap_entity: Entity,
+  /// Chunks the origin moved by.
+  pub chunk_offset: IVec2,
+  /// Translation added to everything, i.e. the opposite of how far the origin moved.
+  pub translation: Vec2,
+}
+
+/// Translation a shift of the origin by `chunk_offset` chunks moves the world by.
+pub fn get_origin_translation(chunk_offset: IVec2, chunk_size: UVec2, tile_size: Vec2)->Vec2{
+  -Vec2::new(chunk_offset.x as f32, -chunk_offset.y as f32)*tile_size*chunk_size.as_vec2()
+}
+
+/// Chunks a translation of the world moves the origin of a tilemap by, `None` unless it is made of whole chunks.
+pub fn get_origin_offset(translation: Vec2, chunk_size: UVec2, tile_size: Vec2)->Option<IVec2>{
+  let chunks = -translation/(tile_size*chunk_size.as_vec2());
+  let offset = chunks.round();
+  ((chunks - offset).abs().max_element() < 0.001).then_some(IVec2::new(offset.x as i32, -offset.y as i32))
+}
+
+type FollowerFilter = (Or<(With<Camera>, With<FollowsOrigin>)>, Without<Parent>, Without<TilemapChunk>);
+
+pub fn recenter_origin(
+  floating_origin: Res<FloatingOrigin>,
+  mut ew_origin_shifted: EventWriter<OriginShiftedEvent>,
+  mut q_tilemaps: Query<(Entity, &mut ChunkedTilemap, Option<&ChunkedTilemapLayer>)>,
+  mut q_chunks: Query<&mut Transform, With<TilemapChunk>>,
+  mut q_followers: Query<&mut Transform, FollowerFilter>,
+){
+  // layers copy the center of their root
+  let shift = q_tilemaps.iter()
+    .filter(|(_, tilemap, layer)| layer.is_none() && tilemap.center.length() > floating_origin.threshold)
+    .map(|(entity, tilemap, _)|{
+      let chunk_offset = tilemap.chunk_at_position(tilemap.center) - tilemap.origin;
+      (entity, chunk_offset, get_origin_translation(chunk_offset, tilemap.chunk_size, tilemap.tile_size))
+    })
+    .find(|&(_, chunk_offset, _)| chunk_offset != IVec2::ZERO);
+  let (tilemap_entity, chunk_offset, translation) = match shift{
+    Some(shift) => shift,
+    None => return
+  };
+
+  for (entity, mut tilemap, _) in q_tilemaps.iter_mut(){
+    let offset = match get_origin_offset(translation, tilemap.chunk_size, tilemap.tile_size){
+      Some(offset) => offset,
+      None => {
+        warn!("tilemap {:?} can't follow the floating origin, its chunks don't fit the shift", entity);
+        continue;
+      }
+    };
+    tilemap.origin += offset;
+    tilemap.center += translation;
+    for (&chunk_index, &chunk_entity) in tilemap.chunk_entities.iter(){
+      if let Ok(mut transform) = q_chunks.get_mut(chunk_entity){
+        // placed again rather than moved, so errors don't pile up
+        let position = tilemap.chunk_position(chunk_index);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+      }
+    }
+  }
+  for mut transform in q_followers.iter_mut(){
+    transform.translation += translation.extend(0.);
+  }
+  debug!("origin shifted by {} chunks", chunk_offset);
+  ew_origin_shifted.send(OriginShiftedEvent{
+    tilemap_entity,
+    chunk_offset,
+    translation,
+  });
+}
+
+/// Recenters the world with a `FloatingOrigin`, the default one unless it is inserted before.
+pub struct FloatingOriginPlugin;
+
+impl Plugin for FloatingOriginPlugin{
+  fn build(&self, app: &mut App){
+    app
+      .init_resource::<FloatingOrigin>()
+      .add_event::<OriginShiftedEvent>()
+      .add_system(recenter_origin.before(update_current_chunk));
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use rstest::rstest;
+  use super::{get_origin_offset, get_origin_translation};
+
+  #[rstest]
+  #[case(IVec2::new(3, -2), UVec2::new(16, 16), Vec2::new(8., 8.))]
+  #[case(IVec2::new(-10_000_001, 9_999_999), UVec2::new(16, 16), Vec2::new(16., 16.))]
+  fn origin_translation_test(
+    #[case] chunk_offset: IVec2,
+    #[case] chunk_size: UVec2,
+    #[case] tile_size: Vec2,
+  ){
+    let translation = get_origin_translation(chunk_offset, chunk_size, tile_size);
+    assert_eq!(translation.x < 0., chunk_offset.x > 0);
+    assert_eq!(translation.y > 0., chunk_offset.y > 0);
+    assert_eq!(get_origin_offset(translation, chunk_size, tile_size), Some(chunk_offset));
+    // half the chunk size doesn't fit whole chunks
+    assert_eq!(get_origin_offset(translation, chunk_size*2, tile_size), None);
+  }
+}
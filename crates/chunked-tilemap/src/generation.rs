@@ -0,0 +1,529 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+  authored::AuthoredRegions,
+  bundle::{ChunkedTilemap, ChunkAnchor, ChunkBounds},
+  chunk_data::ChunkData,
+  chunks::{global_tile_index_to_local, local_tile_index_to_global, wrap_global_tile_index},
+  fill_chunk::FillChunkEvent,
+  spawn_around::generate_chunk_indexes,
+  spawn_chunk::PrepareChunkEvent,
+  random::SeededRng,
+  stamps::Stamp,
+  tiles::SetTileEvent,
+};
+
+/// One pass of a `GenerationPipeline`.
+///
+/// A pass may read the chunks within `neighbor_radius` as they were after the previous pass. Structures crossing
+/// chunk borders are placed by looking at every chunk in the radius and drawing only the part falling into the
+/// generated chunk, so the result doesn't depend on the order in which chunks get loaded.
+pub trait ChunkGenerator: Send + Sync + 'static{
+  fn neighbor_radius(&self)->i32{
+    0
+  }
+
+  fn generate(&self, context: &mut GenerationContext);
+}
+
+impl<F> ChunkGenerator for F where F: Fn(&mut GenerationContext) + Send + Sync + 'static{
+  fn generate(&self, context: &mut GenerationContext){
+    self(context)
+  }
+}
+
+/// Chunk data after each completed pass.
+#[derive(Default)]
+struct ProtoChunk{
+  passes: Vec<ChunkData>,
+}
+
+fn wrap(bounds: &Option<ChunkBounds>, chunk_index: IVec2)->IVec2{
+  match bounds{
+    Some(bounds) => bounds.wrap(chunk_index),
+    None => chunk_index,
+  }
+}
+
+pub struct GenerationContext<'a>{
+  pub chunk_index: IVec2,
+  pub chunk_size: UVec2,
+  pub anchor: ChunkAnchor,
+  /// Index of the running pass.
+  pub pass: usize,
+  /// Data of the generated chunk, as left by the previous pass.
+  pub data: ChunkData,
+  pub world_seed: u64,
+  pub layer_id: u64,
+  neighbor_radius: i32,
+  bounds: Option<ChunkBounds>,
+  cache: &'a HashMap<IVec2, ProtoChunk>,
+  deferred: Vec<(IVec2, u32)>,
+}
+
+impl<'a> GenerationContext<'a>{
+  pub fn local_to_global(&self, local_tile_index: UVec2)->IVec2{
+    local_tile_index_to_global(self.chunk_index, self.chunk_size, local_tile_index.as_ivec2(), self.anchor)
+  }
+
+  pub fn global_to_local(&self, global_tile_index: IVec2)->(IVec2, IVec2){
+    global_tile_index_to_local(global_tile_index, self.chunk_size, self.anchor)
+  }
+
+  /// Random numbers for the generated chunk, each pass gets its own sequence.
+  pub fn rng(&self)->SeededRng{
+    SeededRng::for_chunk(self.world_seed, self.layer_id.wrapping_add(self.pass as u64), self.chunk_index)
+  }
+
+  /// Random numbers for a single tile, the same no matter which chunk (or repetition of a wrapping world) asks for them.
+  pub fn tile_rng(&self, global_tile_index: IVec2)->SeededRng{
+    let global_tile_index = match self.bounds{
+      Some(bounds) => wrap_global_tile_index(global_tile_index, self.chunk_size, self.anchor, &bounds),
+      None => global_tile_index,
+    };
+    SeededRng::for_tile(self.world_seed, self.layer_id.wrapping_add(self.pass as u64), global_tile_index)
+  }
+
+  /// Bounds of a wrapping world, the generated chunk is already wrapped into them.
+  pub fn bounds(&self)->Option<ChunkBounds>{
+    self.bounds
+  }
+
+  /// Chunks the running pass is allowed to read, including the generated one.
+  pub fn chunks_in_radius(&self)->Vec<IVec2>{
+    generate_chunk_indexes(self.chunk_index, self.neighbor_radius)
+  }
+
+  /// Data of a chunk in `neighbor_radius` as it was after the previous pass.
+  pub fn chunk(&self, chunk_index: IVec2)->Option<&ChunkData>{
+    let distance = (chunk_index - self.chunk_index).abs();
+    if self.pass == 0 || distance.x > self.neighbor_radius || distance.y > self.neighbor_radius {
+      return None;
+    }
+    self.cache.get(&wrap(&self.bounds, chunk_index)).and_then(|proto| proto.passes.get(self.pass - 1))
+  }
+
+  /// Tile at a global index. Tiles of the generated chunk come from `data`,
+  /// tiles of its neighbors are read as they were after the previous pass.
+  pub fn get_global(&self, global_tile_index: IVec2)->Option<u32>{
+    let (chunk_index, local_tile_index) = self.global_to_local(global_tile_index);
+    if chunk_index == self.chunk_index {
+      self.data.get(local_tile_index.as_uvec2())
+    } else {
+      self.chunk(chunk_index).and_then(|data| data.get(local_tile_index.as_uvec2()))
+    }
+  }
+
+  /// Sets a tile of the generated chunk by its global index, tiles outside of the chunk are clipped.
+  pub fn set_global(&mut self, global_tile_index: IVec2, texture: Option<u32>)->bool{
+    let (chunk_index, local_tile_index) = self.global_to_local(global_tile_index);
+    chunk_index == self.chunk_index && self.data.set(local_tile_index.as_uvec2(), texture)
+  }
+
+  /// Draws a layer of a stamp placed at `position`. The part falling into the generated chunk is drawn right away,
+  /// the rest is deferred to the neighbor chunks and put over their final data, or set with `SetTileEvent`s
+  /// if they are already filled.
+  pub fn place_stamp(&mut self, stamp: &Stamp, layer: &str, position: IVec2){
+    for (global_tile_index, tile) in stamp.tiles(layer, position){
+      let (chunk_index, _) = self.global_to_local(global_tile_index);
+      if chunk_index == self.chunk_index {
+        self.set_global(global_tile_index, Some(tile));
+      } else {
+        self.deferred.push((global_tile_index, tile));
+      }
+    }
+  }
+}
+
+/// Generates chunks of a `ChunkedTilemap` by running them through a sequence of passes.
+#[derive(Component, Default)]
+pub struct GenerationPipeline{
+  passes: Vec<Box<dyn ChunkGenerator>>,
+  cache: HashMap<IVec2, ProtoChunk>,
+  /// Tiles of stamps deferred to other chunks, by chunk index and global tile index, with the chunk which placed them.
+  deferred: HashMap<IVec2, HashMap<IVec2, (u32, IVec2)>>,
+  /// Deferred tiles not taken by `take_deferred` yet.
+  new_deferred: Vec<(IVec2, u32)>,
+  world_seed: u64,
+  layer_id: u64,
+  bounds: Option<ChunkBounds>,
+}
+
+impl GenerationPipeline{
+  pub fn new()->GenerationPipeline{
+    GenerationPipeline::default()
+  }
+
+  /// Seeds the random numbers handed to the passes, see `random::layer_id`.
+  pub fn with_seed(mut self, world_seed: u64, layer_id: u64)->GenerationPipeline{
+    self.world_seed = world_seed;
+    self.layer_id = layer_id;
+    self.clear();
+    self
+  }
+
+  /// Bounds of a wrapping world, chunks past their edges are generated as the ones they stand for
+  /// and passes read neighbors across the edges.
+  pub fn with_bounds(mut self, bounds: Option<ChunkBounds>)->GenerationPipeline{
+    self.set_bounds(bounds);
+    self
+  }
+
+  pub fn set_bounds(&mut self, bounds: Option<ChunkBounds>){
+    if self.bounds != bounds {
+      self.bounds = bounds;
+      self.clear();
+    }
+  }
+
+  pub fn with_pass(mut self, pass: impl ChunkGenerator)->GenerationPipeline{
+    self.passes.push(Box::new(pass));
+    self
+  }
+
+  /// How far from the loaded chunks partially generated chunks may be needed.
+  pub fn total_radius(&self)->i32{
+    self.passes.iter().skip(1).map(|pass| pass.neighbor_radius()).sum()
+  }
+
+  pub fn generate(&mut self, chunk_index: IVec2, chunk_size: UVec2, anchor: ChunkAnchor)->ChunkData{
+    let chunk_index = wrap(&self.bounds, chunk_index);
+    self.ensure(chunk_index, self.passes.len(), chunk_size, anchor);
+    let mut data = self.cache.get(&chunk_index)
+      .and_then(|proto| proto.passes.last().cloned())
+      .unwrap_or_else(|| ChunkData::new(chunk_size));
+    for (&global_tile_index, &(tile, _)) in self.deferred.get(&chunk_index).into_iter().flatten(){
+      let (_, local_tile_index) = global_tile_index_to_local(global_tile_index, chunk_size, anchor);
+      data.set(local_tile_index.as_uvec2(), Some(tile));
+    }
+    data
+  }
+
+  /// Stamp tiles deferred to other chunks since the last call, by global tile index.
+  /// Chunks generated later get them anyway, this is for the ones which were generated already.
+  pub fn take_deferred(&mut self)->Vec<(IVec2, u32)>{
+    std::mem::take(&mut self.new_deferred)
+  }
+
+  fn ensure(&mut self, chunk_index: IVec2, passes: usize, chunk_size: UVec2, anchor: ChunkAnchor){
+    let done = self.cache.get(&chunk_index).map_or(0, |proto| proto.passes.len());
+    for pass in done..passes{
+      let neighbor_radius = if pass > 0 { self.passes[pass].neighbor_radius().max(0) } else { 0 };
+      for neighbor in generate_chunk_indexes(chunk_index, neighbor_radius){
+        let neighbor = wrap(&self.bounds, neighbor);
+        if neighbor != chunk_index {
+          self.ensure(neighbor, pass, chunk_size, anchor);
+        }
+      }
+
+      let data = self.cache.get(&chunk_index)
+        .and_then(|proto| proto.passes.last().cloned())
+        .unwrap_or_else(|| ChunkData::new(chunk_size));
+      let mut context = GenerationContext{
+        chunk_index,
+        chunk_size,
+        anchor,
+        pass,
+        data,
+        world_seed: self.world_seed,
+        layer_id: self.layer_id,
+        neighbor_radius,
+        bounds: self.bounds,
+        cache: &self.cache,
+        deferred: vec![],
+      };
+      self.passes[pass].generate(&mut context);
+      let (data, deferred) = (context.data, context.deferred);
+      self.cache.entry(chunk_index).or_default().passes.push(data);
+      for (global_tile_index, tile) in deferred{
+        let (target, _) = global_tile_index_to_local(global_tile_index, chunk_size, anchor);
+        let target = wrap(&self.bounds, target);
+        let previous = self.deferred.entry(target).or_default().insert(global_tile_index, (tile, chunk_index));
+        if previous.map(|(previous, _)| previous) != Some(tile) {
+          self.new_deferred.push((global_tile_index, tile));
+        }
+      }
+    }
+  }
+
+  /// Drops cached chunks farther than `range` from `center_chunk`, along with the stamp tiles they deferred to
+  /// other chunks. Those come back when the chunks get generated again.
+  pub fn retain_around(&mut self, center_chunk: IVec2, range: i32){
+    let bounds = self.bounds;
+    self.cache.retain(|&chunk_index, _|{
+      let distance = match bounds{
+        Some(bounds) => bounds.offset(center_chunk, chunk_index).abs(),
+        None => (chunk_index - center_chunk).abs(),
+      };
+      distance.x <= range && distance.y <= range
+    });
+    let cache = &self.cache;
+    self.deferred.retain(|_, tiles|{
+      tiles.retain(|_, (_, source)| cache.contains_key(source));
+      !tiles.is_empty()
+    });
+  }
+
+  pub fn clear(&mut self){
+    self.cache.clear();
+    self.deferred.clear();
+    self.new_deferred.clear();
+  }
+}
+
+pub fn generate_chunks(
+  mut er_prepare_chunk: EventReader<PrepareChunkEvent>,
+  mut ew_fill_chunk: EventWriter<FillChunkEvent>,
+  mut ew_set_tile: EventWriter<SetTileEvent>,
+  mut q_tilemaps: Query<(Entity, &ChunkedTilemap, &mut GenerationPipeline, Option<&AuthoredRegions>)>,
+){
+  let mut generated = vec![];
+  for event in er_prepare_chunk.iter(){
+    if let Ok((_, tilemap, mut pipeline, authored)) = q_tilemaps.get_mut(event.tilemap_entity){
+      pipeline.set_bounds(tilemap.bounds);
+      let (world_seed, layer_id) = (pipeline.world_seed, pipeline.layer_id);
+      let mut generate = || pipeline.generate(event.chunk_index, tilemap.chunk_size, tilemap.anchor);
+      let data = match authored{
+        Some(authored) => authored.serve(tilemap.wrap_chunk(event.chunk_index), tilemap.chunk_size, tilemap.anchor, world_seed, layer_id, generate),
+        None => generate(),
+      };
+      generated.push((event.tilemap_entity, event.chunk_index, event.chunk_entity, data));
+    }
+  }
+  for (tilemap_entity, tilemap, mut pipeline, _) in q_tilemaps.iter_mut(){
+    for (global_tile_index, tile) in pipeline.take_deferred(){
+      // chunks which are not loaded get the tile once they are generated
+      let mut filled = false;
+      for (chunk_index, local_tile_index) in tilemap.loaded_tiles(global_tile_index){
+        let chunk = generated.iter_mut()
+          .find(|(entity, index, ..)| *entity == tilemap_entity && *index == chunk_index);
+        match chunk {
+          Some((.., data)) => { data.set(local_tile_index.as_uvec2(), Some(tile)); },
+          None => filled = true,
+        }
+      }
+      // `set_tiles` updates every filled repetition
+      if filled {
+        ew_set_tile.send(SetTileEvent{tilemap_entity, global_tile_index, tile: Some(tile)});
+      }
+    }
+    let keep_range = tilemap.range + pipeline.total_radius() + 1;
+    pipeline.retain_around(tilemap.current_chunk, keep_range);
+  }
+  for (_, chunk_index, chunk_entity, data) in generated{
+    ew_fill_chunk.send(FillChunkEvent{
+      bundles: data.to_bundles(),
+      chunk_index,
+      chunk_entity,
+    });
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use rand::Rng;
+  use crate::{bundle::{ChunkAnchor, ChunkBounds}, chunk_data::ChunkData, random::layer_id, stamps::Stamp};
+  use super::{ChunkGenerator, GenerationContext, GenerationPipeline};
+
+  const CHUNK_SIZE: UVec2 = UVec2{x: 5, y: 5};
+  const HOUSE: u32 = 2;
+  const GRASS: u32 = 1;
+
+  fn terrain(context: &mut GenerationContext){
+    context.data = ChunkData::filled(context.chunk_size, GRASS);
+    // every third chunk gets a house seed in its center
+    if (context.chunk_index.x + 2*context.chunk_index.y).rem_euclid(3) == 0 {
+      context.data.set(UVec2::new(2, 2), Some(0));
+    }
+  }
+
+  /// Draws a row of houses from every seed 6 tiles to the east, crossing into the next chunk.
+  struct Houses;
+
+  impl ChunkGenerator for Houses{
+    fn neighbor_radius(&self)->i32{
+      1
+    }
+
+    fn generate(&self, context: &mut GenerationContext){
+      for chunk_index in context.chunks_in_radius(){
+        let seed = context.chunk(chunk_index).and_then(|data| data.get(UVec2::new(2, 2)));
+        if seed == Some(0) {
+          let origin = crate::chunks::local_tile_index_to_global(chunk_index, context.chunk_size, IVec2::new(2, 2), context.anchor);
+          for x in 0..6{
+            context.set_global(origin + IVec2::new(x, 0), Some(HOUSE));
+          }
+        }
+      }
+    }
+  }
+
+  fn pipeline()->GenerationPipeline{
+    GenerationPipeline::new()
+      .with_pass(terrain)
+      .with_pass(Houses)
+  }
+
+  #[test]
+  fn passes_run_in_order_test(){
+    let data = pipeline().generate(IVec2::new(0, 0), CHUNK_SIZE, ChunkAnchor::Center);
+    assert_eq!(data.get(UVec2::new(0, 0)), Some(GRASS));
+    assert_eq!(data.get(UVec2::new(2, 2)), Some(HOUSE));
+    assert_eq!(data.get(UVec2::new(4, 2)), Some(HOUSE));
+    assert_eq!(data.get(UVec2::new(1, 2)), Some(GRASS));
+  }
+
+  #[test]
+  fn structures_cross_chunk_borders_test(){
+    // the house row started in chunk (0, 0) ends in chunk (1, 0)
+    let data = pipeline().generate(IVec2::new(1, 0), CHUNK_SIZE, ChunkAnchor::Center);
+    assert_eq!(data.get(UVec2::new(0, 2)), Some(HOUSE));
+    assert_eq!(data.get(UVec2::new(2, 2)), Some(HOUSE));
+    assert_eq!(data.get(UVec2::new(3, 2)), Some(GRASS));
+  }
+
+  #[test]
+  fn load_order_does_not_matter_test(){
+    let chunks: Vec<IVec2> = crate::spawn_around::generate_chunk_indexes(IVec2::ZERO, 3);
+
+    let mut forward = pipeline();
+    let forward_data: Vec<ChunkData> = chunks.iter().map(|&chunk_index| forward.generate(chunk_index, CHUNK_SIZE, ChunkAnchor::Center)).collect();
+
+    let mut backward = pipeline();
+    let mut backward_data: Vec<ChunkData> = chunks.iter().rev().map(|&chunk_index| backward.generate(chunk_index, CHUNK_SIZE, ChunkAnchor::Center)).collect();
+    backward_data.reverse();
+
+    assert_eq!(forward_data, backward_data);
+  }
+
+  fn noisy(context: &mut GenerationContext){
+    let mut rng = context.rng();
+    for y in 0..context.chunk_size.y{
+      for x in 0..context.chunk_size.x{
+        if rng.gen_bool(0.5) {
+          context.data.set(UVec2::new(x, y), Some(rng.gen_range(0..20)));
+        }
+      }
+    }
+  }
+
+  fn scattered(context: &mut GenerationContext){
+    for y in 0..context.chunk_size.y{
+      for x in 0..context.chunk_size.x{
+        let global_tile_index = context.local_to_global(UVec2::new(x, y));
+        if context.tile_rng(global_tile_index).gen_ratio(1, 4) {
+          context.data.set(UVec2::new(x, y), Some(HOUSE));
+        }
+      }
+    }
+  }
+
+  fn seeded_pipeline(world_seed: u64)->GenerationPipeline{
+    GenerationPipeline::new()
+      .with_seed(world_seed, layer_id("ground"))
+      .with_pass(noisy)
+      .with_pass(scattered)
+  }
+
+  #[test]
+  fn same_seed_same_chunks_test(){
+    let chunks = crate::spawn_around::generate_chunk_indexes(IVec2::new(4, -2), 2);
+    let mut first = seeded_pipeline(42);
+    let mut second = seeded_pipeline(42);
+    for &chunk_index in chunks.iter(){
+      let data = first.generate(chunk_index, CHUNK_SIZE, ChunkAnchor::Center);
+      // a reloaded chunk comes out the same as well
+      first.clear();
+      assert_eq!(data, first.generate(chunk_index, CHUNK_SIZE, ChunkAnchor::Center));
+      assert_eq!(data, second.generate(chunk_index, CHUNK_SIZE, ChunkAnchor::Center));
+    }
+    assert_ne!(
+      seeded_pipeline(42).generate(IVec2::ZERO, CHUNK_SIZE, ChunkAnchor::Center),
+      seeded_pipeline(43).generate(IVec2::ZERO, CHUNK_SIZE, ChunkAnchor::Center)
+    );
+  }
+
+  #[test]
+  fn retain_around_test(){
+    let mut pipeline = pipeline();
+    pipeline.generate(IVec2::new(0, 0), CHUNK_SIZE, ChunkAnchor::Center);
+    pipeline.generate(IVec2::new(10, 0), CHUNK_SIZE, ChunkAnchor::Center);
+    pipeline.retain_around(IVec2::new(10, 0), 1);
+    assert!(!pipeline.cache.contains_key(&IVec2::new(0, 0)));
+    assert_eq!(pipeline.cache.get(&IVec2::new(10, 0)).unwrap().passes.len(), 2);
+    assert_eq!(pipeline.cache.get(&IVec2::new(11, 0)).unwrap().passes.len(), 1);
+  }
+
+  #[test]
+  fn wrapped_chunks_test(){
+    let bounds = ChunkBounds::new(IVec2::ZERO, IVec2::new(3, 3)).with_wrap(true, true);
+    let mut wrapped = pipeline().with_bounds(Some(bounds));
+    let data = wrapped.generate(IVec2::ZERO, CHUNK_SIZE, ChunkAnchor::Center);
+    assert_eq!(data, wrapped.generate(IVec2::new(4, -4), CHUNK_SIZE, ChunkAnchor::Center));
+    assert!(wrapped.cache.keys().all(|&chunk_index| bounds.wrap(chunk_index) == chunk_index));
+    // houses of chunk (3, 0) cross the edge, chunk (-1, 0) of an endless world has none
+    assert_eq!(data.get(UVec2::new(0, 2)), Some(HOUSE));
+    assert_eq!(pipeline().generate(IVec2::ZERO, CHUNK_SIZE, ChunkAnchor::Center).get(UVec2::new(0, 2)), Some(GRASS));
+    wrapped.retain_around(IVec2::ZERO, 1);
+    assert!(wrapped.cache.contains_key(&IVec2::new(3, 0)));
+  }
+
+  fn stamped(context: &mut GenerationContext){
+    context.data = ChunkData::filled(context.chunk_size, GRASS);
+    if context.chunk_index == IVec2::ZERO {
+      // 3x1 stamp on the east edge, sticking out into chunk (1, 0)
+      let stamp = Stamp::new().with_layer("ground", vec![vec![Some(HOUSE), Some(HOUSE), Some(HOUSE)]]);
+      let position = context.local_to_global(UVec2::new(4, 2));
+      context.place_stamp(&stamp, "ground", position);
+    }
+  }
+
+  #[test]
+  fn stamps_are_deferred_to_neighbors_test(){
+    let mut pipeline = GenerationPipeline::new().with_pass(stamped);
+    let east = pipeline.generate(IVec2::new(1, 0), CHUNK_SIZE, ChunkAnchor::Center);
+    assert_eq!(east.get(UVec2::new(0, 2)), Some(GRASS));
+    let data = pipeline.generate(IVec2::ZERO, CHUNK_SIZE, ChunkAnchor::Center);
+    assert_eq!(data.get(UVec2::new(4, 2)), Some(HOUSE));
+    assert_eq!(data.get(UVec2::new(3, 2)), Some(GRASS));
+    // the east chunk was generated before the stamp got placed
+    let deferred = pipeline.take_deferred();
+    assert_eq!(deferred.len(), 2);
+    assert!(pipeline.take_deferred().is_empty());
+    let east = pipeline.generate(IVec2::new(1, 0), CHUNK_SIZE, ChunkAnchor::Center);
+    assert_eq!(east.get(UVec2::new(0, 2)), Some(HOUSE));
+    assert_eq!(east.get(UVec2::new(1, 2)), Some(HOUSE));
+    assert_eq!(east.get(UVec2::new(2, 2)), Some(GRASS));
+    // an evicted stamped chunk defers its tiles again once it is generated again
+    pipeline.retain_around(IVec2::new(2, 0), 1);
+    assert!(!pipeline.cache.contains_key(&IVec2::ZERO));
+    assert!(pipeline.deferred.is_empty());
+    pipeline.generate(IVec2::ZERO, CHUNK_SIZE, ChunkAnchor::Center);
+    assert_eq!(pipeline.take_deferred().len(), 2);
+  }
+
+  #[test]
+  fn deferred_tiles_stay_with_their_cached_source_test(){
+    let mut pipeline = GenerationPipeline::new().with_pass(stamped);
+    pipeline.generate(IVec2::ZERO, CHUNK_SIZE, ChunkAnchor::Center);
+    pipeline.generate(IVec2::X, CHUNK_SIZE, ChunkAnchor::Center);
+    // the stamped chunk sits at exactly the range, the chunk it deferred tiles to right past it
+    pipeline.retain_around(IVec2::new(-2, 0), 2);
+    assert!(pipeline.cache.contains_key(&IVec2::ZERO));
+    assert!(!pipeline.cache.contains_key(&IVec2::X));
+    let east = pipeline.generate(IVec2::X, CHUNK_SIZE, ChunkAnchor::Center);
+    assert_eq!(east.get(UVec2::new(0, 2)), Some(HOUSE));
+    assert_eq!(east.get(UVec2::new(1, 2)), Some(HOUSE));
+  }
+
+  #[test]
+  fn deferred_tiles_are_dropped_out_of_range_test(){
+    let mut pipeline = GenerationPipeline::new().with_pass(stamped);
+    pipeline.generate(IVec2::ZERO, CHUNK_SIZE, ChunkAnchor::Center);
+    assert!(pipeline.deferred.contains_key(&IVec2::X));
+    pipeline.retain_around(IVec2::new(1, 5), 2);
+    assert!(pipeline.deferred.is_empty());
+    assert!(pipeline.cache.is_empty());
+  }
+}
@@ -0,0 +1,187 @@
+use bevy::prelude::*;
+
+use crate::{bundle::ChunkAnchor, chunks::{get_tile_at_position, get_tile_position}};
+
+/// Checks if `point` is inside of the axis aligned rectangle of `rect_size` centered at `rect_pos`, borders included.
+pub fn is_point_in_rect(
+  rect_pos: Vec2,
+  rect_size: Vec2,
+  point: Vec2
+)->bool{
+  let distance = (point-rect_pos).abs();
+  distance.x<=rect_size.x/2. && distance.y<=rect_size.y/2.
+}
+
+pub fn is_point_in_circle(
+  center: Vec2,
+  radius: f32,
+  point: Vec2,
+)->bool{
+  center.distance_squared(point)<=radius*radius
+}
+
+/// Area in tilemap space. A tile belongs to the area if its center does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TileArea{
+  Rect{min: Vec2, max: Vec2},
+  Circle{center: Vec2, radius: f32},
+}
+
+impl TileArea{
+  pub fn rect(a: Vec2, b: Vec2)->TileArea{
+    TileArea::Rect{min: a.min(b), max: a.max(b)}
+  }
+
+  pub fn circle(center: Vec2, radius: f32)->TileArea{
+    TileArea::Circle{center, radius: radius.abs()}
+  }
+
+  pub fn bounds(&self)->(Vec2, Vec2){
+    match *self{
+      TileArea::Rect{min, max} => (min, max),
+      TileArea::Circle{center, radius} => (center-radius, center+radius),
+    }
+  }
+
+  pub fn contains(&self, point: Vec2)->bool{
+    match *self{
+      TileArea::Rect{min, max} => is_point_in_rect((min+max)/2., max-min, point),
+      TileArea::Circle{center, radius} => is_point_in_circle(center, radius, point),
+    }
+  }
+
+  pub fn translate(&self, offset: Vec2)->TileArea{
+    match *self{
+      TileArea::Rect{min, max} => TileArea::Rect{min: min+offset, max: max+offset},
+      TileArea::Circle{center, radius} => TileArea::Circle{center: center+offset, radius},
+    }
+  }
+}
+
+/// Global indexes of the tiles whose centers are inside of `area`, row by row from the top.
+pub fn get_tile_indexes_in_area(
+  area: TileArea,
+  chunk_size: UVec2,
+  tile_size: Vec2,
+  anchor: ChunkAnchor,
+)->Vec<IVec2>{
+  let (min, max) = area.bounds();
+  let a = get_tile_at_position(min, chunk_size, tile_size, anchor);
+  let b = get_tile_at_position(max, chunk_size, tile_size, anchor);
+  let (from, to) = (a.min(b), a.max(b));
+
+  let mut indexes = vec![];
+  for y in from.y..=to.y{
+    for x in from.x..=to.x{
+      let index = IVec2::new(x, y);
+      if area.contains(get_tile_position(index, chunk_size, tile_size, anchor)){
+        indexes.push(index);
+      }
+    }
+  }
+  indexes
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use rstest::rstest;
+  use crate::bundle::ChunkAnchor;
+  use super::{TileArea, get_tile_indexes_in_area};
+
+  #[rstest]
+  #[case((0., 0.), (10., 10.), (5., 0.), true)]
+  #[case((0., 0.), (10., 10.), (-6., 5.), false)]
+  #[case((0., 0.), (10., 10.), (-5., 0.), true)]
+  #[case((0., 0.), (10., 10.), (7., 7.), false)]
+  #[case((0., 0.), (10., 10.), (5., 0.), true)]
+  #[case((0., 0.), (10., 10.), (50., 5.), false)]
+  #[case((0., 0.), (10., 10.), (-50., 5.), false)]
+
+  #[case((0., 0.), (10., 10.), (-50., 5.), false)]
+  #[case((0., 0.), (10., 10.), (5., 50.), false)]
+
+  #[case((-1., -1.), (10., 10.), (3., 3.), true)]
+  #[case((-1., -1.), (10., 10.), (5., 0.), false)]
+  #[case((-1., -1.), (10., 10.), (-50., 1.), false)]
+  #[case((10., 0.), (4., 20.), (11., -9.), true)]
+  #[case((10., 0.), (4., 20.), (13., 0.), false)]
+  fn is_point_in_rect_test(
+    #[case] rect_pos: (f32, f32),
+    #[case] rect_size: (f32, f32),
+    #[case] point: (f32, f32),
+    #[case] expected: bool,
+  ){
+    assert_eq!(
+      super::is_point_in_rect(
+        Vec2::from(rect_pos),
+        Vec2::from(rect_size),
+        Vec2::from(point),
+      ),
+      expected
+    );
+  }
+
+  #[rstest]
+  #[case((0., 0.), 5., (3., 4.), true)]
+  #[case((0., 0.), 5., (3.1, 4.), false)]
+  #[case((-10., 2.), 1., (-10., 1.), true)]
+  fn is_point_in_circle_test(
+    #[case] center: (f32, f32),
+    #[case] radius: f32,
+    #[case] point: (f32, f32),
+    #[case] expected: bool,
+  ){
+    assert_eq!(super::is_point_in_circle(Vec2::from(center), radius, Vec2::from(point)), expected);
+  }
+
+  const CHUNK_SIZE: UVec2 = UVec2{x: 5, y: 5};
+  const TILE_SIZE: Vec2 = Vec2{x: 32., y: 32.};
+
+  #[test]
+  fn get_tile_indexes_in_rect_test(){
+    // tile centers of odd sized centered chunks lie on multiples of the tile size
+    let indexes = get_tile_indexes_in_area(
+      TileArea::rect(Vec2::new(-10., -40.), Vec2::new(40., 10.)),
+      CHUNK_SIZE, TILE_SIZE, ChunkAnchor::Center
+    );
+    assert_eq!(indexes, vec![
+      IVec2::new(0, 0), IVec2::new(1, 0),
+      IVec2::new(0, 1), IVec2::new(1, 1),
+    ]);
+  }
+
+  #[test]
+  fn get_tile_indexes_in_rect_across_chunks_test(){
+    let indexes = get_tile_indexes_in_area(
+      TileArea::rect(Vec2::new(-200., -1.), Vec2::new(200., 1.)),
+      CHUNK_SIZE, TILE_SIZE, ChunkAnchor::Center
+    );
+    assert_eq!(indexes, (-6..=6).map(|x| IVec2::new(x, 0)).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn get_tile_indexes_in_circle_test(){
+    let indexes = get_tile_indexes_in_area(
+      TileArea::circle(Vec2::new(0., 0.), 32.),
+      CHUNK_SIZE, TILE_SIZE, ChunkAnchor::Center
+    );
+    assert_eq!(indexes, vec![
+      IVec2::new(0, -1),
+      IVec2::new(-1, 0), IVec2::new(0, 0), IVec2::new(1, 0),
+      IVec2::new(0, 1),
+    ]);
+  }
+
+  #[test]
+  fn get_tile_indexes_in_circle_corner_anchor_test(){
+    let indexes = get_tile_indexes_in_area(
+      TileArea::circle(Vec2::new(32., -32.), 23.),
+      CHUNK_SIZE, TILE_SIZE, ChunkAnchor::Corner
+    );
+    assert_eq!(indexes, vec![
+      IVec2::new(0, 0), IVec2::new(1, 0),
+      IVec2::new(0, 1), IVec2::new(1, 1),
+    ]);
+  }
+}
@@ -0,0 +1,180 @@
+use bevy::prelude::*;
+
+use crate::{bundle::ChunkedTilemap, spawn_chunk::{PrepareChunkEvent, SpawnChunkEvent}, spawn_around::generate_chunk_indexes, teleport::{Teleport, TeleportSettings}};
+
+/// Root of a layered tilemap. Its `ChunkedTilemap` decides which chunks are loaded, the tiles themselves live in
+/// the `ChunkedTilemapLayer` children, which get their chunks spawned and despawned together.
+#[derive(Component, Default)]
+pub struct LayeredTilemap{
+  /// Registered layers, in the order they were added.
+  pub layers: Vec<Entity>,
+}
+
+/// Render layer of a `LayeredTilemap`, spawned as a child of the root. Chunk settings are copied from the root,
+/// the layer only brings its texture and its `Transform` (usually just a z offset).
+#[derive(Component, Default, Clone)]
+pub struct ChunkedTilemapLayer{
+  pub texture_handle: Handle<Image>,
+}
+
+#[derive(Bundle, Default)]
+pub struct ChunkedTilemapLayerBundle{
+  pub layer: ChunkedTilemapLayer,
+  pub name: Name,
+  #[bundle]
+  pub spatial: SpatialBundle,
+}
+
+impl ChunkedTilemapLayerBundle{
+  pub fn new(name: &str, texture_handle: Handle<Image>, z_offset: f32)->ChunkedTilemapLayerBundle{
+    ChunkedTilemapLayerBundle{
+      layer: ChunkedTilemapLayer{texture_handle},
+      name: Name::new(name.to_string()),
+      spatial: SpatialBundle{
+        transform: Transform::from_xyz(0., 0., z_offset),
+        ..Default::default()
+      },
+    }
+  }
+}
+
+pub fn register_layers(
+  mut commands: Commands,
+  q_added: Query<(Entity, &ChunkedTilemapLayer, &Parent), Added<ChunkedTilemapLayer>>,
+  mut q_roots: Query<(&ChunkedTilemap, &mut LayeredTilemap)>,
+){
+  for (entity, layer, parent) in q_added.iter(){
+    if let Ok((root, mut layered)) = q_roots.get_mut(parent.get()){
+      commands.entity(entity).insert(ChunkedTilemap{
+        texture_handle: layer.texture_handle.clone(),
+        chunks: Default::default(),
+        chunk_entities: Default::default(),
+        generated: Default::default(),
+        repetitions: Default::default(),
+        ..root.clone()
+      });
+      layered.layers.push(entity);
+    } else {
+      warn!("layer {:?} is not a child of a LayeredTilemap", entity);
+    }
+  }
+}
+
+/// Copies the streaming state of every root to its layers.
+pub fn sync_layers(
+  q_roots: Query<(&ChunkedTilemap, &LayeredTilemap), Without<ChunkedTilemapLayer>>,
+  mut q_layers: Query<&mut ChunkedTilemap, With<ChunkedTilemapLayer>>,
+){
+  for (root, layered) in q_roots.iter(){
+    for &layer in layered.layers.iter(){
+      if let Ok(mut tilemap) = q_layers.get_mut(layer){
+        tilemap.chunk_size = root.chunk_size;
+        tilemap.tile_size = root.tile_size;
+        tilemap.range = root.range;
+        tilemap.anchor = root.anchor;
+        tilemap.center = root.center;
+        tilemap.current_chunk = root.current_chunk;
+        tilemap.bounds = root.bounds;
+        tilemap.origin = root.origin;
+      }
+    }
+  }
+}
+
+pub fn spawn_layer_chunks(
+  mut ew_spawn_chunk: EventWriter<SpawnChunkEvent>,
+  q_roots: Query<(&ChunkedTilemap, &LayeredTilemap), Without<ChunkedTilemapLayer>>,
+  q_layers: Query<&ChunkedTilemap, With<ChunkedTilemapLayer>>,
+){
+  for (root, layered) in q_roots.iter(){
+    for chunk_index in generate_chunk_indexes(root.current_chunk, root.range).into_iter().filter(|&index| root.contains_chunk(index)){
+      for &layer in layered.layers.iter(){
+        if let Ok(tilemap) = q_layers.get(layer){
+          if !tilemap.chunks.contains(&chunk_index) {
+            ew_spawn_chunk.send(SpawnChunkEvent{
+              tilemap_entity: layer,
+              chunk_index,
+            });
+          }
+        }
+      }
+    }
+  }
+}
+
+pub fn despawn_outrange_layer_chunks(
+  mut commands: Commands,
+  teleport_settings: Res<TeleportSettings>,
+  q_roots: Query<(&ChunkedTilemap, &LayeredTilemap), Without<ChunkedTilemapLayer>>,
+  mut q_layers: Query<&mut ChunkedTilemap, With<ChunkedTilemapLayer>>,
+){
+  for (root, layered) in q_roots.iter(){
+    for &layer in layered.layers.iter(){
+      if let Ok(mut tilemap) = q_layers.get_mut(layer){
+        let outrange: Vec<IVec2> = tilemap.chunks.iter().copied()
+          .filter(|&chunk_index| !root.in_range(chunk_index))
+          .take(Teleport::unload_budget(root.teleport, &teleport_settings))
+          .collect();
+        for chunk_index in outrange{
+          debug!("despawning chunk at {:?} of layer {:?}", chunk_index, layer);
+          if let Some(entity) = tilemap.remove_chunk(chunk_index){
+            commands.entity(entity).despawn_recursive();
+          }
+        }
+      }
+    }
+  }
+}
+
+/// Tilemaps (layers) whose generated data a tilemap needs before its own chunks can be generated.
+///
+/// `PrepareChunkEvent`s of a tilemap with this component are held back until every dependency has been filled
+/// for the same chunk index, and then carry that data in `PrepareChunkEvent::dependencies`.
+/// Dependencies must not form a cycle.
+#[derive(Component, Default)]
+pub struct LayerDependencies{
+  pub layers: Vec<Entity>,
+  pub(crate) pending: Vec<(IVec2, Entity)>,
+}
+
+impl LayerDependencies{
+  pub fn new(layers: Vec<Entity>)->LayerDependencies{
+    LayerDependencies{
+      layers,
+      pending: vec![],
+    }
+  }
+}
+
+pub fn prepare_dependent_chunks(
+  mut ew_prepare_chunk: EventWriter<PrepareChunkEvent>,
+  mut q_dependents: Query<(Entity, &ChunkedTilemap, &mut LayerDependencies)>,
+  q_tilemaps: Query<&ChunkedTilemap>,
+){
+  for (tilemap_entity, tilemap, mut dependencies) in q_dependents.iter_mut(){
+    let LayerDependencies{layers, pending} = &mut *dependencies;
+    pending.retain(|&(chunk_index, chunk_entity)|{
+      // chunk was despawned before its dependencies got generated
+      if tilemap.chunk_entities.get(&chunk_index) != Some(&chunk_entity) {
+        return false;
+      }
+      let mut data = vec![];
+      for &layer in layers.iter(){
+        // despawned layers are not waited for
+        if let Ok(layer_tilemap) = q_tilemaps.get(layer){
+          match layer_tilemap.generated.get(&chunk_index){
+            Some(layer_data) => data.push((layer, layer_data.clone())),
+            None => return true,
+          }
+        }
+      }
+      ew_prepare_chunk.send(PrepareChunkEvent{
+        tilemap_entity,
+        chunk_index,
+        chunk_entity,
+        dependencies: data,
+      });
+      false
+    });
+  }
+}
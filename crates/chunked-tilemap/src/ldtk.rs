@@ -0,0 +1,316 @@
+use bevy::{
+  prelude::*,
+  asset::{AssetLoader, LoadContext, LoadedAsset},
+  reflect::TypeUuid,
+  utils::BoxedFuture,
+};
+use std::collections::HashMap;
+use serde::Deserialize;
+
+use crate::{
+  bundle::ChunkAnchor,
+  chunk_data::ChunkData,
+  chunks::{global_tile_index_to_local, local_tile_index_to_global},
+  generation::{ChunkGenerator, GenerationContext},
+};
+
+/// Project made in LDtk, only the parts needed to place the tiles of its levels.
+/// Levels saved in separate files are not supported.
+#[derive(Debug, Clone, Default, Deserialize, TypeUuid)]
+#[uuid = "9a4d6c2b-3e1f-4b7a-8c05-6f2e9d1b4a73"]
+pub struct LdtkProject{
+  #[serde(default)]
+  pub levels: Vec<LdtkLevel>,
+  /// Projects with multiple worlds keep their levels here.
+  #[serde(default)]
+  pub worlds: Vec<LdtkWorld>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LdtkWorld{
+  #[serde(default)]
+  pub levels: Vec<LdtkLevel>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LdtkLevel{
+  pub identifier: String,
+  /// Position in the world, in pixels.
+  pub world_x: i32,
+  pub world_y: i32,
+  #[serde(default)]
+  pub layer_instances: Option<Vec<LdtkLayerInstance>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LdtkLayerInstance{
+  #[serde(rename = "__identifier")]
+  pub identifier: String,
+  #[serde(rename = "__gridSize")]
+  pub grid_size: i32,
+  #[serde(rename = "__pxTotalOffsetX", default)]
+  pub px_offset_x: i32,
+  #[serde(rename = "__pxTotalOffsetY", default)]
+  pub px_offset_y: i32,
+  #[serde(rename = "gridTiles", default)]
+  pub grid_tiles: Vec<LdtkTile>,
+  #[serde(rename = "autoLayerTiles", default)]
+  pub auto_layer_tiles: Vec<LdtkTile>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LdtkTile{
+  /// Position in the layer, in pixels.
+  pub px: [i32; 2],
+  /// Tile id in the tileset of the layer, used as `TileTexture` index.
+  pub t: u32,
+}
+
+impl LdtkProject{
+  pub fn parse(json: &[u8])->Result<LdtkProject, serde_json::Error>{
+    serde_json::from_slice(json)
+  }
+
+  pub fn all_levels(&self)->impl Iterator<Item=&LdtkLevel>{
+    self.levels.iter().chain(self.worlds.iter().flat_map(|world| world.levels.iter()))
+  }
+
+  /// Tiles of every layer named `layer` in every level, by global tile index.
+  /// Cell (0, 0) of the LDtk world lands on `offset`. Tiles drawn later in LDtk cover the earlier ones.
+  pub fn layer_tiles(&self, layer: &str, offset: IVec2)->HashMap<IVec2, u32>{
+    let mut tiles = HashMap::new();
+    for level in self.all_levels(){
+      for instance in level.layer_instances.iter().flatten().filter(|instance| instance.identifier == layer){
+        if instance.grid_size <= 0 {
+          continue;
+        }
+        let origin = IVec2::new(level.world_x + instance.px_offset_x, level.world_y + instance.px_offset_y);
+        for tile in instance.auto_layer_tiles.iter().chain(instance.grid_tiles.iter()){
+          let px = origin + IVec2::new(tile.px[0], tile.px[1]);
+          let cell = IVec2::new(px.x.div_euclid(instance.grid_size), px.y.div_euclid(instance.grid_size));
+          tiles.insert(offset + cell, tile.t);
+        }
+      }
+    }
+    tiles
+  }
+
+  /// Chunk data of a chunk of a `ChunkedTilemap` layer named like the LDtk `layer`, `None` if no level covers it.
+  pub fn chunk_data(&self, layer: &str, offset: IVec2, chunk_index: IVec2, chunk_size: UVec2, anchor: ChunkAnchor)->Option<ChunkData>{
+    LdtkRegion::new(self, layer, offset).chunk_data(chunk_index, chunk_size, anchor)
+  }
+
+  /// Regions for every layer of the project, by identifier.
+  pub fn regions(&self, offset: IVec2)->HashMap<String, LdtkRegion>{
+    let mut regions = HashMap::new();
+    for level in self.all_levels(){
+      for instance in level.layer_instances.iter().flatten(){
+        regions.entry(instance.identifier.clone())
+          .or_insert_with(|| LdtkRegion::new(self, &instance.identifier, offset));
+      }
+    }
+    regions
+  }
+}
+
+/// Tiles of an LDtk layer as a pass of a `GenerationPipeline`, drawn over whatever the previous passes left.
+#[derive(Debug, Clone, Default)]
+pub struct LdtkRegion{
+  pub tiles: HashMap<IVec2, u32>,
+}
+
+impl LdtkRegion{
+  pub fn new(project: &LdtkProject, layer: &str, offset: IVec2)->LdtkRegion{
+    LdtkRegion{tiles: project.layer_tiles(layer, offset)}
+  }
+
+  pub fn chunk_data(&self, chunk_index: IVec2, chunk_size: UVec2, anchor: ChunkAnchor)->Option<ChunkData>{
+    let mut data = ChunkData::new(chunk_size);
+    let mut found = false;
+    for y in 0..chunk_size.y{
+      for x in 0..chunk_size.x{
+        let global_tile_index = local_tile_index_to_global(chunk_index, chunk_size, IVec2::new(x as i32, y as i32), anchor);
+        if let Some(&tile) = self.tiles.get(&global_tile_index) {
+          data.set(UVec2::new(x, y), Some(tile));
+          found = true;
+        }
+      }
+    }
+    found.then_some(data)
+  }
+
+  /// Chunks holding at least one tile of the region.
+  pub fn chunk_indexes(&self, chunk_size: UVec2, anchor: ChunkAnchor)->Vec<IVec2>{
+    let mut chunk_indexes: Vec<IVec2> = self.tiles.keys()
+      .map(|&global_tile_index| global_tile_index_to_local(global_tile_index, chunk_size, anchor).0)
+      .collect();
+    chunk_indexes.sort_by_key(|chunk_index| (chunk_index.y, chunk_index.x));
+    chunk_indexes.dedup();
+    chunk_indexes
+  }
+}
+
+impl ChunkGenerator for LdtkRegion{
+  fn generate(&self, context: &mut GenerationContext){
+    if let Some(data) = self.chunk_data(context.chunk_index, context.chunk_size, context.anchor) {
+      for (local_tile_index, tile) in data.iter(){
+        context.data.set(local_tile_index, Some(tile));
+      }
+    }
+  }
+}
+
+#[derive(Default)]
+pub struct LdtkProjectLoader;
+
+impl AssetLoader for LdtkProjectLoader{
+  fn load<'a>(
+    &'a self,
+    bytes: &'a [u8],
+    load_context: &'a mut LoadContext,
+  )->BoxedFuture<'a, Result<(), bevy::asset::Error>>{
+    Box::pin(async move {
+      let project = LdtkProject::parse(bytes)?;
+      load_context.set_default_asset(LoadedAsset::new(project));
+      Ok(())
+    })
+  }
+
+  fn extensions(&self)->&[&str]{
+    &["ldtk"]
+  }
+}
+
+/// Loads `LdtkProject` assets, whose `regions` go into the `GenerationPipeline`s of the matching layers.
+pub struct LdtkPlugin;
+
+impl Plugin for LdtkPlugin{
+  fn build(&self, app: &mut App){
+    app
+      .add_asset::<LdtkProject>()
+      .init_asset_loader::<LdtkProjectLoader>();
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use crate::{bundle::ChunkAnchor, chunk_data::ChunkData, generation::{GenerationContext, GenerationPipeline}};
+  use super::LdtkProject;
+
+  const PROJECT: &str = r#"{
+    "jsonVersion": "1.1.3",
+    "defs": {"layers": [], "tilesets": []},
+    "levels": [
+      {
+        "identifier": "Start",
+        "worldX": 0,
+        "worldY": 0,
+        "pxWid": 32,
+        "pxHei": 16,
+        "layerInstances": [
+          {
+            "__identifier": "Trees",
+            "__type": "Tiles",
+            "__gridSize": 8,
+            "__pxTotalOffsetX": 0,
+            "__pxTotalOffsetY": 0,
+            "gridTiles": [{"px": [8, 0], "src": [16, 0], "f": 0, "t": 9, "d": [1]}],
+            "autoLayerTiles": []
+          },
+          {
+            "__identifier": "Ground",
+            "__type": "AutoLayer",
+            "__gridSize": 8,
+            "__pxTotalOffsetX": 0,
+            "__pxTotalOffsetY": 0,
+            "intGridCsv": [1, 1, 1, 1, 1, 1, 1, 1],
+            "gridTiles": [],
+            "autoLayerTiles": [
+              {"px": [0, 0], "t": 1},
+              {"px": [8, 0], "t": 2},
+              {"px": [0, 8], "t": 3},
+              {"px": [0, 8], "t": 4}
+            ]
+          }
+        ]
+      },
+      {
+        "identifier": "West",
+        "worldX": -16,
+        "worldY": 8,
+        "layerInstances": [
+          {
+            "__identifier": "Ground",
+            "__gridSize": 8,
+            "gridTiles": [{"px": [0, 0], "t": 5}]
+          }
+        ]
+      },
+      {
+        "identifier": "External",
+        "worldX": 64,
+        "worldY": 0,
+        "externalRelPath": "project/External.ldtkl",
+        "layerInstances": null
+      }
+    ]
+  }"#;
+
+  #[test]
+  fn layer_tiles_test(){
+    let project = LdtkProject::parse(PROJECT.as_bytes()).unwrap();
+    let ground = project.layer_tiles("Ground", IVec2::ZERO);
+    assert_eq!(ground.len(), 4);
+    assert_eq!(ground[&IVec2::new(0, 0)], 1);
+    assert_eq!(ground[&IVec2::new(1, 0)], 2);
+    // stacked tiles, the last one is on top
+    assert_eq!(ground[&IVec2::new(0, 1)], 4);
+    assert_eq!(ground[&IVec2::new(-2, 1)], 5);
+    let trees = project.layer_tiles("Trees", IVec2::new(100, -50));
+    assert_eq!(trees.into_iter().collect::<Vec<_>>(), vec![(IVec2::new(101, -50), 9)]);
+    assert!(project.layer_tiles("Clouds", IVec2::ZERO).is_empty());
+  }
+
+  #[test]
+  fn worlds_test(){
+    let project = LdtkProject::parse(r#"{"worlds": [{"levels": [{"identifier": "A", "worldX": 8, "worldY": 0, "layerInstances": [
+      {"__identifier": "Ground", "__gridSize": 8, "gridTiles": [{"px": [0, 0], "t": 7}]}
+    ]}]}]}"#.as_bytes()).unwrap();
+    assert_eq!(project.layer_tiles("Ground", IVec2::ZERO).get(&IVec2::new(1, 0)), Some(&7));
+  }
+
+  #[test]
+  fn chunk_data_test(){
+    let project = LdtkProject::parse(PROJECT.as_bytes()).unwrap();
+    let chunk_size = UVec2::new(4, 4);
+    let regions = project.regions(IVec2::new(10, 10));
+    assert_eq!(regions.len(), 2);
+    let ground = &regions["Ground"];
+    let chunk_indexes = ground.chunk_indexes(chunk_size, ChunkAnchor::Corner);
+    let tiles: usize = chunk_indexes.iter()
+      .map(|&chunk_index| ground.chunk_data(chunk_index, chunk_size, ChunkAnchor::Corner).unwrap().iter().count())
+      .sum();
+    assert_eq!(tiles, 4);
+    assert_eq!(project.chunk_data("Ground", IVec2::new(10, 10), IVec2::new(50, 50), chunk_size, ChunkAnchor::Corner), None);
+  }
+
+  #[test]
+  fn region_pass_test(){
+    let project = LdtkProject::parse(PROJECT.as_bytes()).unwrap();
+    let offset = IVec2::new(-3, 7);
+    let chunk_size = UVec2::new(4, 4);
+    let region = project.regions(offset).remove("Ground").unwrap();
+    let mut pipeline = GenerationPipeline::new()
+      .with_pass(|context: &mut GenerationContext| context.data = ChunkData::filled(context.chunk_size, 50))
+      .with_pass(region.clone());
+    let mut authored = 0;
+    for chunk_index in region.chunk_indexes(chunk_size, ChunkAnchor::Center){
+      let data = pipeline.generate(chunk_index, chunk_size, ChunkAnchor::Center);
+      assert_eq!(data.iter().count(), 16);
+      authored += data.iter().filter(|&(_, tile)| tile != 50).count();
+    }
+    assert_eq!(authored, 4);
+  }
+}
@@ -4,14 +4,41 @@ pub mod spawn_around;
 pub mod despawn_outrange;
 pub mod bundle;
 pub mod fill_chunk;
+pub mod tiles;
+pub mod geometry;
+pub mod picking;
+pub mod raycast;
+pub mod chunk_data;
+pub mod generation;
+pub mod layers;
+pub mod random;
+pub mod noise;
+pub mod biome;
+pub mod rules;
+pub mod variants;
+pub mod autotile;
+pub mod wfc;
+pub mod caves;
+pub mod networks;
+pub mod stamps;
+pub mod tiled;
+pub mod ldtk;
+pub mod authored;
+pub mod floating_origin;
+pub mod teleport;
 
-use bevy::{prelude::{Plugin, Vec2, IVec2, Component, CoreStage, ParallelSystemDescriptorCoercion}};
+use bevy::{prelude::{Plugin, IVec2, Component, CoreStage, ParallelSystemDescriptorCoercion}};
 use bevy_ecs_tilemap::TilemapPlugin;
-use chunks::{update_current_chunk, nest_chunks};
+use chunks::{update_current_chunk, nest_chunks, register_tiles};
 use despawn_outrange::despawn_outrange_chunks;
 use spawn_chunk::{SpawnChunkEvent, spawn_chunk, PrepareChunkEvent};
 use spawn_around::spawn_chunks_around_current;
 use fill_chunk::{fill_chunk, FillChunkEvent};
+use generation::generate_chunks;
+use tiles::{SetTileEvent, set_tiles};
+use autotile::{mark_autotiles, apply_autotiles};
+use layers::{prepare_dependent_chunks, register_layers, sync_layers, spawn_layer_chunks, despawn_outrange_layer_chunks};
+use teleport::{TeleportSettings, TeleportEvent, StreamingSettled, teleport_tilemaps, settle_teleports};
 
 
 
@@ -23,45 +50,32 @@ impl Plugin for ChunkedTilemapPlugin{
       .add_event::<SpawnChunkEvent>()
       .add_event::<PrepareChunkEvent>()
       .add_event::<FillChunkEvent>()
+      .add_event::<SetTileEvent>()
+      .add_event::<TeleportEvent>()
+      .add_event::<StreamingSettled>()
+      .init_resource::<TeleportSettings>()
       .add_plugin(TilemapPlugin)
+      .add_system(teleport_tilemaps.before(update_current_chunk))
       .add_system(update_current_chunk)
+      .add_system(register_layers)
+      .add_system(sync_layers.after(update_current_chunk))
       .add_system(spawn_chunks_around_current)
+      .add_system(spawn_layer_chunks.after(sync_layers))
       .add_system(spawn_chunk)
+      .add_system(generate_chunks.before(fill_chunk))
       .add_system(fill_chunk)
+      .add_system(prepare_dependent_chunks.after(fill_chunk))
       .add_system(nest_chunks.after(fill_chunk))
-      .add_system(despawn_outrange_chunks);
+      .add_system(register_tiles.after(fill_chunk))
+      .add_system(set_tiles.after(register_tiles))
+      .add_system(mark_autotiles.after(fill_chunk).after(set_tiles))
+      .add_system(apply_autotiles.after(mark_autotiles).after(register_tiles))
+      .add_system(despawn_outrange_chunks)
+      .add_system(despawn_outrange_layer_chunks.after(sync_layers))
+      .add_system(settle_teleports.after(fill_chunk).after(despawn_outrange_chunks).after(despawn_outrange_layer_chunks));
   }
 }
 
 
 #[derive(Component)]
 pub struct TilemapChunk(pub IVec2);
-
-pub fn is_point_in_rect(
-  rect_pos: Vec2,
-  rect_size: Vec2,
-  point: Vec2
-)->bool{
-  let max = rect_pos+rect_size/2.;
-  
-  // println!("max {max}");
-  // println!("max.dot(max) {}", max.dot(max));
-  // println!("max.dot(point) {}", max.dot((point+rect_pos).abs()));
-
-  // println!("max.dot(max) {}", min.dot(max));
-  // println!("max.dot(point) {}", max.dot(point));
-  // true
-  max.dot(max)/2.>=max.dot((point+rect_pos).abs())
-  // let a = rect_pos-rect_size/2.;
-  // let b = a*Vec2::new(1., -1.);
-  // let c = rect_pos+rect_size/2.;
-  // let ab = a-b;
-  // let bc = c-c;
-  // let bm = point-b;
-
-  // println!("a {a}, b {b}, c {c}");
-
-  // println!("{ab}, {cb}");
-
-  // 0.<=bc.dot(bm) && bc.dot(bm)<=bc.dot(bc) && ab.dot(bm)<=ab.dot(ab)
-}
\ No newline at end of file
@@ -1,7 +1,7 @@
 use bevy::{prelude::*, DefaultPlugins, sprite::MaterialMesh2dBundle, input::mouse::MouseMotion, asset::AssetServerSettings};
 use bevy_ecs_tilemap::{tiles::{TilePos, TileTexture, TileBundle}};
 use bevy_editor_pls::EditorPlugin;
-use chunked_tilemap::{ChunkedTilemapPlugin, bundle::{ChunkedTilemapBundle, ChunkedTilemap}, spawn_chunk::{PrepareChunkEvent, SpawnChunkEvent}, fill_chunk::FillChunkEvent};
+use chunked_tilemap::{ChunkedTilemapPlugin, bundle::{ChunkedTilemapBundle, ChunkedTilemap}, spawn_chunk::{PrepareChunkEvent, SpawnChunkEvent}, fill_chunk::FillChunkEvent, picking::{TilePickingPlugin, TilePickingCamera, TileClicked}};
 
 const CHUNK_SIZE: u32 = 15;
 const TILE_SIZE: f32 = 32.;
@@ -22,9 +22,11 @@ fn main() {
   })
   .add_plugins(DefaultPlugins)
   .add_plugin(ChunkedTilemapPlugin)
+  .add_plugin(TilePickingPlugin)
   .add_plugin(EditorPlugin)
   .add_startup_system(startup)
   .add_system(move_camera)
+  .add_system(log_clicked_tiles)
   .add_system_to_stage(CoreStage::PreUpdate, init_ground_chunk)
   .add_system_to_stage(CoreStage::PreUpdate, init_trees_chunk);
     
@@ -44,7 +46,7 @@ fn startup(
   mut tilemap_layers: ResMut<TilemapLayers>,
   asset_server: Res<AssetServer>,
 ){
-  commands.spawn_bundle(Camera2dBundle::default()).insert(DefaultCamera);
+  commands.spawn_bundle(Camera2dBundle::default()).insert(DefaultCamera).insert(TilePickingCamera);
   commands.spawn_bundle(MaterialMesh2dBundle {
     mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
     transform: Transform::default().with_scale(Vec3::splat(TILE_SIZE*(CHUNK_SIZE as f32))).with_translation(Vec3::new(0., 0., 20.)),
@@ -105,6 +107,17 @@ fn move_camera(
   };
 }
 
+fn log_clicked_tiles(
+  mut er_tile_clicked: EventReader<TileClicked>,
+){
+  for event in er_tile_clicked.iter(){
+    info!(
+      "{:?} clicked tile {} (chunk {}, local {}) of {:?}: {:?}",
+      event.button, event.pick.global_tile_index, event.pick.chunk_index, event.pick.local_tile_index, event.pick.tilemap_entity, event.pick.tile
+    );
+  }
+}
+
 fn init_ground_chunk(
   mut er_prepare_chunk: EventReader<PrepareChunkEvent>,
   mut ew_fill_chunk: EventWriter<FillChunkEvent>,
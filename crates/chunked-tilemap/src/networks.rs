@@ -0,0 +1,620 @@
+use bevy::{prelude::*, utils::HashMap};
+use rand::Rng;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+  bundle::{ChunkAnchor, ChunkBounds},
+  chunks::local_tile_index_to_global,
+  generation::{ChunkGenerator, GenerationContext},
+  random::SeededRng,
+  tiles::Direction,
+};
+
+const RIVERS: u64 = 0x7269_7665_7273;
+const ROADS: u64 = 0x0072_6f61_6473;
+const MAX_CACHED_REGIONS: usize = 1024;
+
+/// 4-connected line of tiles, by global tile index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TilePath{
+  pub tiles: Vec<IVec2>,
+}
+
+impl TilePath{
+  /// Straight line between two tiles.
+  pub fn line(from: IVec2, to: IVec2)->TilePath{
+    let delta = to - from;
+    let (step, dx, dy) = (delta.signum(), delta.x.abs() as i64, delta.y.abs() as i64);
+    let mut tiles = vec![from];
+    let (mut current, mut x, mut y) = (from, 0, 0);
+    while x < dx || y < dy {
+      if (1 + 2*x)*dy < (1 + 2*y)*dx {
+        current.x += step.x;
+        x += 1;
+      } else {
+        current.y += step.y;
+        y += 1;
+      }
+      tiles.push(current);
+    }
+    TilePath{tiles}
+  }
+
+  /// Smallest and largest tile index of the path.
+  pub fn bounds(&self)->Option<(IVec2, IVec2)>{
+    let first = *self.tiles.first()?;
+    Some(self.tiles.iter().fold((first, first), |(min, max), &tile| (min.min(tile), max.max(tile))))
+  }
+
+  /// Draws the path into the generated chunk with a square brush `width` tiles wide.
+  pub fn draw(&self, context: &mut GenerationContext, width: i32, tile: u32){
+    self.draw_moved(context, IVec2::ZERO, width, tile);
+  }
+
+  /// Draws the path moved by `offset` tiles, i.e. into another repetition of a wrapping world.
+  fn draw_moved(&self, context: &mut GenerationContext, offset: IVec2, width: i32, tile: u32){
+    let (low, high) = (-(width - 1)/2, width/2);
+    let (chunk_min, chunk_max) = chunk_bounds(context);
+    match self.bounds(){
+      Some((min, max)) if (max + offset + high).cmpge(chunk_min).all() && (min + offset + low).cmple(chunk_max).all() => {}
+      _ => return
+    }
+    for &path_tile in self.tiles.iter(){
+      for y in low..=high{
+        for x in low..=high{
+          context.set_global(path_tile + offset + IVec2::new(x, y), Some(tile));
+        }
+      }
+    }
+  }
+}
+
+/// Global tiles of the first repetition of a wrapping world, from `min` and `size` tiles large along the wrapped
+/// axes. `size` is zero along axes which don't wrap, endless worlds are all zero.
+///
+/// Region paths of a wrapping world only start in its first repetition and get drawn moved into the others,
+/// so they run across the wrap seam.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+struct WrappedTiles{
+  min: IVec2,
+  size: IVec2,
+}
+
+impl WrappedTiles{
+  fn new(bounds: Option<ChunkBounds>, chunk_size: UVec2, anchor: ChunkAnchor)->WrappedTiles{
+    let bounds = match bounds.filter(|bounds| bounds.wrap.any()){
+      Some(bounds) => bounds,
+      None => return WrappedTiles::default()
+    };
+    let size = bounds.size()*chunk_size.as_ivec2();
+    WrappedTiles{
+      min: local_tile_index_to_global(bounds.min, chunk_size, IVec2::new(0, chunk_size.y as i32 - 1), anchor),
+      size: IVec2::new(if bounds.wrap.x { size.x } else { 0 }, if bounds.wrap.y { size.y } else { 0 }),
+    }
+  }
+
+  fn of(context: &GenerationContext)->WrappedTiles{
+    WrappedTiles::new(context.bounds(), context.chunk_size, context.anchor)
+  }
+
+  /// North-west tile of region `(0, 0)`, regions start at the edge of the world along wrapped axes.
+  fn region_origin(&self)->IVec2{
+    IVec2::new(if self.size.x == 0 { 0 } else { self.min.x }, if self.size.y == 0 { 0 } else { self.min.y })
+  }
+
+  /// Region holding a tile.
+  fn region(&self, tile: IVec2, region_size: i32)->IVec2{
+    let tile = tile - self.region_origin();
+    IVec2::new(tile.x.div_euclid(region_size), tile.y.div_euclid(region_size))
+  }
+
+  /// Whether a tile is in the first repetition.
+  fn contains(&self, tile: IVec2)->bool{
+    let inside = |tile: i32, min: i32, size: i32| size == 0 || (min..min + size).contains(&tile);
+    inside(tile.x, self.min.x, self.size.x) && inside(tile.y, self.min.y, self.size.y)
+  }
+
+  /// Offsets of the repetitions overlapping the tiles from `min` to `max`.
+  fn offsets(&self, min: IVec2, max: IVec2)->Vec<IVec2>{
+    let repetitions = |min: i32, max: i32, start: i32, size: i32|{
+      if size == 0 { 0..=0 } else { (min - start).div_euclid(size)..=(max - start).div_euclid(size) }
+    };
+    let ys = repetitions(min.y, max.y, self.min.y, self.size.y);
+    repetitions(min.x, max.x, self.min.x, self.size.x)
+      .flat_map(|x| ys.clone().map(move |y| IVec2::new(x, y)*self.size))
+      .collect()
+  }
+
+  /// Tiles from `min` to `max` cut to the first repetition.
+  fn clip(&self, min: IVec2, max: IVec2)->(IVec2, IVec2){
+    let clip = |min: i32, max: i32, start: i32, size: i32| if size == 0 { (min, max) } else { (min.max(start), max.min(start + size - 1)) };
+    let (x, y) = (clip(min.x, max.x, self.min.x, self.size.x), clip(min.y, max.y, self.min.y, self.size.y));
+    (IVec2::new(x.0, y.0), IVec2::new(x.1, y.1))
+  }
+
+  /// Region of the first repetition a region stands for, and the offset of the repetition it is in.
+  /// The last region is cut short if the size of the world is not a multiple of `region_size`.
+  fn wrap_region(&self, region: IVec2, region_size: i32)->(IVec2, IVec2){
+    let wrap = |region: i32, size: i32|{
+      if size == 0 {
+        return (region, 0);
+      }
+      let count = (size - 1).div_euclid(region_size) + 1;
+      (region.rem_euclid(count), region.div_euclid(count)*size)
+    };
+    let (x, y) = (wrap(region.x, self.size.x), wrap(region.y, self.size.y));
+    (IVec2::new(x.0, y.0), IVec2::new(x.1, y.1))
+  }
+}
+
+/// Smallest and largest global tile index of the generated chunk.
+fn chunk_bounds(context: &GenerationContext)->(IVec2, IVec2){
+  let min = context.local_to_global(UVec2::new(0, context.chunk_size.y - 1));
+  (min, min + context.chunk_size.as_ivec2() - IVec2::ONE)
+}
+
+/// Regions of `region_size` tiles within `reach` tiles of the generated chunk, with the offset their paths are
+/// drawn at. Regions of a wrapping world are those of its first repetition, see `WrappedTiles`.
+fn regions_around(context: &GenerationContext, world: WrappedTiles, region_size: i32, reach: i32)->Vec<(IVec2, IVec2)>{
+  let (min, max) = chunk_bounds(context);
+  world.offsets(min - reach, max + reach).into_iter().flat_map(|offset|{
+    let (min, max) = world.clip(min - reach - offset, max + reach - offset);
+    let (from, to) = (world.region(min, region_size), world.region(max, region_size));
+    (from.y..=to.y).flat_map(move |y| (from.x..=to.x).map(move |x| (IVec2::new(x, y), offset)))
+  }).collect()
+}
+
+/// World seed, layer id, wrapped tiles and region index.
+type RegionKey = (u64, u64, WrappedTiles, IVec2);
+
+/// Paths of regions, computed once and shared by every chunk they cross.
+#[derive(Default)]
+struct RegionCache{
+  paths: Mutex<HashMap<RegionKey, Arc<Vec<TilePath>>>>,
+}
+
+impl RegionCache{
+  /// Paths of a region, traced if they are not cached. A full cache drops the half farthest from `region`.
+  fn get(&self, key: RegionKey, trace: impl FnOnce()->Vec<TilePath>)->Arc<Vec<TilePath>>{
+    let mut paths = self.paths.lock().unwrap();
+    if let Some(region_paths) = paths.get(&key) {
+      return region_paths.clone();
+    }
+    let (world_seed, layer_id, world, region) = key;
+    if paths.len() >= MAX_CACHED_REGIONS {
+      // regions of other seeds, layers and worlds go first
+      let distance = |&(seed, layer, cached_world, cached): &RegionKey| match seed == world_seed && layer == layer_id && cached_world == world{
+        true => (cached - region).abs().max_element(),
+        false => i32::MAX,
+      };
+      let mut distances: Vec<i32> = paths.keys().map(distance).collect();
+      distances.sort_unstable();
+      let farthest = distances[MAX_CACHED_REGIONS/2];
+      paths.retain(|key, _| distance(key) < farthest);
+    }
+    let region_paths = Arc::new(trace());
+    paths.insert(key, region_paths.clone());
+    region_paths
+  }
+}
+
+/// Rivers running downhill over a height field, as a pass of a `GenerationPipeline`.
+///
+/// The world is split into square regions of `region_size` tiles, each one gets `sources` random spots and those
+/// at least `source_height` high start a river. A river keeps flowing to the lowest of its neighbors until it drops
+/// below `sea_level`, ends up in a pit or gets `max_length` tiles long. Rivers of a region are traced once, when
+/// the first chunk within `max_length` tiles of it gets generated, and drawn into every chunk they cross.
+/// In a wrapping world, regions are laid out from its edge and rivers flow on across the wrap seam.
+pub struct RiverGenerator{
+  pub water: u32,
+  pub width: i32,
+  pub region_size: i32,
+  pub sources: u32,
+  pub source_height: f64,
+  pub sea_level: f64,
+  pub max_length: i32,
+  height: Box<dyn Fn(u64, IVec2)->f64 + Send + Sync>,
+  cache: RegionCache,
+}
+
+impl RiverGenerator{
+  /// `height` gives the height of a global tile for a world seed, in about `[-1, 1]`.
+  pub fn new(height: impl Fn(u64, IVec2)->f64 + Send + Sync + 'static, water: u32)->RiverGenerator{
+    RiverGenerator{
+      water,
+      width: 1,
+      region_size: 64,
+      sources: 2,
+      source_height: 0.3,
+      sea_level: -0.2,
+      max_length: 256,
+      height: Box::new(height),
+      cache: RegionCache::default(),
+    }
+  }
+
+  pub fn with_width(mut self, width: i32)->RiverGenerator{
+    self.width = width;
+    self
+  }
+
+  pub fn with_region_size(mut self, region_size: i32)->RiverGenerator{
+    self.region_size = region_size.max(1);
+    self
+  }
+
+  pub fn with_sources(mut self, sources: u32, source_height: f64)->RiverGenerator{
+    self.sources = sources;
+    self.source_height = source_height;
+    self
+  }
+
+  pub fn with_sea_level(mut self, sea_level: f64)->RiverGenerator{
+    self.sea_level = sea_level;
+    self
+  }
+
+  pub fn with_max_length(mut self, max_length: i32)->RiverGenerator{
+    self.max_length = max_length;
+    self
+  }
+
+  pub fn height(&self, world_seed: u64, global_tile_index: IVec2)->f64{
+    (self.height)(world_seed, global_tile_index)
+  }
+
+  /// Rivers starting in a region of an endless world.
+  pub fn rivers(&self, world_seed: u64, layer_id: u64, region: IVec2)->Arc<Vec<TilePath>>{
+    self.wrapped_rivers(world_seed, layer_id, WrappedTiles::default(), region)
+  }
+
+  /// Rivers starting in a region of the first repetition of a world.
+  fn wrapped_rivers(&self, world_seed: u64, layer_id: u64, world: WrappedTiles, region: IVec2)->Arc<Vec<TilePath>>{
+    self.cache.get((world_seed, layer_id, world, region), ||{
+      let mut rng = SeededRng::for_chunk(world_seed, layer_id ^ RIVERS, region);
+      (0..self.sources)
+        .map(|_| world.region_origin() + region*self.region_size + IVec2::new(rng.gen_range(0..self.region_size), rng.gen_range(0..self.region_size)))
+        .filter(|&source| world.contains(source) && self.height(world_seed, source) >= self.source_height)
+        .map(|source| self.trace(world_seed, source))
+        .collect()
+    })
+  }
+
+  fn trace(&self, world_seed: u64, source: IVec2)->TilePath{
+    let mut tiles = vec![source];
+    let (mut current, mut height) = (source, self.height(world_seed, source));
+    while height >= self.sea_level && (tiles.len() as i32) < self.max_length {
+      let lowest = Direction::CARDINAL.iter()
+        .map(|direction| current + direction.offset())
+        .map(|neighbor| (neighbor, self.height(world_seed, neighbor)))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+      match lowest{
+        Some((neighbor, neighbor_height)) if neighbor_height < height => {
+          tiles.push(neighbor);
+          current = neighbor;
+          height = neighbor_height;
+        }
+        _ => break
+      }
+    }
+    TilePath{tiles}
+  }
+}
+
+impl ChunkGenerator for RiverGenerator{
+  fn generate(&self, context: &mut GenerationContext){
+    let world = WrappedTiles::of(context);
+    for (region, offset) in regions_around(context, world, self.region_size, self.max_length + self.width){
+      for river in self.wrapped_rivers(context.world_seed, context.layer_id, world, region).iter(){
+        river.draw_moved(context, offset, self.width, self.water);
+      }
+    }
+  }
+}
+
+/// Roads between points of interest, as a pass of a `GenerationPipeline`.
+///
+/// Every square region of `region_size` tiles gets a point of interest with a chance of `density`, away from the
+/// region's edges. Points of regions next to each other get joined by straight roads, traced once per region and
+/// drawn into every chunk they cross. In a wrapping world, regions are laid out from its edge and the last ones
+/// get joined to the first ones across the wrap seam.
+pub struct RoadGenerator{
+  pub road: u32,
+  pub width: i32,
+  pub region_size: i32,
+  pub density: f64,
+  cache: RegionCache,
+}
+
+impl RoadGenerator{
+  pub fn new(road: u32)->RoadGenerator{
+    RoadGenerator{
+      road,
+      width: 1,
+      region_size: 48,
+      density: 0.7,
+      cache: RegionCache::default(),
+    }
+  }
+
+  pub fn with_width(mut self, width: i32)->RoadGenerator{
+    self.width = width;
+    self
+  }
+
+  pub fn with_region_size(mut self, region_size: i32)->RoadGenerator{
+    self.region_size = region_size.max(1);
+    self
+  }
+
+  pub fn with_density(mut self, density: f64)->RoadGenerator{
+    self.density = density;
+    self
+  }
+
+  /// Point of interest of a region of an endless world.
+  pub fn point_of_interest(&self, world_seed: u64, layer_id: u64, region: IVec2)->Option<IVec2>{
+    self.wrapped_point_of_interest(world_seed, layer_id, WrappedTiles::default(), region)
+  }
+
+  /// Point of interest of any region of a world, the one of the region it stands for in the first repetition
+  /// moved into its own repetition.
+  fn wrapped_point_of_interest(&self, world_seed: u64, layer_id: u64, world: WrappedTiles, region: IVec2)->Option<IVec2>{
+    let (region, offset) = world.wrap_region(region, self.region_size);
+    let mut rng = SeededRng::for_chunk(world_seed, layer_id ^ ROADS, region);
+    if !rng.gen_bool(self.density.clamp(0., 1.)) {
+      return None;
+    }
+    let margin = self.region_size/4;
+    let position = IVec2::new(rng.gen_range(margin..self.region_size - margin), rng.gen_range(margin..self.region_size - margin));
+    let point = world.region_origin() + region*self.region_size + position;
+    // the last region, if cut short by the wrap seam, only gets a point on its side of it
+    world.contains(point).then_some(point + offset)
+  }
+
+  /// Roads from the point of interest of a region of an endless world to those of its east and south neighbors.
+  pub fn roads(&self, world_seed: u64, layer_id: u64, region: IVec2)->Arc<Vec<TilePath>>{
+    self.wrapped_roads(world_seed, layer_id, WrappedTiles::default(), region)
+  }
+
+  /// Roads of a region of the first repetition of a world.
+  fn wrapped_roads(&self, world_seed: u64, layer_id: u64, world: WrappedTiles, region: IVec2)->Arc<Vec<TilePath>>{
+    self.cache.get((world_seed, layer_id, world, region), ||{
+      let from = match self.wrapped_point_of_interest(world_seed, layer_id, world, region){
+        Some(from) => from,
+        None => return vec![]
+      };
+      [Direction::East, Direction::South].iter()
+        .filter_map(|direction| self.wrapped_point_of_interest(world_seed, layer_id, world, region + direction.offset()))
+        .map(|to| TilePath::line(from, to))
+        .collect()
+    })
+  }
+}
+
+impl ChunkGenerator for RoadGenerator{
+  fn generate(&self, context: &mut GenerationContext){
+    // a road stays within its region and the neighbor it leads to
+    let world = WrappedTiles::of(context);
+    for (region, offset) in regions_around(context, world, self.region_size, self.region_size + self.width){
+      for road in self.wrapped_roads(context.world_seed, context.layer_id, world, region).iter(){
+        road.draw_moved(context, offset, self.width, self.road);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::{prelude::*, utils::HashSet};
+  use rstest::rstest;
+  use crate::{
+    bundle::{ChunkAnchor, ChunkBounds},
+    chunks::{local_tile_index_to_global, wrap_global_tile_index},
+    generation::{ChunkGenerator, GenerationPipeline},
+    noise::{Noise, NoiseFn},
+    random::layer_id,
+    spawn_around::generate_chunk_indexes,
+  };
+  use super::{MAX_CACHED_REGIONS, RegionCache, RiverGenerator, RoadGenerator, TilePath, WrappedTiles};
+
+  const WATER: u32 = 1;
+  const ROAD: u32 = 2;
+  const CHUNK_SIZE: UVec2 = UVec2{x: 8, y: 8};
+  const WORLD_SEED: u64 = 11;
+
+  fn is_connected(path: &TilePath)->bool{
+    path.tiles.windows(2).all(|pair| (pair[1] - pair[0]).abs().to_array().iter().sum::<i32>() == 1)
+  }
+
+  /// Tiles set by a generator in the chunks around the origin, each generated by a pipeline of its own.
+  fn generated_tiles<G: ChunkGenerator>(generator: impl Fn()->G, tile: u32)->(HashSet<IVec2>, Vec<IVec2>){
+    let mut tiles = HashSet::default();
+    let mut area = vec![];
+    for chunk_index in generate_chunk_indexes(IVec2::ZERO, 2){
+      let mut pipeline = GenerationPipeline::new()
+        .with_seed(WORLD_SEED, layer_id("ground"))
+        .with_pass(generator());
+      for (local_tile_index, placed) in pipeline.generate(chunk_index, CHUNK_SIZE, ChunkAnchor::Center).iter(){
+        assert_eq!(placed, tile);
+        tiles.insert(local_tile_index_to_global(chunk_index, CHUNK_SIZE, local_tile_index.as_ivec2(), ChunkAnchor::Center));
+      }
+      for y in 0..CHUNK_SIZE.y as i32{
+        for x in 0..CHUNK_SIZE.x as i32{
+          area.push(local_tile_index_to_global(chunk_index, CHUNK_SIZE, IVec2::new(x, y), ChunkAnchor::Center));
+        }
+      }
+    }
+    (tiles, area)
+  }
+
+  #[test]
+  fn region_cache_keeps_the_nearest_regions_test(){
+    let cache = RegionCache::default();
+    let world = WrappedTiles::default();
+    cache.get((WORLD_SEED, 1, world, IVec2::ZERO), Vec::new);
+    for x in 1..MAX_CACHED_REGIONS as i32{
+      cache.get((WORLD_SEED, 0, world, IVec2::new(x, 0)), Vec::new);
+    }
+    cache.get((WORLD_SEED, 0, world, IVec2::new(-1, 0)), Vec::new);
+    {
+      let paths = cache.paths.lock().unwrap();
+      assert!(paths.len() <= MAX_CACHED_REGIONS/2 + 1);
+      assert!(!paths.contains_key(&(WORLD_SEED, 1, world, IVec2::ZERO)));
+      assert!(!paths.contains_key(&(WORLD_SEED, 0, world, IVec2::new(MAX_CACHED_REGIONS as i32 - 1, 0))));
+    }
+    // regions around the one asked for last are not traced again
+    let traced = cache.get((WORLD_SEED, 0, world, IVec2::new(3, 0)), || vec![TilePath::line(IVec2::ZERO, IVec2::X)]);
+    assert!(traced.is_empty());
+  }
+
+  #[rstest]
+  #[case((0, 0), (3, -2))]
+  #[case((5, 5), (5, 9))]
+  #[case((-4, 1), (-10, 1))]
+  #[case((2, 3), (2, 3))]
+  fn line_test(
+    #[case] from: (i32, i32),
+    #[case] to: (i32, i32),
+  ){
+    let (from, to) = (IVec2::new(from.0, from.1), IVec2::new(to.0, to.1));
+    let line = TilePath::line(from, to);
+    assert_eq!(line.tiles.first(), Some(&from));
+    assert_eq!(line.tiles.last(), Some(&to));
+    assert_eq!(line.tiles.len() as i32, (to - from).abs().x + (to - from).abs().y + 1);
+    assert!(is_connected(&line));
+  }
+
+  #[test]
+  fn rivers_flow_downhill_test(){
+    let rivers = RiverGenerator::new(|seed, tile| Noise::perlin(seed).fbm(3).get(tile.x as f64*0.03, tile.y as f64*0.03), WATER)
+      .with_region_size(32)
+      .with_sources(4, 0.1);
+    let paths: Vec<TilePath> = generate_chunk_indexes(IVec2::ZERO, 2).into_iter()
+      .flat_map(|region| rivers.rivers(WORLD_SEED, 0, region).to_vec())
+      .collect();
+    assert!(paths.iter().any(|river| river.tiles.len() > 3));
+    for river in paths.iter(){
+      let heights: Vec<f64> = river.tiles.iter().map(|&tile| rivers.height(WORLD_SEED, tile)).collect();
+      assert!(heights[0] >= 0.1);
+      assert!(heights.windows(2).all(|pair| pair[1] < pair[0]));
+      assert!(river.tiles.len() as i32 <= rivers.max_length);
+      assert!(is_connected(river));
+    }
+  }
+
+  #[test]
+  fn rivers_cross_chunks_test(){
+    // a slope going down to the east, rivers run straight east until they reach the sea
+    let rivers = ||{
+      RiverGenerator::new(|_, tile| -0.01*tile.x as f64, WATER)
+        .with_region_size(16)
+        .with_sources(1, 0.05)
+        .with_sea_level(-0.15)
+        .with_max_length(64)
+    };
+    let (tiles, area) = generated_tiles(rivers, WATER);
+    let expected: HashSet<IVec2> = (-10..10).flat_map(|y| (-10..10).map(move |x| IVec2::new(x, y)))
+      .flat_map(|region| rivers().rivers(WORLD_SEED, layer_id("ground"), region).to_vec())
+      .flat_map(|river| river.tiles)
+      .filter(|tile| area.contains(tile))
+      .collect();
+    assert_eq!(tiles, expected);
+    // rivers start west of x = -5 and end past x = 15, across several chunks
+    let river_rows: HashSet<i32> = tiles.iter().map(|tile| tile.y).collect();
+    assert!(river_rows.iter().any(|&y| (-16..=16).all(|x| tiles.contains(&IVec2::new(x, y)))));
+  }
+
+  #[test]
+  fn roads_connect_points_of_interest_test(){
+    let roads = RoadGenerator::new(ROAD).with_region_size(12).with_density(1.);
+    for region in generate_chunk_indexes(IVec2::ZERO, 2){
+      let paths = roads.roads(WORLD_SEED, 0, region);
+      assert_eq!(paths.len(), 2);
+      let from = roads.point_of_interest(WORLD_SEED, 0, region).unwrap();
+      assert_eq!(paths[0].tiles.last(), roads.point_of_interest(WORLD_SEED, 0, region + IVec2::X).as_ref());
+      assert_eq!(paths[1].tiles.last(), roads.point_of_interest(WORLD_SEED, 0, region + IVec2::Y).as_ref());
+      assert!(paths.iter().all(|road| road.tiles[0] == from && is_connected(road)));
+    }
+    assert!(RoadGenerator::new(ROAD).with_density(0.).roads(WORLD_SEED, 0, IVec2::ZERO).is_empty());
+  }
+
+  #[test]
+  fn roads_cross_chunks_test(){
+    let roads = || RoadGenerator::new(ROAD).with_region_size(12).with_density(0.8).with_width(2);
+    let (tiles, area) = generated_tiles(roads, ROAD);
+    let expected: HashSet<IVec2> = (-10..10).flat_map(|y| (-10..10).map(move |x| IVec2::new(x, y)))
+      .flat_map(|region| roads().roads(WORLD_SEED, layer_id("ground"), region).to_vec())
+      .flat_map(|road| road.tiles)
+      .flat_map(|tile| [tile, tile + IVec2::X, tile + IVec2::Y, tile + IVec2::ONE])
+      .filter(|tile| area.contains(tile))
+      .collect();
+    assert!(!tiles.is_empty());
+    assert_eq!(tiles, expected);
+  }
+
+  /// Every chunk of a world wrapping both ways, 6 chunks (48 tiles) wide and high.
+  fn wrapped_world()->(ChunkBounds, WrappedTiles, Vec<IVec2>){
+    let bounds = ChunkBounds::new(IVec2::ZERO, IVec2::new(5, 5)).with_wrap(true, true);
+    let chunks = (0..6).flat_map(|y| (0..6).map(move |x| IVec2::new(x, y))).collect();
+    (bounds, WrappedTiles::new(Some(bounds), CHUNK_SIZE, ChunkAnchor::Center), chunks)
+  }
+
+  /// Tiles set by a generator in every chunk of the wrapped world, and the paths of every region it starts.
+  fn wrapped_tiles(generator: impl ChunkGenerator, paths: impl Fn(WrappedTiles, IVec2)->Vec<TilePath>, region_size: i32)->(HashSet<IVec2>, Vec<TilePath>){
+    let (bounds, world, chunks) = wrapped_world();
+    let mut pipeline = GenerationPipeline::new()
+      .with_seed(WORLD_SEED, layer_id("ground"))
+      .with_bounds(Some(bounds))
+      .with_pass(generator);
+    let mut tiles = HashSet::default();
+    for chunk_index in chunks{
+      for (local_tile_index, _) in pipeline.generate(chunk_index, CHUNK_SIZE, ChunkAnchor::Center).iter(){
+        tiles.insert(local_tile_index_to_global(chunk_index, CHUNK_SIZE, local_tile_index.as_ivec2(), ChunkAnchor::Center));
+      }
+    }
+    let (first, last) = (world.region(world.min, region_size), world.region(world.min + world.size - IVec2::ONE, region_size));
+    let paths = (first.y..=last.y)
+      .flat_map(|y| (first.x..=last.x).map(move |x| IVec2::new(x, y)))
+      .flat_map(|region| paths(world, region))
+      .collect();
+    (tiles, paths)
+  }
+
+  #[rstest]
+  #[case(16)]
+  #[case(20)]
+  fn roads_cross_the_wrap_seam_test(
+    #[case] region_size: i32,
+  ){
+    let roads = || RoadGenerator::new(ROAD).with_region_size(region_size).with_density(1.);
+    let (tiles, paths) = wrapped_tiles(roads(), |world, region| roads().wrapped_roads(WORLD_SEED, layer_id("ground"), world, region).to_vec(), region_size);
+    let (bounds, world, _) = wrapped_world();
+    assert!(paths.iter().flat_map(|road| road.tiles.iter()).any(|&tile| !world.contains(tile)));
+    for road in paths.iter(){
+      assert!(is_connected(road));
+      for &tile in road.tiles.iter(){
+        assert!(tiles.contains(&wrap_global_tile_index(tile, CHUNK_SIZE, ChunkAnchor::Center, &bounds)), "{tile}");
+      }
+    }
+  }
+
+  #[test]
+  fn rivers_cross_the_wrap_seam_test(){
+    let rivers = ||{
+      RiverGenerator::new(|_, tile| -0.01*tile.x as f64, WATER)
+        .with_region_size(16)
+        .with_sources(2, -10.)
+        .with_sea_level(-10.)
+        .with_max_length(30)
+    };
+    let (tiles, paths) = wrapped_tiles(rivers(), |world, region| rivers().wrapped_rivers(WORLD_SEED, layer_id("ground"), world, region).to_vec(), 16);
+    let (bounds, world, _) = wrapped_world();
+    assert!(paths.iter().flat_map(|river| river.tiles.iter()).any(|&tile| !world.contains(tile)));
+    for river in paths.iter(){
+      for &tile in river.tiles.iter(){
+        assert!(tiles.contains(&wrap_global_tile_index(tile, CHUNK_SIZE, ChunkAnchor::Center, &bounds)), "{tile}");
+      }
+    }
+  }
+}
@@ -0,0 +1,316 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{bundle::ChunkAnchor, chunks::local_tile_index_to_global, random::mix};
+
+/// 2d noise function returning values in about `[-1, 1]`.
+pub trait NoiseFn{
+  fn get(&self, x: f64, y: f64)->f64;
+
+  /// Fractal Brownian motion with a lacunarity of 2 and a gain of 0.5.
+  fn fbm(self, octaves: u32)->Fbm<Self> where Self: Sized{
+    Fbm{source: self, octaves, lacunarity: 2., gain: 0.5}
+  }
+
+  /// Ridged multifractal with a lacunarity of 2 and a gain of 0.5.
+  fn ridged(self, octaves: u32)->Ridged<Self> where Self: Sized{
+    Ridged{source: self, octaves, lacunarity: 2., gain: 0.5}
+  }
+
+  /// Offsets the sampled position by `warp`, scaled by `amplitude`.
+  fn warped<W: NoiseFn>(self, warp: W, amplitude: f64)->DomainWarp<Self, W> where Self: Sized{
+    DomainWarp{source: self, warp, amplitude}
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum NoiseKind{
+  Value,
+  Perlin,
+  Simplex,
+}
+
+/// Seeded lattice noise. The lattice is hashed from the seed, so there are no permutation tables to build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Noise{
+  pub kind: NoiseKind,
+  pub seed: u64,
+}
+
+const GRADIENTS: [(f64, f64); 8] = [
+  (1., 1.), (-1., 1.), (1., -1.), (-1., -1.),
+  (1., 0.), (-1., 0.), (0., 1.), (0., -1.),
+];
+
+fn hash(seed: u64, x: i64, y: i64)->u64{
+  mix(seed ^ mix((x as u64) ^ mix(y as u64)))
+}
+
+fn lattice_value(seed: u64, x: i64, y: i64)->f64{
+  (hash(seed, x, y) >> 11) as f64/(1u64 << 53) as f64*2. - 1.
+}
+
+fn gradient(seed: u64, x: i64, y: i64, dx: f64, dy: f64)->f64{
+  let (gx, gy) = GRADIENTS[(hash(seed, x, y) & 7) as usize];
+  gx*dx + gy*dy
+}
+
+fn fade(t: f64)->f64{
+  t*t*t*(t*(t*6. - 15.) + 10.)
+}
+
+fn lerp(a: f64, b: f64, t: f64)->f64{
+  a + (b - a)*t
+}
+
+impl Noise{
+  pub fn new(kind: NoiseKind, seed: u64)->Noise{
+    Noise{kind, seed}
+  }
+
+  pub fn value(seed: u64)->Noise{
+    Noise::new(NoiseKind::Value, seed)
+  }
+
+  pub fn perlin(seed: u64)->Noise{
+    Noise::new(NoiseKind::Perlin, seed)
+  }
+
+  pub fn simplex(seed: u64)->Noise{
+    Noise::new(NoiseKind::Simplex, seed)
+  }
+
+  fn value_noise(&self, x: f64, y: f64)->f64{
+    let (x0, y0) = (x.floor(), y.floor());
+    let (ix, iy) = (x0 as i64, y0 as i64);
+    let (u, v) = (fade(x - x0), fade(y - y0));
+    lerp(
+      lerp(lattice_value(self.seed, ix, iy), lattice_value(self.seed, ix + 1, iy), u),
+      lerp(lattice_value(self.seed, ix, iy + 1), lattice_value(self.seed, ix + 1, iy + 1), u),
+      v,
+    )
+  }
+
+  fn perlin_noise(&self, x: f64, y: f64)->f64{
+    let (x0, y0) = (x.floor(), y.floor());
+    let (ix, iy) = (x0 as i64, y0 as i64);
+    let (dx, dy) = (x - x0, y - y0);
+    let (u, v) = (fade(dx), fade(dy));
+    lerp(
+      lerp(gradient(self.seed, ix, iy, dx, dy), gradient(self.seed, ix + 1, iy, dx - 1., dy), u),
+      lerp(gradient(self.seed, ix, iy + 1, dx, dy - 1.), gradient(self.seed, ix + 1, iy + 1, dx - 1., dy - 1.), u),
+      v,
+    )
+  }
+
+  fn simplex_noise(&self, x: f64, y: f64)->f64{
+    const F2: f64 = 0.366_025_403_784_438_6;
+    const G2: f64 = 0.211_324_865_405_187_1;
+    let skew = (x + y)*F2;
+    let (i, j) = ((x + skew).floor(), (y + skew).floor());
+    let unskew = (i + j)*G2;
+    let (x0, y0) = (x - (i - unskew), y - (j - unskew));
+    let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+    let corners = [
+      (0, 0, x0, y0),
+      (i1, j1, x0 - i1 as f64 + G2, y0 - j1 as f64 + G2),
+      (1, 1, x0 - 1. + 2.*G2, y0 - 1. + 2.*G2),
+    ];
+    let (i, j) = (i as i64, j as i64);
+    70.*corners.iter().map(|&(ci, cj, dx, dy)|{
+      let t = 0.5 - dx*dx - dy*dy;
+      if t < 0. { 0. } else { t*t*t*t*gradient(self.seed, i + ci, j + cj, dx, dy) }
+    }).sum::<f64>()
+  }
+}
+
+impl NoiseFn for Noise{
+  fn get(&self, x: f64, y: f64)->f64{
+    match self.kind{
+      NoiseKind::Value => self.value_noise(x, y),
+      NoiseKind::Perlin => self.perlin_noise(x, y),
+      NoiseKind::Simplex => self.simplex_noise(x, y),
+    }
+  }
+}
+
+/// Offset applied to every octave, so octaves don't share the lattice origin.
+const OCTAVE_OFFSET: f64 = 71.37;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fbm<N>{
+  pub source: N,
+  pub octaves: u32,
+  pub lacunarity: f64,
+  pub gain: f64,
+}
+
+impl<N: NoiseFn> NoiseFn for Fbm<N>{
+  fn get(&self, x: f64, y: f64)->f64{
+    let (mut sum, mut total, mut amplitude, mut frequency) = (0., 0., 1., 1.);
+    for octave in 0..self.octaves{
+      let offset = octave as f64*OCTAVE_OFFSET;
+      sum += amplitude*self.source.get(x*frequency + offset, y*frequency + offset);
+      total += amplitude;
+      amplitude *= self.gain;
+      frequency *= self.lacunarity;
+    }
+    if total > 0. { sum/total } else { 0. }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ridged<N>{
+  pub source: N,
+  pub octaves: u32,
+  pub lacunarity: f64,
+  pub gain: f64,
+}
+
+impl<N: NoiseFn> NoiseFn for Ridged<N>{
+  fn get(&self, x: f64, y: f64)->f64{
+    let (mut sum, mut total, mut amplitude, mut frequency) = (0., 0., 1., 1.);
+    for octave in 0..self.octaves{
+      let offset = octave as f64*OCTAVE_OFFSET;
+      let ridge = 1. - self.source.get(x*frequency + offset, y*frequency + offset).abs().min(1.);
+      sum += amplitude*ridge*ridge;
+      total += amplitude;
+      amplitude *= self.gain;
+      frequency *= self.lacunarity;
+    }
+    if total > 0. { sum/total*2. - 1. } else { 0. }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DomainWarp<N, W>{
+  pub source: N,
+  pub warp: W,
+  pub amplitude: f64,
+}
+
+impl<N: NoiseFn, W: NoiseFn> NoiseFn for DomainWarp<N, W>{
+  fn get(&self, x: f64, y: f64)->f64{
+    let dx = self.warp.get(x, y);
+    let dy = self.warp.get(x + 5.2, y + 1.3);
+    self.source.get(x + self.amplitude*dx, y + self.amplitude*dy)
+  }
+}
+
+/// Samples a rectangle of global tile indexes starting at `min`, row by row, at `frequency` samples per tile.
+pub fn sample_grid(noise: &impl NoiseFn, min: IVec2, size: UVec2, frequency: f64)->Vec<f64>{
+  let mut values = Vec::with_capacity((size.x*size.y) as usize);
+  for y in 0..size.y as i32{
+    for x in 0..size.x as i32{
+      values.push(noise.get((min.x + x) as f64*frequency, (min.y + y) as f64*frequency));
+    }
+  }
+  values
+}
+
+/// Samples every tile of a chunk at its global tile index, indexed like `ChunkData::tiles`.
+pub fn sample_chunk(noise: &impl NoiseFn, chunk_index: IVec2, chunk_size: UVec2, anchor: ChunkAnchor, frequency: f64)->Vec<f64>{
+  let origin = local_tile_index_to_global(chunk_index, chunk_size, IVec2::ZERO, anchor);
+  let mut values = Vec::with_capacity((chunk_size.x*chunk_size.y) as usize);
+  for y in 0..chunk_size.y as i32{
+    for x in 0..chunk_size.x as i32{
+      // local y grows upwards, global y downwards
+      values.push(noise.get((origin.x + x) as f64*frequency, (origin.y - y) as f64*frequency));
+    }
+  }
+  values
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use rstest::rstest;
+  use crate::{bundle::ChunkAnchor, chunks::local_tile_index_to_global};
+  use super::{Noise, NoiseFn, sample_chunk, sample_grid};
+
+  fn sources()->Vec<Noise>{
+    vec![Noise::value(7), Noise::perlin(7), Noise::simplex(7)]
+  }
+
+  #[test]
+  fn noise_is_in_range_test(){
+    for noise in sources(){
+      for i in 0..2000{
+        let (x, y) = (i as f64*0.173 - 150., i as f64*0.391 - 350.);
+        let value = noise.get(x, y);
+        assert!((-1. ..=1.).contains(&value), "{:?} at ({x}, {y}) is {value}", noise.kind);
+        let value = noise.fbm(5).get(x, y);
+        assert!((-1. ..=1.).contains(&value), "fbm {:?} at ({x}, {y}) is {value}", noise.kind);
+        let value = noise.ridged(5).get(x, y);
+        assert!((-1. ..=1.).contains(&value), "ridged {:?} at ({x}, {y}) is {value}", noise.kind);
+      }
+    }
+  }
+
+  #[test]
+  fn seed_changes_noise_test(){
+    for noise in sources(){
+      let other = Noise::new(noise.kind, noise.seed + 1);
+      assert!((0..20).any(|i| noise.get(i as f64*0.37, 0.5) != other.get(i as f64*0.37, 0.5)));
+    }
+  }
+
+  #[test]
+  fn lattice_points_test(){
+    // gradient noise is zero on the lattice
+    assert_eq!(Noise::perlin(3).get(4., -9.), 0.);
+  }
+
+  #[rstest]
+  #[case(Noise::value(42), [0.13071944288016846, 0.29278166490888424, -0.6414040174226596, -0.025336636484423455])]
+  #[case(Noise::perlin(42), [-0.125, 0.37210557887999907, 0.039732158631997194, 0.2100135507196117])]
+  #[case(Noise::simplex(42), [-0.30715651362721635, -0.4057015329542773, -0.9883643787853923, 0.3128291036665644])]
+  fn snapshot_test(
+    #[case] noise: Noise,
+    #[case] expected: [f64; 4],
+  ){
+    let points = [(0.5, 0.5), (-5.1, 3.3), (-0.99, -3.03), (12345.678, 9876.543)];
+    for (&(x, y), expected) in points.iter().zip(expected){
+      let value = noise.get(x, y);
+      assert!((value - expected).abs() < 1e-12, "{:?} at ({x}, {y}) is {value}, expected {expected}", noise.kind);
+    }
+  }
+
+  #[test]
+  fn composed_snapshot_test(){
+    let noise = Noise::simplex(1).fbm(4).warped(Noise::perlin(2).fbm(2), 4.);
+    let expected = [0.06404353050406503, -0.08969506493281616, 0.3933704882859865];
+    for (i, expected) in expected.into_iter().enumerate(){
+      let value = noise.get(i as f64*3.3, i as f64*-1.7);
+      assert!((value - expected).abs() < 1e-12, "{i}: {value} != {expected}");
+    }
+    let (value, expected) = (Noise::perlin(5).ridged(3).get(0.3, 0.6), -0.23868409801816015);
+    assert!((value - expected).abs() < 1e-12, "{value} != {expected}");
+  }
+
+  #[rstest]
+  #[case(ChunkAnchor::Center)]
+  #[case(ChunkAnchor::Corner)]
+  fn sample_chunk_test(
+    #[case] anchor: ChunkAnchor,
+  ){
+    let noise = Noise::perlin(9).fbm(3);
+    let chunk_size = UVec2::new(4, 3);
+    let chunk_index = IVec2::new(-2, 5);
+    let values = sample_chunk(&noise, chunk_index, chunk_size, anchor, 0.1);
+    for y in 0..chunk_size.y{
+      for x in 0..chunk_size.x{
+        let global = local_tile_index_to_global(chunk_index, chunk_size, IVec2::new(x as i32, y as i32), anchor);
+        assert_eq!(values[(y*chunk_size.x + x) as usize], noise.get(global.x as f64*0.1, global.y as f64*0.1));
+      }
+    }
+  }
+
+  #[test]
+  fn sample_grid_test(){
+    let noise = Noise::value(1);
+    let values = sample_grid(&noise, IVec2::new(-1, 2), UVec2::new(3, 2), 0.5);
+    assert_eq!(values.len(), 6);
+    assert_eq!(values[4], noise.get(0., 1.5));
+  }
+}
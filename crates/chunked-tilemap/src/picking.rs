@@ -0,0 +1,139 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{bundle::ChunkedTilemap, tiles::{ChunkedTiles, GlobalTile}, layers::LayeredTilemap};
+
+/// Tile of a `ChunkedTilemap` found under a world position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TilePick{
+  pub tilemap_entity: Entity,
+  pub chunk_index: IVec2,
+  pub local_tile_index: UVec2,
+  pub global_tile_index: IVec2,
+  pub world_position: Vec2,
+  pub tile: GlobalTile,
+}
+
+/// Marks the camera used by `TilePickingPlugin` to turn the cursor into world positions.
+#[derive(Component, Default)]
+pub struct TilePickingCamera;
+
+pub struct TileHovered(pub TilePick);
+
+pub struct TileClicked{
+  pub pick: TilePick,
+  pub button: MouseButton,
+}
+
+/// Converts a cursor position (window pixels, origin at the bottom-left) into a world position on the `z = 0` plane.
+pub fn cursor_to_world(
+  cursor_position: Vec2,
+  viewport_size: Vec2,
+  camera_matrix: Mat4,
+  projection_matrix: Mat4,
+)->Vec2{
+  let ndc = (cursor_position/viewport_size)*2. - Vec2::ONE;
+  let ndc_to_world = camera_matrix*projection_matrix.inverse();
+  ndc_to_world.project_point3(ndc.extend(-1.)).truncate()
+}
+
+pub fn camera_cursor_to_world(
+  camera: &Camera,
+  camera_transform: &GlobalTransform,
+  cursor_position: Vec2,
+)->Option<Vec2>{
+  Some(cursor_to_world(
+    cursor_position,
+    camera.logical_viewport_size()?,
+    camera_transform.compute_matrix(),
+    camera.projection_matrix(),
+  ))
+}
+
+impl<'w, 's> ChunkedTiles<'w, 's>{
+  /// Finds the tile of a tilemap under a world position.
+  pub fn pick(&self, tilemap_entity: Entity, world_position: Vec2)->Option<TilePick>{
+    let tilemap = self.tilemap(tilemap_entity)?;
+    let position = world_position - self.tilemap_offset(tilemap_entity);
+    let global_tile_index = tilemap.tile_at_position(position);
+    let (chunk_index, local_tile_index) = tilemap.global_tile_index_to_local(global_tile_index);
+    Some(TilePick{
+      tilemap_entity,
+      chunk_index,
+      local_tile_index: local_tile_index.as_uvec2(),
+      global_tile_index,
+      world_position,
+      tile: self.get(tilemap_entity, global_tile_index),
+    })
+  }
+}
+
+pub fn pick_tiles(
+  tiles: ChunkedTiles,
+  windows: Res<Windows>,
+  buttons: Res<Input<MouseButton>>,
+  q_cameras: Query<(&Camera, &GlobalTransform), With<TilePickingCamera>>,
+  q_tilemaps: Query<Entity, (With<ChunkedTilemap>, Without<LayeredTilemap>)>,
+  mut ew_tile_hovered: EventWriter<TileHovered>,
+  mut ew_tile_clicked: EventWriter<TileClicked>,
+  mut hovered: Local<HashMap<Entity, IVec2>>,
+){
+  let cursor_position = match windows.get_primary().and_then(|window| window.cursor_position()){
+    Some(cursor_position) => cursor_position,
+    None => return
+  };
+  let (camera, camera_transform) = match q_cameras.get_single(){
+    Ok(camera) => camera,
+    Err(_) => return
+  };
+  let world_position = match camera_cursor_to_world(camera, camera_transform, cursor_position){
+    Some(world_position) => world_position,
+    None => return
+  };
+
+  for tilemap_entity in q_tilemaps.iter(){
+    if let Some(pick) = tiles.pick(tilemap_entity, world_position){
+      if hovered.insert(tilemap_entity, pick.global_tile_index) != Some(pick.global_tile_index){
+        ew_tile_hovered.send(TileHovered(pick));
+      }
+      for &button in buttons.get_just_pressed(){
+        ew_tile_clicked.send(TileClicked{pick, button});
+      }
+    }
+  }
+}
+
+/// Emits `TileHovered` and `TileClicked` events for the tiles under the cursor of the `TilePickingCamera`.
+pub struct TilePickingPlugin;
+
+impl Plugin for TilePickingPlugin{
+  fn build(&self, app: &mut App) {
+    app
+      .add_event::<TileHovered>()
+      .add_event::<TileClicked>()
+      .add_system(pick_tiles);
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use rstest::rstest;
+
+  #[rstest]
+  #[case((400., 300.), (0., 0., 0.), 1., (0., 0.))]
+  #[case((0., 0.), (0., 0., 0.), 1., (-400., -300.))]
+  #[case((800., 600.), (100., -50., 0.), 1., (500., 250.))]
+  #[case((600., 300.), (10., 10., 999.), 2., (410., 10.))]
+  fn cursor_to_world_test(
+    #[case] cursor_position: (f32, f32),
+    #[case] camera_translation: (f32, f32, f32),
+    #[case] scale: f32,
+    #[case] expected: (f32, f32),
+  ){
+    let viewport_size = Vec2::new(800., 600.);
+    let camera_matrix = Mat4::from_scale_rotation_translation(Vec3::splat(scale), Quat::IDENTITY, Vec3::from(camera_translation));
+    let projection_matrix = Mat4::orthographic_rh(-400., 400., -300., 300., 0., 1000.);
+    let world_position = super::cursor_to_world(Vec2::from(cursor_position), viewport_size, camera_matrix, projection_matrix);
+    assert!(world_position.abs_diff_eq(Vec2::from(expected), 0.001), "{world_position} != {expected:?}");
+  }
+}
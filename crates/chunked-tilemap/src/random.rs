@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+use rand::{RngCore, Error};
+
+/// Seed of the whole world, every chunk and tile seed is derived from it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorldSeed(pub u64);
+
+const CHUNK_DOMAIN: u64 = 0x6368_756e_6b00_0001;
+const TILE_DOMAIN: u64 = 0x7469_6c65_0000_0002;
+
+/// SplitMix64 finalizer.
+pub(crate) fn mix(mut z: u64)->u64{
+  z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+  z ^ (z >> 31)
+}
+
+fn pack(index: IVec2)->u64{
+  ((index.x as u32 as u64) << 32) | index.y as u32 as u64
+}
+
+/// Stable id of a layer derived from its name (FNV-1a).
+pub fn layer_id(name: &str)->u64{
+  name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+pub fn chunk_seed(world_seed: u64, layer_id: u64, chunk_index: IVec2)->u64{
+  mix(mix(mix(world_seed ^ CHUNK_DOMAIN) ^ layer_id) ^ pack(chunk_index))
+}
+
+pub fn tile_seed(world_seed: u64, layer_id: u64, global_tile_index: IVec2)->u64{
+  mix(mix(mix(world_seed ^ TILE_DOMAIN) ^ layer_id) ^ pack(global_tile_index))
+}
+
+/// Small SplitMix64 generator. Unlike the generators of `rand`, its output is fixed forever,
+/// so worlds generated from the same seed stay the same across versions and platforms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeededRng{
+  state: u64,
+}
+
+impl SeededRng{
+  pub fn new(seed: u64)->SeededRng{
+    SeededRng{state: seed}
+  }
+
+  pub fn for_chunk(world_seed: u64, layer_id: u64, chunk_index: IVec2)->SeededRng{
+    SeededRng::new(chunk_seed(world_seed, layer_id, chunk_index))
+  }
+
+  pub fn for_tile(world_seed: u64, layer_id: u64, global_tile_index: IVec2)->SeededRng{
+    SeededRng::new(tile_seed(world_seed, layer_id, global_tile_index))
+  }
+}
+
+impl RngCore for SeededRng{
+  fn next_u32(&mut self)->u32{
+    (self.next_u64() >> 32) as u32
+  }
+
+  fn next_u64(&mut self)->u64{
+    self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    mix(self.state)
+  }
+
+  fn fill_bytes(&mut self, dest: &mut [u8]){
+    for chunk in dest.chunks_mut(8){
+      let bytes = self.next_u64().to_le_bytes();
+      chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+  }
+
+  fn try_fill_bytes(&mut self, dest: &mut [u8])->Result<(), Error>{
+    self.fill_bytes(dest);
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use rand::{Rng, RngCore};
+  use rstest::rstest;
+  use super::{SeededRng, chunk_seed, layer_id, tile_seed};
+
+  #[test]
+  fn same_seed_same_sequence_test(){
+    let a: Vec<u32> = (0..32).scan(SeededRng::for_chunk(1, layer_id("ground"), IVec2::new(-3, 7)), |rng, _| Some(rng.gen_range(0..1000))).collect();
+    let b: Vec<u32> = (0..32).scan(SeededRng::for_chunk(1, layer_id("ground"), IVec2::new(-3, 7)), |rng, _| Some(rng.gen_range(0..1000))).collect();
+    assert_eq!(a, b);
+  }
+
+  #[rstest]
+  #[case(2, "ground", (-3, 7))]
+  #[case(1, "trees", (-3, 7))]
+  #[case(1, "ground", (-3, 8))]
+  #[case(1, "ground", (7, -3))]
+  fn seeds_differ_test(
+    #[case] world_seed: u64,
+    #[case] layer: &str,
+    #[case] chunk_index: (i32, i32),
+  ){
+    assert_ne!(
+      chunk_seed(1, layer_id("ground"), IVec2::new(-3, 7)),
+      chunk_seed(world_seed, layer_id(layer), IVec2::from(chunk_index))
+    );
+  }
+
+  #[test]
+  fn chunk_and_tile_seeds_differ_test(){
+    assert_ne!(chunk_seed(1, 0, IVec2::new(2, 2)), tile_seed(1, 0, IVec2::new(2, 2)));
+  }
+
+  #[test]
+  fn reference_values_test(){
+    // SplitMix64 and FNV-1a reference outputs, these must never change
+    let mut rng = SeededRng::new(0);
+    assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+    assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+    assert_eq!(layer_id(""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(layer_id("a"), 0xaf63_dc4c_8601_ec8c);
+  }
+
+  #[test]
+  fn fill_bytes_test(){
+    let mut bytes = [0u8; 12];
+    SeededRng::new(5).fill_bytes(&mut bytes);
+    let mut rng = SeededRng::new(5);
+    let first = rng.next_u64().to_le_bytes();
+    let second = rng.next_u64().to_le_bytes();
+    assert_eq!(&bytes[..8], &first);
+    assert_eq!(&bytes[8..], &second[..4]);
+  }
+}
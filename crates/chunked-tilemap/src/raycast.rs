@@ -0,0 +1,208 @@
+use bevy::prelude::*;
+
+use crate::tiles::{ChunkedTiles, GlobalTile};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridRayHit{
+  pub cell: IVec2,
+  /// Ray parameter at which the ray enters `cell`, the entry point is `origin + direction*distance`.
+  pub distance: f32,
+  /// Side of `cell` the ray entered through, zero if the ray started inside of it.
+  pub normal: IVec2,
+}
+
+/// Cells a ray crosses at most past the one it starts in, so rays with an infinite `max_distance` end as well.
+pub const MAX_RAY_CELLS: u32 = 1_000_000;
+
+/// Walks the cells of a unit grid crossed by a ray, in order, until `is_hit` returns `true`
+/// or the ray gets longer than `max_distance` (or crosses `MAX_RAY_CELLS` cells). Cell `(x, y)` covers `[x, x+1)×[y, y+1)`.
+/// Rays with a NaN `max_distance`, or a non-finite origin or direction, hit nothing.
+pub fn grid_raycast(
+  origin: Vec2,
+  direction: Vec2,
+  max_distance: f32,
+  mut is_hit: impl FnMut(IVec2)->bool,
+)->Option<GridRayHit>{
+  if max_distance.is_nan() || !origin.is_finite() || !direction.is_finite() {
+    return None;
+  }
+  let mut cell = origin.floor().as_ivec2();
+  if is_hit(cell){
+    return Some(GridRayHit{cell, distance: 0., normal: IVec2::ZERO});
+  }
+  if direction == Vec2::ZERO {
+    return None;
+  }
+
+  let step = IVec2::new(direction.x.signum() as i32, direction.y.signum() as i32);
+  let t_delta = Vec2::new(
+    if direction.x != 0. { 1./direction.x.abs() } else { f32::INFINITY },
+    if direction.y != 0. { 1./direction.y.abs() } else { f32::INFINITY },
+  );
+  let boundary_distance = |origin: f32, cell: i32, direction: f32|{
+    if direction > 0. {
+      (cell as f32 + 1. - origin)/direction
+    } else if direction < 0. {
+      (origin - cell as f32)/-direction
+    } else {
+      f32::INFINITY
+    }
+  };
+  let mut t_max = Vec2::new(
+    boundary_distance(origin.x, cell.x, direction.x),
+    boundary_distance(origin.y, cell.y, direction.y),
+  );
+
+  for _ in 0..MAX_RAY_CELLS{
+    let (distance, normal) = if t_max.x < t_max.y {
+      cell.x += step.x;
+      t_max.x += t_delta.x;
+      (t_max.x - t_delta.x, IVec2::new(-step.x, 0))
+    } else {
+      cell.y += step.y;
+      t_max.y += t_delta.y;
+      (t_max.y - t_delta.y, IVec2::new(0, -step.y))
+    };
+    if distance > max_distance {
+      return None;
+    }
+    if is_hit(cell){
+      return Some(GridRayHit{cell, distance, normal});
+    }
+  }
+  None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileRayHit{
+  pub global_tile_index: IVec2,
+  pub tile_entity: Entity,
+  /// World distance from the ray origin to the point where the ray enters the tile.
+  pub distance: f32,
+  pub position: Vec2,
+  /// Side of the tile the ray entered through, in global tile space.
+  pub normal: IVec2,
+}
+
+impl<'w, 's> ChunkedTiles<'w, 's>{
+  /// Casts a world space ray over the global tiles of a tilemap, across chunk borders.
+  /// `is_blocking` is called for every existing tile on the way, empty tiles and tiles of unloaded chunks never block.
+  /// An infinite `max_distance` walks up to `MAX_RAY_CELLS` tiles.
+  pub fn raycast(
+    &self,
+    tilemap_entity: Entity,
+    origin: Vec2,
+    direction: Vec2,
+    max_distance: f32,
+    mut is_blocking: impl FnMut(IVec2, Entity)->bool,
+  )->Option<TileRayHit>{
+    let tilemap = self.tilemap(tilemap_entity)?;
+    let direction = direction.try_normalize()?;
+    let tile_size = tilemap.tile_size;
+    let origin_tile = tilemap.origin_tile();
+    let zero_tile_position = tilemap.tile_position(origin_tile) + self.tilemap_offset(tilemap_entity);
+    // tile space relative to the floating origin, where tile (x, y) is the cell covering [x, x+1)×[y, y+1)
+    let grid_origin = (origin - zero_tile_position)/tile_size*Vec2::new(1., -1.) + 0.5;
+    let grid_direction = direction/tile_size*Vec2::new(1., -1.);
+
+    let mut tile_entity = None;
+    let hit = grid_raycast(grid_origin, grid_direction, max_distance, |cell|{
+      let cell = cell + origin_tile;
+      match self.get(tilemap_entity, cell){
+        GlobalTile::Tile(entity) if is_blocking(cell, entity) => {
+          tile_entity = Some(entity);
+          true
+        }
+        _ => false
+      }
+    })?;
+    Some(TileRayHit{
+      global_tile_index: hit.cell + origin_tile,
+      tile_entity: tile_entity?,
+      distance: hit.distance,
+      position: origin + direction*hit.distance,
+      normal: hit.normal,
+    })
+  }
+
+  /// Checks that no blocking tile lies between two world positions.
+  pub fn line_of_sight(
+    &self,
+    tilemap_entity: Entity,
+    from: Vec2,
+    to: Vec2,
+    is_blocking: impl FnMut(IVec2, Entity)->bool,
+  )->bool{
+    self.raycast(tilemap_entity, from, to-from, from.distance(to), is_blocking).is_none()
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::{prelude::*, utils::HashSet};
+  use rstest::rstest;
+  use super::{MAX_RAY_CELLS, grid_raycast};
+
+  #[rstest]
+  #[case((0.5, 0.5), (1., 0.), (3, 0), Some(((3, 0), 2.5, (-1, 0))))]
+  #[case((0.5, 0.5), (-1., 0.), (-2, 0), Some(((-2, 0), 1.5, (1, 0))))]
+  #[case((0.5, 0.5), (0., 1.), (0, 4), Some(((0, 4), 3.5, (0, -1))))]
+  #[case((0.5, 0.5), (1., 0.), (5, 0), None)]
+  #[case((0.5, 0.5), (0., 1.), (1, 1), None)]
+  #[case((0.5, 0.5), (0., 0.), (0, 0), Some(((0, 0), 0., (0, 0))))]
+  fn grid_raycast_test(
+    #[case] origin: (f32, f32),
+    #[case] direction: (f32, f32),
+    #[case] blocking: (i32, i32),
+    #[case] expected: Option<((i32, i32), f32, (i32, i32))>,
+  ){
+    let hit = grid_raycast(Vec2::from(origin), Vec2::from(direction), 4., |cell| cell == IVec2::from(blocking));
+    assert_eq!(
+      hit.map(|hit| (hit.cell, hit.distance, hit.normal)),
+      expected.map(|(cell, distance, normal)| (IVec2::from(cell), distance, IVec2::from(normal)))
+    );
+  }
+
+  #[rstest]
+  #[case((0.5, 0.5), (1., 0.), f32::NAN)]
+  #[case((0.5, 0.5), (f32::NAN, 0.), 4.)]
+  #[case((f32::INFINITY, 0.5), (1., 0.), 4.)]
+  fn grid_raycast_non_finite_test(
+    #[case] origin: (f32, f32),
+    #[case] direction: (f32, f32),
+    #[case] max_distance: f32,
+  ){
+    assert_eq!(grid_raycast(Vec2::from(origin), Vec2::from(direction), max_distance, |_| false), None);
+  }
+
+  #[test]
+  fn grid_raycast_infinite_test(){
+    let hit = grid_raycast(Vec2::new(0.5, 0.5), Vec2::X, f32::INFINITY, |cell| cell == IVec2::X).unwrap();
+    assert_eq!((hit.cell, hit.distance, hit.normal), (IVec2::X, 0.5, IVec2::new(-1, 0)));
+    // nothing to hit, the ray still ends
+    let mut visited = 0;
+    assert_eq!(grid_raycast(Vec2::new(0.5, 0.5), Vec2::new(1., 0.3), f32::INFINITY, |_|{ visited += 1; false }), None);
+    assert_eq!(visited, MAX_RAY_CELLS + 1);
+  }
+
+  #[test]
+  fn grid_raycast_visits_cells_in_order_test(){
+    let mut visited = vec![];
+    let hit = grid_raycast(Vec2::new(0.5, 0.5), Vec2::new(2., 1.).normalize(), 100., |cell|{
+      visited.push(cell);
+      cell.x >= 4
+    });
+    assert_eq!(visited, vec![
+      IVec2::new(0, 0), IVec2::new(1, 0), IVec2::new(1, 1), IVec2::new(2, 1), IVec2::new(3, 1), IVec2::new(3, 2), IVec2::new(4, 2),
+    ]);
+    assert_eq!(hit.unwrap().normal, IVec2::new(-1, 0));
+  }
+
+  #[test]
+  fn grid_raycast_diagonal_walls_test(){
+    let walls: HashSet<IVec2> = (-10..10).map(|i| IVec2::new(i, 6)).collect();
+    let hit = grid_raycast(Vec2::new(0.5, 0.5), Vec2::new(1., 1.).normalize(), 100., |cell| walls.contains(&cell)).unwrap();
+    assert_eq!(hit.cell.y, 6);
+    assert!((hit.distance - 5.5*2f32.sqrt()).abs() < 0.0001);
+  }
+}
@@ -0,0 +1,422 @@
+use bevy::{
+  prelude::*,
+  asset::{AssetLoader, LoadContext, LoadedAsset},
+  reflect::TypeUuid,
+  utils::{BoxedFuture, HashSet},
+};
+use std::collections::HashMap;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+  authored::AuthoredRegions,
+  biome::{Biome, BiomeMap, Climate},
+  bundle::{ChunkedTilemap, ChunkAnchor},
+  chunk_data::ChunkData,
+  chunks::local_tile_index_to_global,
+  fill_chunk::FillChunkEvent,
+  generation::GenerationPipeline,
+  layers::LayerDependencies,
+  noise::{Noise, NoiseFn, NoiseKind},
+  random::{SeededRng, WorldSeed, chunk_seed, layer_id},
+  spawn_chunk::PrepareChunkEvent,
+  variants::TileVariants,
+};
+
+fn one()->f64{
+  1.
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum Fractal{
+  #[default]
+  Fbm,
+  Ridged,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct NoiseSource{
+  pub kind: NoiseKind,
+  /// Added to the world seed, so several sources of the same kind differ.
+  #[serde(default)]
+  pub seed: u64,
+  #[serde(default = "one")]
+  pub frequency: f64,
+  #[serde(default)]
+  pub fractal: Fractal,
+  #[serde(default)]
+  pub octaves: Option<u32>,
+}
+
+impl NoiseSource{
+  pub fn get(&self, world_seed: u64, name: &str, global_tile_index: IVec2)->f64{
+    let noise = Noise::new(self.kind, chunk_seed(world_seed.wrapping_add(self.seed), layer_id(name), IVec2::ZERO));
+    let (x, y) = (global_tile_index.x as f64*self.frequency, global_tile_index.y as f64*self.frequency);
+    let octaves = self.octaves.unwrap_or(1);
+    match self.fractal{
+      Fractal::Fbm => noise.fbm(octaves).get(x, y),
+      Fractal::Ridged => noise.ridged(octaves).get(x, y),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BiomeRules{
+  pub name: String,
+  pub climate: Climate,
+}
+
+/// Condition on the tile of another layer at the same position.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LayerTiles{
+  pub layer: String,
+  pub tiles: Vec<u32>,
+}
+
+/// Rule of a layer, applies when all of its conditions are met.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default)]
+pub struct TileRule{
+  /// Name of a noise source, whose value must be in `[min, max)`.
+  pub noise: Option<String>,
+  pub min: Option<f64>,
+  pub max: Option<f64>,
+  pub biome: Option<String>,
+  /// Tile of another layer, the layer must be one of the tilemap's `LayerDependencies`.
+  pub on: Option<LayerTiles>,
+  /// Chance of the rule applying once the other conditions are met.
+  pub chance: Option<f64>,
+  /// Variant table to pick the tile from, no tile if `None`.
+  pub variants: Option<String>,
+}
+
+/// Generation rules of one or more layers, loaded from `*.rules.ron` files.
+///
+/// Every tile of a layer gets the tile of the first of its `layers` rules that applies, or no tile if none does.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, TypeUuid)]
+#[uuid = "5b1c8f8e-2a5e-4c1f-9a1b-6f4f0f6d2c37"]
+#[serde(default)]
+pub struct GenerationRules{
+  pub noise: HashMap<String, NoiseSource>,
+  pub variants: HashMap<String, TileVariants>,
+  pub biomes: Vec<BiomeRules>,
+  /// Biome map frequency, see `BiomeMap::frequency`.
+  pub biome_frequency: Option<f64>,
+  pub layers: HashMap<String, Vec<TileRule>>,
+}
+
+impl GenerationRules{
+  pub fn biome_map(&self, world_seed: u64)->Option<BiomeMap>{
+    if self.biomes.is_empty() {
+      return None;
+    }
+    let biomes = self.biomes.iter().map(|biome| Biome{
+      name: biome.name.clone(),
+      climate: biome.climate,
+      ..Default::default()
+    }).collect();
+    let map = BiomeMap::new(world_seed, biomes);
+    Some(match self.biome_frequency{
+      Some(frequency) => map.with_frequency(frequency),
+      None => map,
+    })
+  }
+
+  /// `rule_seed` keeps the `chance` rolls of different rules apart.
+  fn applies(
+    &self,
+    rule: &TileRule,
+    rule_seed: u64,
+    world_seed: u64,
+    global_tile_index: IVec2,
+    biome: Option<&str>,
+    dependency_tile: impl Fn(&str)->Option<u32>,
+  )->bool{
+    if let Some(name) = &rule.noise {
+      let value = match self.noise.get(name){
+        Some(source) => source.get(world_seed, name, global_tile_index),
+        None => return false,
+      };
+      if matches!(rule.min, Some(min) if value < min) || matches!(rule.max, Some(max) if value >= max) {
+        return false;
+      }
+    }
+    if rule.biome.is_some() && rule.biome.as_deref() != biome {
+      return false;
+    }
+    if let Some(on) = &rule.on {
+      match dependency_tile(&on.layer){
+        Some(tile) if on.tiles.contains(&tile) => {}
+        _ => return false,
+      }
+    }
+    match rule.chance{
+      Some(chance) => SeededRng::for_tile(world_seed, rule_seed, global_tile_index).gen::<f64>() < chance,
+      None => true
+    }
+  }
+
+  /// Picks a tile from a variant table, see `TileVariants::pick`.
+  pub fn pick_variant(&self, variants: &str, layer: &str, world_seed: u64, global_tile_index: IVec2)->Option<u32>{
+    self.variants.get(variants)?.pick(world_seed, layer_id(layer), global_tile_index)
+  }
+
+  /// Generates a chunk of `layer`. `dependencies` holds the data of other layers for the same chunk, by layer name.
+  pub fn generate_chunk(
+    &self,
+    layer: &str,
+    world_seed: u64,
+    chunk_index: IVec2,
+    chunk_size: UVec2,
+    anchor: ChunkAnchor,
+    dependencies: &HashMap<String, &ChunkData>,
+  )->ChunkData{
+    let mut data = ChunkData::new(chunk_size);
+    let rules = match self.layers.get(layer){
+      Some(rules) => rules,
+      None => return data,
+    };
+    let biomes = self.biome_map(world_seed);
+    for y in 0..chunk_size.y{
+      for x in 0..chunk_size.x{
+        let local_tile_index = UVec2::new(x, y);
+        let global_tile_index = local_tile_index_to_global(chunk_index, chunk_size, local_tile_index.as_ivec2(), anchor);
+        let biome = biomes.as_ref()
+          .and_then(|biomes| biomes.biome_at(global_tile_index).map(|biome_id| biomes.biome(biome_id).name.as_str()));
+        let dependency_tile = |layer: &str| dependencies.get(layer).and_then(|data| data.get(local_tile_index));
+        let rule = rules.iter().enumerate()
+          .find(|&(rule_index, rule)|{
+            let rule_seed = layer_id(layer).wrapping_add(rule_index as u64 + 1);
+            self.applies(rule, rule_seed, world_seed, global_tile_index, biome, dependency_tile)
+          });
+        if let Some((_, rule)) = rule {
+          let tile = rule.variants.as_ref().and_then(|variants| self.pick_variant(variants, layer, world_seed, global_tile_index));
+          data.set(local_tile_index, tile);
+        }
+      }
+    }
+    data
+  }
+}
+
+#[derive(Default)]
+pub struct GenerationRulesLoader;
+
+impl AssetLoader for GenerationRulesLoader{
+  fn load<'a>(
+    &'a self,
+    bytes: &'a [u8],
+    load_context: &'a mut LoadContext,
+  )->BoxedFuture<'a, Result<(), bevy::asset::Error>>{
+    Box::pin(async move {
+      let rules: GenerationRules = ron::de::from_bytes(bytes)?;
+      load_context.set_default_asset(LoadedAsset::new(rules));
+      Ok(())
+    })
+  }
+
+  fn extensions(&self)->&[&str]{
+    &["rules.ron"]
+  }
+}
+
+/// Generates the chunks of a tilemap from the `layer` rules of a `GenerationRules` asset.
+#[derive(Component)]
+pub struct TilemapRules{
+  pub handle: Handle<GenerationRules>,
+  pub layer: String,
+  /// Chunks waiting for the asset to load.
+  pending: Vec<PrepareChunkEvent>,
+}
+
+impl TilemapRules{
+  pub fn new(handle: Handle<GenerationRules>, layer: &str)->TilemapRules{
+    TilemapRules{
+      handle,
+      layer: layer.to_string(),
+      pending: vec![],
+    }
+  }
+}
+
+pub fn generate_from_rules(
+  mut er_prepare_chunk: EventReader<PrepareChunkEvent>,
+  mut ew_fill_chunk: EventWriter<FillChunkEvent>,
+  rules: Res<Assets<GenerationRules>>,
+  world_seed: Option<Res<WorldSeed>>,
+  mut q_tilemaps: Query<(Entity, &ChunkedTilemap, &mut TilemapRules, Option<&AuthoredRegions>)>,
+){
+  let world_seed = world_seed.map_or(0, |seed| seed.0);
+  let layer_names: HashMap<Entity, String> = q_tilemaps.iter().map(|(entity, _, rules, _)| (entity, rules.layer.clone())).collect();
+  for event in er_prepare_chunk.iter(){
+    if let Ok((_, _, mut tilemap_rules, _)) = q_tilemaps.get_mut(event.tilemap_entity){
+      tilemap_rules.pending.push(event.clone());
+    }
+  }
+  for (_, tilemap, mut tilemap_rules, authored) in q_tilemaps.iter_mut(){
+    let rules = match rules.get(&tilemap_rules.handle){
+      Some(rules) => rules,
+      None => continue
+    };
+    for event in std::mem::take(&mut tilemap_rules.pending){
+      // chunk was despawned while waiting
+      if tilemap.chunk_entities.get(&event.chunk_index) != Some(&event.chunk_entity) {
+        continue;
+      }
+      let dependencies = event.dependencies.iter()
+        .filter_map(|(entity, data)| Some((layer_names.get(entity)?.clone(), data)))
+        .collect();
+      let chunk_index = tilemap.wrap_chunk(event.chunk_index);
+      let generate = || rules.generate_chunk(&tilemap_rules.layer, world_seed, chunk_index, tilemap.chunk_size, tilemap.anchor, &dependencies);
+      let data = match authored{
+        Some(authored) => authored.serve(chunk_index, tilemap.chunk_size, tilemap.anchor, world_seed, layer_id(&tilemap_rules.layer), generate),
+        None => generate(),
+      };
+      ew_fill_chunk.send(FillChunkEvent{
+        bundles: data.to_bundles(),
+        chunk_index: event.chunk_index,
+        chunk_entity: event.chunk_entity,
+      });
+    }
+  }
+}
+
+/// Regenerates the loaded chunks of tilemaps whose rules changed, and of the layers depending on them.
+pub fn reload_generation_rules(
+  mut commands: Commands,
+  mut er_asset: EventReader<AssetEvent<GenerationRules>>,
+  mut q_tilemaps: Query<(Entity, &mut ChunkedTilemap, Option<&TilemapRules>, Option<&LayerDependencies>, Option<&mut GenerationPipeline>)>,
+){
+  let modified: Vec<Handle<GenerationRules>> = er_asset.iter().filter_map(|event| match event{
+    AssetEvent::Modified{handle} => Some(handle.clone()),
+    _ => None,
+  }).collect();
+  if modified.is_empty() {
+    return;
+  }
+
+  let mut regenerate: HashSet<Entity> = q_tilemaps.iter()
+    .filter(|(_, _, rules, _, _)| matches!(rules, Some(rules) if modified.contains(&rules.handle)))
+    .map(|(entity, ..)| entity)
+    .collect();
+  loop {
+    let dependents: Vec<Entity> = q_tilemaps.iter()
+      .filter(|(entity, _, _, dependencies, _)|{
+        !regenerate.contains(entity) && matches!(dependencies, Some(dependencies) if dependencies.layers.iter().any(|layer| regenerate.contains(layer)))
+      })
+      .map(|(entity, ..)| entity)
+      .collect();
+    if dependents.is_empty() {
+      break;
+    }
+    regenerate.extend(dependents);
+  }
+
+  for entity in regenerate{
+    if let Ok((_, mut tilemap, _, _, pipeline)) = q_tilemaps.get_mut(entity){
+      info!("regenerating chunks of {:?}", entity);
+      tilemap.despawn_chunks(&mut commands);
+      if let Some(mut pipeline) = pipeline {
+        pipeline.clear();
+      }
+    }
+  }
+}
+
+/// Loads `GenerationRules` assets and generates the chunks of tilemaps with `TilemapRules`.
+pub struct GenerationRulesPlugin;
+
+impl Plugin for GenerationRulesPlugin{
+  fn build(&self, app: &mut App){
+    app
+      .add_asset::<GenerationRules>()
+      .init_asset_loader::<GenerationRulesLoader>()
+      .add_system(generate_from_rules)
+      .add_system(reload_generation_rules);
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use std::collections::HashMap;
+  use crate::{bundle::ChunkAnchor, chunk_data::ChunkData};
+  use super::GenerationRules;
+
+  const RULES: &str = r#"
+    #![enable(implicit_some)]
+    (
+      noise: {
+        "height": (kind: Perlin, frequency: 0.1, octaves: 3),
+      },
+      variants: {
+        "grass": [3, (tile: 5, weight: 3.)],
+        "water": (tiles: [30, 31], avoid_neighbors: true),
+        "trees": [(tile: 1)],
+      },
+      layers: {
+        "ground": [
+          (noise: "height", max: -0.1, variants: "water"),
+          (variants: "grass"),
+        ],
+        "trees": [
+          (on: (layer: "ground", tiles: [30])),
+          (chance: 0.5, variants: "trees"),
+        ],
+      },
+    )
+  "#;
+
+  fn rules()->GenerationRules{
+    ron::de::from_str(RULES).unwrap()
+  }
+
+  const CHUNK_SIZE: UVec2 = UVec2{x: 8, y: 8};
+
+  #[test]
+  fn parse_test(){
+    let rules = rules();
+    assert_eq!(rules.variants["grass"].variants[0].weight, 1.);
+    assert_eq!(rules.noise["height"].octaves, Some(3));
+    assert_eq!(rules.layers["trees"].len(), 2);
+    assert_eq!(rules.layers["trees"][0].variants, None);
+  }
+
+  #[test]
+  fn ground_rules_test(){
+    let rules = rules();
+    let data = rules.generate_chunk("ground", 1, IVec2::new(2, 3), CHUNK_SIZE, ChunkAnchor::Center, &HashMap::new());
+    let tiles: Vec<u32> = data.iter().map(|(_, tile)| tile).collect();
+    assert_eq!(tiles.len(), 64);
+    assert!(tiles.iter().all(|tile| [3, 5, 30, 31].contains(tile)));
+    assert_eq!(data, rules.generate_chunk("ground", 1, IVec2::new(2, 3), CHUNK_SIZE, ChunkAnchor::Center, &HashMap::new()));
+    // weighted variants, tile 5 is three times as likely as tile 3
+    let grass: Vec<u32> = (0..40)
+      .flat_map(|x| rules.generate_chunk("ground", 1, IVec2::new(x, 0), CHUNK_SIZE, ChunkAnchor::Center, &HashMap::new()).tiles)
+      .flatten()
+      .filter(|&tile| tile < 30)
+      .collect();
+    let fives = grass.iter().filter(|&&tile| tile == 5).count() as f64/grass.len() as f64;
+    assert!((fives - 0.75).abs() < 0.05, "{fives}");
+  }
+
+  #[test]
+  fn layer_rules_test(){
+    let rules = rules();
+    let mut ground = ChunkData::filled(CHUNK_SIZE, 3);
+    for x in 0..8{
+      ground.set(UVec2::new(x, 0), Some(30));
+    }
+    let dependencies = HashMap::from([("ground".to_string(), &ground)]);
+    let trees = rules.generate_chunk("trees", 1, IVec2::ZERO, CHUNK_SIZE, ChunkAnchor::Center, &dependencies);
+    assert!((0..8).all(|x| trees.get(UVec2::new(x, 0)).is_none()));
+    let count = trees.iter().count();
+    assert!(count > 10 && count < 46, "{count}");
+  }
+
+  #[test]
+  fn missing_layer_test(){
+    let data = rules().generate_chunk("clouds", 1, IVec2::ZERO, CHUNK_SIZE, ChunkAnchor::Center, &HashMap::new());
+    assert_eq!(data, ChunkData::new(CHUNK_SIZE));
+  }
+}
@@ -1,5 +1,5 @@
 use bevy::{prelude::*, utils::HashSet};
-use crate::{spawn_chunk::{PrepareChunkEvent, SpawnChunkEvent}, bundle::ChunkedTilemap};
+use crate::{spawn_chunk::{PrepareChunkEvent, SpawnChunkEvent}, bundle::ChunkedTilemap, layers::{ChunkedTilemapLayer, LayeredTilemap}};
 
 pub fn generate_chunk_indexes(
   current_chunk_index: IVec2,
@@ -33,14 +33,16 @@ fn prepare_event(
 
 pub fn spawn_chunks_around_current(
   mut ew_spawn_chunk: EventWriter<SpawnChunkEvent>,
-  q_tilemaps: Query<(&ChunkedTilemap, Entity)>
+  q_tilemaps: Query<(&ChunkedTilemap, Entity), (Without<ChunkedTilemapLayer>, Without<LayeredTilemap>)>
 ){
   for (tilemap, entity) in q_tilemaps.iter(){
-    generate_chunk_indexes(tilemap.current_chunk, tilemap.range as i32).iter().for_each(|index|{
-      if let Some(event) = prepare_event(&tilemap.chunks, index.clone(), entity){
-        ew_spawn_chunk.send(event);
-      }
-    });
+    generate_chunk_indexes(tilemap.current_chunk, tilemap.range as i32).iter()
+      .filter(|&&index| tilemap.contains_chunk(index))
+      .for_each(|index|{
+        if let Some(event) = prepare_event(&tilemap.chunks, index.clone(), entity){
+          ew_spawn_chunk.send(event);
+        }
+      });
   }
 }
 
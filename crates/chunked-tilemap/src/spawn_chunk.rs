@@ -1,13 +1,21 @@
 use bevy::{prelude::*, utils::Instant};
 use bevy_ecs_tilemap::{prelude::{TilemapSize, TilemapGridSize, TilemapTileSize, TilemapTexture, TilemapId}, tiles::{TileStorage, TileBundle}, TilemapBundle};
 
-use crate::{TilemapChunk, bundle::{ChunkedTilemap}, chunks::get_chunk_center};
+use crate::{TilemapChunk, bundle::{ChunkedTilemap}, chunk_data::ChunkData, layers::LayerDependencies};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PrepareChunkEvent{
   pub tilemap_entity: Entity,
   pub chunk_index: IVec2,
   pub chunk_entity: Entity,
+  /// Data the layers listed in the tilemap's `LayerDependencies` were filled with for the same chunk index.
+  pub dependencies: Vec<(Entity, ChunkData)>,
+}
+
+impl PrepareChunkEvent{
+  pub fn dependency(&self, tilemap_entity: Entity)->Option<&ChunkData>{
+    self.dependencies.iter().find(|(entity, _)| *entity == tilemap_entity).map(|(_, data)| data)
+  }
 }
 pub struct SpawnChunkEvent{
   pub tilemap_entity: Entity,
@@ -18,12 +26,15 @@ pub fn spawn_chunk(
   mut er_spawn_chunk: EventReader<SpawnChunkEvent>,
   mut ew_prepare_chunk: EventWriter<PrepareChunkEvent>,
   mut commands: Commands,
-  mut q_tilemaps: Query<&mut ChunkedTilemap>,
+  mut q_tilemaps: Query<(&mut ChunkedTilemap, Option<&mut LayerDependencies>)>,
   #[cfg(feature = "dev-labels")] asset_server: Res<AssetServer>,
 ){
   
   for event in er_spawn_chunk.iter(){
-    if let Ok(mut tilemap) = q_tilemaps.get_mut(event.tilemap_entity){
+    if let Ok((mut tilemap, dependencies)) = q_tilemaps.get_mut(event.tilemap_entity){
+      if tilemap.chunks.contains(&event.chunk_index) {
+        continue;
+      }
       let start = Instant::now();
 
       // let tilemap_entity = 
@@ -33,11 +44,7 @@ pub fn spawn_chunk(
       let grid_size = TilemapGridSize::from(tilemap.tile_size);
 
       
-      let transform = Transform::from_translation(get_chunk_center(
-        tilemap.chunk_size,
-        tilemap.tile_size,
-        event.chunk_index
-      ).extend(0.));
+      let transform = Transform::from_translation(tilemap.chunk_position(event.chunk_index).extend(0.));
       
       // debug!(target: "chunk spawner", "spawning chunk {:?} on position {:?}", event.chunk_index, transform.translation);
   
@@ -66,8 +73,8 @@ pub fn spawn_chunk(
           text: Text::from_section(format!("{}:{}", event.chunk_index.x, event.chunk_index.y), text_style.clone())
             .with_alignment(text_alignment),
           transform: Transform::from_xyz(
-            tile_size.x * (event.chunk_size.x-1) as f32 / 2.,
-            tile_size.y * (event.chunk_size.y-1) as f32 / 2.,
+            tile_size.x * (tilemap.chunk_size.x-1) as f32 / 2.,
+            tile_size.y * (tilemap.chunk_size.y-1) as f32 / 2.,
             10.
           ),
           ..default()
@@ -76,12 +83,17 @@ pub fn spawn_chunk(
       }
       
       commands.entity(event.tilemap_entity).push_children(&[chunk]);
-      tilemap.chunks.insert(event.chunk_index);
-      ew_prepare_chunk.send(PrepareChunkEvent{
-        chunk_index: event.chunk_index,
-        tilemap_entity: event.tilemap_entity,
-        chunk_entity: chunk
-      });
+      tilemap.insert_chunk(event.chunk_index, chunk);
+      if let Some(mut dependencies) = dependencies {
+        dependencies.pending.push((event.chunk_index, chunk));
+      } else {
+        ew_prepare_chunk.send(PrepareChunkEvent{
+          chunk_index: event.chunk_index,
+          tilemap_entity: event.tilemap_entity,
+          chunk_entity: chunk,
+          dependencies: vec![],
+        });
+      }
       debug!("chunk {:?}-{:?} spawn took {:?}", event.chunk_index, chunk, start.elapsed());
     }
   }
@@ -0,0 +1,168 @@
+use bevy::{
+  prelude::*,
+  asset::{AssetLoader, LoadContext, LoadedAsset},
+  reflect::TypeUuid,
+  utils::BoxedFuture,
+};
+use std::collections::HashMap;
+use serde::Deserialize;
+
+use crate::generation::{ChunkGenerator, GenerationContext};
+
+/// Small pre-authored pattern of tiles (a ruined house, a grove) to be stamped into the world,
+/// see `GenerationContext::place_stamp`.
+///
+/// Every layer holds rows of tiles from north to south, `None` tiles leave the world as it is.
+/// The tile at `anchor` (column, row) lands on the position the stamp is placed at.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, TypeUuid)]
+#[uuid = "0f5b7a43-9d1e-4c55-8b7e-2d64c1a3e9f2"]
+#[serde(default)]
+pub struct Stamp{
+  pub anchor: (i32, i32),
+  pub layers: HashMap<String, Vec<Vec<Option<u32>>>>,
+}
+
+impl Stamp{
+  pub fn new()->Stamp{
+    Stamp::default()
+  }
+
+  pub fn with_anchor(mut self, anchor: IVec2)->Stamp{
+    self.anchor = (anchor.x, anchor.y);
+    self
+  }
+
+  pub fn with_layer(mut self, layer: &str, rows: Vec<Vec<Option<u32>>>)->Stamp{
+    self.layers.insert(layer.to_string(), rows);
+    self
+  }
+
+  /// Size of the footprint, the largest of all layers.
+  pub fn size(&self)->UVec2{
+    self.layers.values().fold(UVec2::ZERO, |size, rows|{
+      let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+      size.max(UVec2::new(width as u32, rows.len() as u32))
+    })
+  }
+
+  /// Smallest and largest global tile index covered by the stamp placed at `position`.
+  pub fn footprint(&self, position: IVec2)->(IVec2, IVec2){
+    let min = position - IVec2::new(self.anchor.0, self.anchor.1);
+    (min, min + self.size().as_ivec2() - IVec2::ONE)
+  }
+
+  /// Tiles of a layer of the stamp placed at `position`, by global tile index.
+  pub fn tiles<'a>(&'a self, layer: &str, position: IVec2)->impl Iterator<Item=(IVec2, u32)> + 'a{
+    let (min, _) = self.footprint(position);
+    self.layers.get(layer).into_iter().flat_map(move |rows|{
+      rows.iter().enumerate().flat_map(move |(y, row)|{
+        row.iter().enumerate().filter_map(move |(x, tile)| tile.map(|tile| (min + IVec2::new(x as i32, y as i32), tile)))
+      })
+    })
+  }
+}
+
+/// Stamp at a fixed place of the world, as a pass of a `GenerationPipeline`.
+/// Every chunk it covers draws its own part of it, over whatever the previous passes left there.
+#[derive(Debug, Clone)]
+pub struct StampRegion{
+  pub stamp: Stamp,
+  pub layer: String,
+  pub position: IVec2,
+}
+
+impl StampRegion{
+  pub fn new(stamp: Stamp, layer: &str, position: IVec2)->StampRegion{
+    StampRegion{stamp, layer: layer.to_string(), position}
+  }
+}
+
+impl ChunkGenerator for StampRegion{
+  fn generate(&self, context: &mut GenerationContext){
+    let (min, max) = self.stamp.footprint(self.position);
+    let (chunk_min, _) = context.global_to_local(min);
+    let (chunk_max, _) = context.global_to_local(max);
+    let chunk_index = context.chunk_index;
+    if chunk_index.x < chunk_min.x || chunk_index.x > chunk_max.x || chunk_index.y < chunk_min.y || chunk_index.y > chunk_max.y {
+      return;
+    }
+    for (global_tile_index, tile) in self.stamp.tiles(&self.layer, self.position){
+      context.set_global(global_tile_index, Some(tile));
+    }
+  }
+}
+
+#[derive(Default)]
+pub struct StampLoader;
+
+impl AssetLoader for StampLoader{
+  fn load<'a>(
+    &'a self,
+    bytes: &'a [u8],
+    load_context: &'a mut LoadContext,
+  )->BoxedFuture<'a, Result<(), bevy::asset::Error>>{
+    Box::pin(async move {
+      let stamp: Stamp = ron::de::from_bytes(bytes)?;
+      load_context.set_default_asset(LoadedAsset::new(stamp));
+      Ok(())
+    })
+  }
+
+  fn extensions(&self)->&[&str]{
+    &["stamp.ron"]
+  }
+}
+
+/// Loads `Stamp` assets.
+pub struct StampPlugin;
+
+impl Plugin for StampPlugin{
+  fn build(&self, app: &mut App){
+    app
+      .add_asset::<Stamp>()
+      .init_asset_loader::<StampLoader>();
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use super::Stamp;
+
+  const STAMP: &str = r#"
+    #![enable(implicit_some)]
+    (
+      anchor: (1, 1),
+      layers: {
+        "ground": [
+          [30, 30, 30],
+          [30, None, 30],
+        ],
+        "trees": [[None, 5]],
+      },
+    )
+  "#;
+
+  #[test]
+  fn parse_test(){
+    let stamp: Stamp = ron::de::from_str(STAMP).unwrap();
+    assert_eq!(stamp, Stamp::new()
+      .with_anchor(IVec2::new(1, 1))
+      .with_layer("ground", vec![vec![Some(30), Some(30), Some(30)], vec![Some(30), None, Some(30)]])
+      .with_layer("trees", vec![vec![None, Some(5)]])
+    );
+    assert_eq!(stamp.size(), UVec2::new(3, 2));
+    assert_eq!(stamp.footprint(IVec2::new(10, 10)), (IVec2::new(9, 9), IVec2::new(11, 10)));
+  }
+
+  #[test]
+  fn tiles_test(){
+    let stamp: Stamp = ron::de::from_str(STAMP).unwrap();
+    let ground: Vec<(IVec2, u32)> = stamp.tiles("ground", IVec2::new(1, 1)).collect();
+    assert_eq!(ground.len(), 5);
+    assert_eq!(ground[0], (IVec2::new(0, 0), 30));
+    assert!(!ground.iter().any(|&(tile, _)| tile == IVec2::new(1, 1)));
+    assert_eq!(stamp.tiles("trees", IVec2::new(1, 1)).collect::<Vec<_>>(), vec![(IVec2::new(1, 0), 5)]);
+    assert_eq!(stamp.tiles("clouds", IVec2::ZERO).count(), 0);
+  }
+}
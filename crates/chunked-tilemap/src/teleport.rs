@@ -0,0 +1,185 @@
+use bevy::prelude::*;
+
+use crate::{bundle::ChunkedTilemap, layers::{ChunkedTilemapLayer, LayeredTilemap}, spawn_around::generate_chunk_indexes};
+
+/// How streaming handles jumps of the center.
+pub struct TeleportSettings{
+  /// Jumps of the current chunk longer than this (in chunks) are handled as teleports,
+  /// `None` counts the jumps whose old and new areas don't overlap.
+  pub distance: Option<i32>,
+  /// Chunks of the old area unloaded per frame once the destination is ready.
+  pub unload_batch: usize,
+  /// Frames a teleport waits for the destination to fill before the old area gets unloaded anyway,
+  /// 300 by default, `None` waits as long as it takes.
+  pub settle_timeout: Option<u32>,
+}
+
+impl Default for TeleportSettings{
+  fn default()->TeleportSettings{
+    TeleportSettings{
+      distance: None,
+      unload_batch: 8,
+      settle_timeout: Some(300),
+    }
+  }
+}
+
+impl TeleportSettings{
+  pub fn is_teleport(&self, from: IVec2, to: IVec2, range: i32)->bool{
+    let distance = (to - from).abs().max_element();
+    distance > self.distance.unwrap_or(2*range)
+  }
+
+  /// Whether a teleport waited for its destination to fill for too long.
+  pub fn is_timed_out(&self, teleport: Teleport)->bool{
+    matches!(self.settle_timeout, Some(timeout) if teleport.waited >= timeout)
+  }
+}
+
+/// Jump of the center the streaming of a tilemap is catching up with. The destination area is loaded first,
+/// the old one is unloaded in batches after `StreamingSettled` was sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Teleport{
+  pub from: IVec2,
+  /// Every chunk around the destination is filled, or `TeleportSettings::settle_timeout` passed.
+  pub settled: bool,
+  /// Frames spent waiting for the destination to fill.
+  pub waited: u32,
+}
+
+impl Teleport{
+  pub fn new(from: IVec2)->Teleport{
+    Teleport{from, settled: false, waited: 0}
+  }
+
+  /// How many out of range chunks may be despawned this frame.
+  pub fn unload_budget(teleport: Option<Teleport>, settings: &TeleportSettings)->usize{
+    match teleport{
+      Some(Teleport{settled: false, ..}) => 0,
+      Some(_) => settings.unload_batch,
+      None => usize::MAX,
+    }
+  }
+}
+
+/// Moves the center of a tilemap, handled as a teleport however short the jump is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeleportEvent{
+  pub tilemap_entity: Entity,
+  pub center: Vec2,
+}
+
+/// Sent when every chunk around the destination of a teleport is filled, e.g. to end a fade.
+/// Also sent when the destination didn't fill in `TeleportSettings::settle_timeout`, with `timed_out` set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamingSettled{
+  pub tilemap_entity: Entity,
+  pub chunk_index: IVec2,
+  /// Some chunks around the destination are not filled yet.
+  pub timed_out: bool,
+}
+
+pub fn teleport_tilemaps(
+  mut er_teleport: EventReader<TeleportEvent>,
+  mut q_tilemaps: Query<&mut ChunkedTilemap>,
+){
+  for event in er_teleport.iter(){
+    if let Ok(mut tilemap) = q_tilemaps.get_mut(event.tilemap_entity){
+      tilemap.center = event.center;
+      tilemap.teleport = Some(Teleport::new(tilemap.current_chunk));
+    }
+  }
+}
+
+/// Whether every chunk in range of a tilemap is filled.
+fn is_ready(tilemap: &ChunkedTilemap)->bool{
+  generate_chunk_indexes(tilemap.current_chunk, tilemap.range).into_iter()
+    .filter(|&chunk_index| tilemap.contains_chunk(chunk_index))
+    .all(|chunk_index| tilemap.generated.contains_key(&chunk_index))
+}
+
+/// Whether a tilemap has no chunk out of range left.
+fn is_unloaded(tilemap: &ChunkedTilemap)->bool{
+  tilemap.chunks.iter().all(|&chunk_index| tilemap.in_range(chunk_index))
+}
+
+pub fn settle_teleports(
+  teleport_settings: Res<TeleportSettings>,
+  mut ew_streaming_settled: EventWriter<StreamingSettled>,
+  mut q_roots: Query<(Entity, &mut ChunkedTilemap, Option<&LayeredTilemap>), Without<ChunkedTilemapLayer>>,
+  q_layers: Query<&ChunkedTilemap, With<ChunkedTilemapLayer>>,
+){
+  for (entity, mut tilemap, layered) in q_roots.iter_mut(){
+    let teleport = match tilemap.teleport{
+      Some(teleport) => teleport,
+      None => continue
+    };
+    // a layered root only streams, the chunks live in its layers
+    let (ready, unloaded) = match layered{
+      Some(layered) => {
+        let layers: Vec<&ChunkedTilemap> = layered.layers.iter().filter_map(|&layer| q_layers.get(layer).ok()).collect();
+        (layers.iter().all(|layer| is_ready(layer)), layers.iter().all(|layer| is_unloaded(layer)))
+      }
+      None => (is_ready(&tilemap), is_unloaded(&tilemap)),
+    };
+    if !teleport.settled {
+      if ready || teleport_settings.is_timed_out(teleport) {
+        if ready {
+          debug!("streaming of {:?} settled around {} after a teleport from {}", entity, tilemap.current_chunk, teleport.from);
+        } else {
+          warn!("streaming of {:?} didn't settle around {} in {} frames, unloading the old area anyway", entity, tilemap.current_chunk, teleport.waited);
+        }
+        ew_streaming_settled.send(StreamingSettled{
+          tilemap_entity: entity,
+          chunk_index: tilemap.current_chunk,
+          timed_out: !ready,
+        });
+        tilemap.teleport = Some(Teleport{settled: true, ..teleport});
+      } else {
+        tilemap.teleport = Some(Teleport{waited: teleport.waited + 1, ..teleport});
+      }
+    } else if unloaded {
+      tilemap.teleport = None;
+    }
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use rstest::rstest;
+  use super::{Teleport, TeleportSettings};
+
+  #[rstest]
+  #[case(None, IVec2::new(2, 0), false)]
+  #[case(None, IVec2::new(3, -1), true)]
+  #[case(Some(10), IVec2::new(3, -1), false)]
+  #[case(Some(10), IVec2::new(0, 11), true)]
+  fn is_teleport_test(
+    #[case] distance: Option<i32>,
+    #[case] to: IVec2,
+    #[case] expected: bool,
+  ){
+    let settings = TeleportSettings{distance, ..Default::default()};
+    assert_eq!(settings.is_teleport(IVec2::ZERO, to, 1), expected);
+  }
+
+  #[test]
+  fn unload_budget_test(){
+    let settings = TeleportSettings::default();
+    let teleport = Teleport::new(IVec2::ZERO);
+    assert_eq!(Teleport::unload_budget(Some(teleport), &settings), 0);
+    assert_eq!(Teleport::unload_budget(Some(Teleport{settled: true, ..teleport}), &settings), settings.unload_batch);
+    assert_eq!(Teleport::unload_budget(None, &settings), usize::MAX);
+  }
+
+  #[test]
+  fn is_timed_out_test(){
+    let teleport = Teleport::new(IVec2::ZERO);
+    let settings = TeleportSettings{settle_timeout: Some(3), ..Default::default()};
+    assert!(!settings.is_timed_out(Teleport{waited: 2, ..teleport}));
+    assert!(settings.is_timed_out(Teleport{waited: 3, ..teleport}));
+    let settings = TeleportSettings{settle_timeout: None, ..Default::default()};
+    assert!(!settings.is_timed_out(Teleport{waited: u32::MAX, ..teleport}));
+  }
+}
@@ -0,0 +1,461 @@
+use bevy::{
+  prelude::*,
+  asset::{AssetLoader, LoadContext, LoadedAsset},
+  reflect::TypeUuid,
+  utils::BoxedFuture,
+};
+use std::{collections::HashMap, fmt, path::Path};
+
+use crate::stamps::Stamp;
+
+/// Bits of a gid telling how the tile is flipped or rotated, they are ignored.
+const FLIP_FLAGS: u32 = 0xf000_0000;
+
+#[derive(Debug)]
+pub enum TiledError{
+  Xml(roxmltree::Error),
+  Io(std::io::Error),
+  /// Missing or malformed attribute or tile data.
+  Format(String),
+}
+
+impl fmt::Display for TiledError{
+  fn fmt(&self, f: &mut fmt::Formatter)->fmt::Result{
+    match self{
+      TiledError::Xml(error) => write!(f, "invalid xml: {error}"),
+      TiledError::Io(error) => write!(f, "can't read file: {error}"),
+      TiledError::Format(error) => write!(f, "invalid Tiled data: {error}"),
+    }
+  }
+}
+
+impl std::error::Error for TiledError{}
+
+impl From<roxmltree::Error> for TiledError{
+  fn from(error: roxmltree::Error)->Self{
+    TiledError::Xml(error)
+  }
+}
+
+impl From<std::io::Error> for TiledError{
+  fn from(error: std::io::Error)->Self{
+    TiledError::Io(error)
+  }
+}
+
+fn attribute<T: std::str::FromStr>(node: roxmltree::Node, name: &str)->Result<T, TiledError>{
+  node.attribute(name)
+    .and_then(|value| value.parse().ok())
+    .ok_or_else(|| TiledError::Format(format!("missing or invalid attribute `{name}` of <{}>", node.tag_name().name())))
+}
+
+fn attribute_or<T: std::str::FromStr>(node: roxmltree::Node, name: &str, default: T)->Result<T, TiledError>{
+  match node.attribute(name){
+    Some(_) => attribute(node, name),
+    None => Ok(default),
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TiledTileset{
+  pub name: String,
+  pub first_gid: u32,
+  pub tile_count: u32,
+  pub columns: u32,
+  pub image: Option<String>,
+  /// Path of the .tsx file the tileset is kept in, relative to the map. Its fields are set once it is loaded.
+  pub source: Option<String>,
+}
+
+impl TiledTileset{
+  /// Parses a .tsx file.
+  pub fn parse_tsx(xml: &str, first_gid: u32)->Result<TiledTileset, TiledError>{
+    let document = roxmltree::Document::parse(xml)?;
+    TiledTileset::from_node(document.root_element(), first_gid)
+  }
+
+  fn from_node(node: roxmltree::Node, first_gid: u32)->Result<TiledTileset, TiledError>{
+    Ok(TiledTileset{
+      name: node.attribute("name").unwrap_or_default().to_string(),
+      first_gid,
+      tile_count: attribute_or(node, "tilecount", 0)?,
+      columns: attribute_or(node, "columns", 0)?,
+      image: node.children()
+        .find(|child| child.has_tag_name("image"))
+        .and_then(|image| image.attribute("source"))
+        .map(|source| source.to_string()),
+      source: None,
+    })
+  }
+}
+
+/// Tile layer of a Tiled map, with gids in rows from north to south, 0 being an empty tile.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TiledLayer{
+  pub name: String,
+  /// Tile of the map the first gid belongs to, only infinite maps have layers not starting at the origin.
+  pub offset: IVec2,
+  pub size: UVec2,
+  pub gids: Vec<u32>,
+}
+
+impl TiledLayer{
+  pub fn gid(&self, position: UVec2)->u32{
+    if position.x >= self.size.x || position.y >= self.size.y {
+      return 0;
+    }
+    self.gids[(position.y*self.size.x + position.x) as usize]
+  }
+}
+
+/// Map made in Tiled, loaded from a .tmx file together with the .tsx files of its tilesets.
+#[derive(Debug, Clone, Default, PartialEq, TypeUuid)]
+#[uuid = "5c2e1f0a-7b3d-4e8a-9f61-0d4b8a2c7e35"]
+pub struct TiledMap{
+  pub size: UVec2,
+  pub tile_size: UVec2,
+  pub tilesets: Vec<TiledTileset>,
+  pub layers: Vec<TiledLayer>,
+}
+
+impl TiledMap{
+  /// Parses a .tmx file, tilesets kept in .tsx files only get their `source` and `first_gid`.
+  pub fn parse(xml: &str)->Result<TiledMap, TiledError>{
+    let document = roxmltree::Document::parse(xml)?;
+    let root = document.root_element();
+    let mut map = TiledMap{
+      size: UVec2::new(attribute(root, "width")?, attribute(root, "height")?),
+      tile_size: UVec2::new(attribute_or(root, "tilewidth", 0)?, attribute_or(root, "tileheight", 0)?),
+      ..Default::default()
+    };
+    for node in root.descendants(){
+      if node.has_tag_name("tileset") && node.parent() == Some(root) {
+        let first_gid = attribute(node, "firstgid")?;
+        map.tilesets.push(match node.attribute("source"){
+          Some(source) => TiledTileset{
+            first_gid,
+            source: Some(source.to_string()),
+            ..Default::default()
+          },
+          None => TiledTileset::from_node(node, first_gid)?,
+        });
+      }
+      if node.has_tag_name("layer") {
+        map.layers.push(parse_layer(node)?);
+      }
+    }
+    map.tilesets.sort_by_key(|tileset| tileset.first_gid);
+    Ok(map)
+  }
+
+  /// Reads a .tmx file and the .tsx files of its tilesets from disk.
+  pub fn from_file(path: impl AsRef<Path>)->Result<TiledMap, TiledError>{
+    let path = path.as_ref();
+    let mut map = TiledMap::parse(&std::fs::read_to_string(path)?)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    for tileset in map.tilesets.iter_mut(){
+      if let Some(source) = tileset.source.clone() {
+        *tileset = TiledTileset::parse_tsx(&std::fs::read_to_string(directory.join(&source))?, tileset.first_gid)?;
+        tileset.source = Some(source);
+      }
+    }
+    Ok(map)
+  }
+
+  /// Tileset a gid belongs to.
+  pub fn tileset(&self, gid: u32)->Option<&TiledTileset>{
+    let gid = gid & !FLIP_FLAGS;
+    if gid == 0 {
+      return None;
+    }
+    self.tilesets.iter().rev().find(|tileset| tileset.first_gid <= gid)
+  }
+
+  pub fn layer(&self, name: &str)->Option<&TiledLayer>{
+    self.layers.iter().find(|layer| layer.name == name)
+  }
+
+  /// Stamp with a layer for each tile layer of the map, named the same.
+  /// Tile (0, 0) of the map lands on the position the stamp is placed at.
+  pub fn to_stamp(&self, mapping: &GidMapping)->Stamp{
+    let min = self.layers.iter().fold(IVec2::ZERO, |min, layer| min.min(layer.offset));
+    let max = self.layers.iter().fold(self.size.as_ivec2(), |max, layer| max.max(layer.offset + layer.size.as_ivec2()));
+    let mut stamp = Stamp::new().with_anchor(-min);
+    for layer in self.layers.iter(){
+      let rows = (min.y..max.y).map(|y|{
+        (min.x..max.x).map(|x|{
+          let position = IVec2::new(x, y) - layer.offset;
+          if position.x < 0 || position.y < 0 {
+            return None;
+          }
+          mapping.texture(self, layer.gid(position.as_uvec2()))
+        }).collect()
+      }).collect();
+      stamp.layers.insert(layer.name.clone(), rows);
+    }
+    stamp
+  }
+}
+
+fn parse_layer(node: roxmltree::Node)->Result<TiledLayer, TiledError>{
+  let name = node.attribute("name").unwrap_or_default().to_string();
+  let data = node.children()
+    .find(|child| child.has_tag_name("data"))
+    .ok_or_else(|| TiledError::Format(format!("layer `{name}` has no data")))?;
+  let encoding = data.attribute("encoding");
+  if data.attribute("compression").is_some() || !matches!(encoding, None | Some("csv")) {
+    return Err(TiledError::Format(format!("layer `{name}` is not saved as CSV or XML")));
+  }
+  let chunks: Vec<roxmltree::Node> = data.children().filter(|child| child.has_tag_name("chunk")).collect();
+  if chunks.is_empty() {
+    let size = UVec2::new(attribute(node, "width")?, attribute(node, "height")?);
+    let gids = parse_gids(data, encoding, size)?;
+    return Ok(TiledLayer{name, offset: IVec2::ZERO, size, gids});
+  }
+
+  // infinite maps keep their layers in chunks
+  let mut parsed = vec![];
+  for chunk in chunks{
+    let offset = IVec2::new(attribute(chunk, "x")?, attribute(chunk, "y")?);
+    let size = UVec2::new(attribute(chunk, "width")?, attribute(chunk, "height")?);
+    parsed.push((offset, size, parse_gids(chunk, encoding, size)?));
+  }
+  let min = parsed.iter().fold(IVec2::splat(i32::MAX), |min, (offset, ..)| min.min(*offset));
+  let max = parsed.iter().fold(IVec2::splat(i32::MIN), |max, (offset, size, _)| max.max(*offset + size.as_ivec2()));
+  let size = (max - min).as_uvec2();
+  let mut gids = vec![0; (size.x*size.y) as usize];
+  for (offset, chunk_size, chunk_gids) in parsed{
+    let start = (offset - min).as_uvec2();
+    for (i, gid) in chunk_gids.into_iter().enumerate(){
+      let position = start + UVec2::new(i as u32 % chunk_size.x, i as u32 / chunk_size.x);
+      gids[(position.y*size.x + position.x) as usize] = gid;
+    }
+  }
+  Ok(TiledLayer{name, offset: min, size, gids})
+}
+
+fn parse_gids(data: roxmltree::Node, encoding: Option<&str>, size: UVec2)->Result<Vec<u32>, TiledError>{
+  let gids: Vec<u32> = if encoding == Some("csv") {
+    data.text().unwrap_or_default()
+      .split(',')
+      .map(|gid| gid.trim().parse().map_err(|_| TiledError::Format(format!("invalid gid `{}`", gid.trim()))))
+      .collect::<Result<_, _>>()?
+  } else {
+    data.children()
+      .filter(|child| child.has_tag_name("tile"))
+      .map(|tile| attribute_or(tile, "gid", 0))
+      .collect::<Result<_, _>>()?
+  };
+  if gids.len() != (size.x*size.y) as usize {
+    return Err(TiledError::Format(format!("expected {} gids, found {}", size.x*size.y, gids.len())));
+  }
+  Ok(gids)
+}
+
+/// Maps Tiled gids to `TileTexture` indices of a `ChunkedTilemap` layer.
+///
+/// Tiles of a tileset get consecutive textures starting at the one set with `with_tileset`, or at 0.
+/// Single gids can be mapped to any texture with `with_tile`.
+#[derive(Debug, Clone, Default)]
+pub struct GidMapping{
+  pub tilesets: HashMap<String, u32>,
+  pub tiles: HashMap<u32, Option<u32>>,
+}
+
+impl GidMapping{
+  pub fn new()->GidMapping{
+    GidMapping::default()
+  }
+
+  pub fn with_tileset(mut self, name: &str, first_texture: u32)->GidMapping{
+    self.tilesets.insert(name.to_string(), first_texture);
+    self
+  }
+
+  /// Maps a gid to a texture, or to no tile at all.
+  pub fn with_tile(mut self, gid: u32, texture: Option<u32>)->GidMapping{
+    self.tiles.insert(gid, texture);
+    self
+  }
+
+  pub fn texture(&self, map: &TiledMap, gid: u32)->Option<u32>{
+    let gid = gid & !FLIP_FLAGS;
+    if let Some(&texture) = self.tiles.get(&gid) {
+      return texture;
+    }
+    let tileset = map.tileset(gid)?;
+    let first_texture = self.tilesets.get(&tileset.name).copied().unwrap_or(0);
+    Some(first_texture + gid - tileset.first_gid)
+  }
+}
+
+#[derive(Default)]
+pub struct TiledMapLoader;
+
+impl AssetLoader for TiledMapLoader{
+  fn load<'a>(
+    &'a self,
+    bytes: &'a [u8],
+    load_context: &'a mut LoadContext,
+  )->BoxedFuture<'a, Result<(), bevy::asset::Error>>{
+    Box::pin(async move {
+      let mut map = TiledMap::parse(std::str::from_utf8(bytes)?)?;
+      let directory = load_context.path().parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+      for tileset in map.tilesets.iter_mut(){
+        if let Some(source) = tileset.source.clone() {
+          let bytes = load_context.read_asset_bytes(directory.join(&source)).await?;
+          *tileset = TiledTileset::parse_tsx(std::str::from_utf8(&bytes)?, tileset.first_gid)?;
+          tileset.source = Some(source);
+        }
+      }
+      load_context.set_default_asset(LoadedAsset::new(map));
+      Ok(())
+    })
+  }
+
+  fn extensions(&self)->&[&str]{
+    &["tmx"]
+  }
+}
+
+/// Loads `TiledMap` assets, to be turned into stamps or `StampRegion`s once loaded.
+pub struct TiledPlugin;
+
+impl Plugin for TiledPlugin{
+  fn build(&self, app: &mut App){
+    app
+      .add_asset::<TiledMap>()
+      .init_asset_loader::<TiledMapLoader>();
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use crate::{bundle::ChunkAnchor, generation::GenerationPipeline, stamps::StampRegion};
+  use super::{GidMapping, TiledMap, TiledTileset};
+
+  const MAP: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.9" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16" infinite="0">
+  <tileset firstgid="1" name="grass" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+    <image source="grass.png" width="32" height="32"/>
+  </tileset>
+  <tileset firstgid="5" source="trees.tsx"/>
+  <layer id="1" name="ground" width="3" height="2">
+    <data encoding="csv">
+1,2,3,
+4,0,2147483649
+</data>
+  </layer>
+  <group name="decorations">
+    <layer id="2" name="trees" width="3" height="2">
+      <data>
+        <tile/><tile gid="6"/><tile/>
+        <tile/><tile/><tile/>
+      </data>
+    </layer>
+  </group>
+</map>"#;
+
+  const TSX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.9" name="trees" tilewidth="16" tileheight="16" tilecount="20" columns="5">
+  <image source="trees.png" width="80" height="64"/>
+</tileset>"#;
+
+  const INFINITE: &str = r#"<map width="10" height="10" tilewidth="16" tileheight="16" infinite="1">
+  <tileset firstgid="1" name="grass" tilecount="4" columns="2"/>
+  <layer name="ground" width="10" height="10">
+    <data encoding="csv">
+      <chunk x="-2" y="0" width="2" height="1">1,2</chunk>
+      <chunk x="0" y="1" width="2" height="1">3,4</chunk>
+    </data>
+  </layer>
+</map>"#;
+
+  fn map()->TiledMap{
+    let mut map = TiledMap::parse(MAP).unwrap();
+    map.tilesets[1] = TiledTileset::parse_tsx(TSX, 5).unwrap();
+    map
+  }
+
+  #[test]
+  fn parse_test(){
+    let map = TiledMap::parse(MAP).unwrap();
+    assert_eq!(map.size, UVec2::new(3, 2));
+    assert_eq!(map.tile_size, UVec2::new(16, 16));
+    assert_eq!(map.tilesets[0].name, "grass");
+    assert_eq!(map.tilesets[0].image.as_deref(), Some("grass.png"));
+    assert_eq!(map.tilesets[1].source.as_deref(), Some("trees.tsx"));
+    assert_eq!(map.layers.len(), 2);
+    assert_eq!(map.layer("ground").unwrap().gids, vec![1, 2, 3, 4, 0, 0x8000_0001]);
+    assert_eq!(map.layer("trees").unwrap().gid(UVec2::new(1, 0)), 6);
+    assert_eq!(map.layer("trees").unwrap().gid(UVec2::new(5, 0)), 0);
+  }
+
+  #[test]
+  fn tsx_test(){
+    let tileset = TiledTileset::parse_tsx(TSX, 5).unwrap();
+    assert_eq!(tileset.name, "trees");
+    assert_eq!(tileset.tile_count, 20);
+    assert_eq!(tileset.columns, 5);
+    assert_eq!(tileset.image.as_deref(), Some("trees.png"));
+  }
+
+  #[test]
+  fn invalid_map_test(){
+    assert!(TiledMap::parse("<map").is_err());
+    assert!(TiledMap::parse(r#"<map width="1" height="1"><layer name="a" width="1" height="1"><data encoding="base64" compression="zlib">eJw=</data></layer></map>"#).is_err());
+    assert!(TiledMap::parse(r#"<map width="2" height="1"><layer name="a" width="2" height="1"><data encoding="csv">1</data></layer></map>"#).is_err());
+  }
+
+  #[test]
+  fn mapping_test(){
+    let map = map();
+    let mapping = GidMapping::new().with_tileset("trees", 100).with_tile(3, None);
+    assert_eq!(mapping.texture(&map, 0), None);
+    assert_eq!(mapping.texture(&map, 1), Some(0));
+    // flipped
+    assert_eq!(mapping.texture(&map, 0x8000_0002), Some(1));
+    assert_eq!(mapping.texture(&map, 3), None);
+    assert_eq!(mapping.texture(&map, 6), Some(101));
+  }
+
+  #[test]
+  fn stamp_test(){
+    let stamp = map().to_stamp(&GidMapping::new().with_tileset("trees", 100));
+    assert_eq!(stamp.anchor, (0, 0));
+    assert_eq!(stamp.layers["ground"], vec![vec![Some(0), Some(1), Some(2)], vec![Some(3), None, Some(0)]]);
+    assert_eq!(stamp.layers["trees"], vec![vec![None, Some(101), None], vec![None, None, None]]);
+  }
+
+  #[test]
+  fn infinite_map_test(){
+    let map = TiledMap::parse(INFINITE).unwrap();
+    let layer = map.layer("ground").unwrap();
+    assert_eq!(layer.offset, IVec2::new(-2, 0));
+    assert_eq!(layer.size, UVec2::new(4, 2));
+    assert_eq!(layer.gids, vec![1, 2, 0, 0, 0, 0, 3, 4]);
+    let stamp = map.to_stamp(&GidMapping::new());
+    assert_eq!(stamp.anchor, (2, 0));
+    let tiles: Vec<(IVec2, u32)> = stamp.tiles("ground", IVec2::ZERO).collect();
+    assert_eq!(tiles, vec![(IVec2::new(-2, 0), 0), (IVec2::new(-1, 0), 1), (IVec2::new(0, 1), 2), (IVec2::new(1, 1), 3)]);
+  }
+
+  #[test]
+  fn region_test(){
+    let stamp = map().to_stamp(&GidMapping::new().with_tileset("trees", 100));
+    let mut pipeline = GenerationPipeline::new()
+      .with_pass(|context: &mut crate::generation::GenerationContext| context.data = crate::chunk_data::ChunkData::filled(context.chunk_size, 50))
+      .with_pass(StampRegion::new(stamp, "ground", IVec2::new(-1, 0)));
+    let chunk_size = UVec2::new(2, 2);
+    // the region's west column falls into chunk (-1, 0)
+    let west = pipeline.generate(IVec2::new(-1, 0), chunk_size, ChunkAnchor::Corner);
+    let east = pipeline.generate(IVec2::ZERO, chunk_size, ChunkAnchor::Corner);
+    let far = pipeline.generate(IVec2::new(5, 5), chunk_size, ChunkAnchor::Corner);
+    let count = |data: &crate::chunk_data::ChunkData, texture: u32| data.iter().filter(|&(_, tile)| tile == texture).count();
+    assert_eq!(count(&west, 0) + count(&east, 0), 2);
+    assert_eq!(count(&west, 50) + count(&east, 50), 3);
+    assert_eq!(west.iter().count() + east.iter().count(), 8);
+    assert_eq!(count(&far, 50), 4);
+  }
+}
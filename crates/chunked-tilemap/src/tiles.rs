@@ -0,0 +1,274 @@
+use bevy::{prelude::*, ecs::system::SystemParam};
+use bevy_ecs_tilemap::{prelude::TilemapId, tiles::{TileBundle, TilePos, TileStorage, TileTexture}};
+
+use crate::{bundle::ChunkedTilemap, geometry::{TileArea, get_tile_indexes_in_area}, TilemapChunk};
+
+/// Tile at a global tile index of a `ChunkedTilemap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalTile{
+  /// Chunk is loaded and has a tile at this index.
+  Tile(Entity),
+  /// Chunk is loaded but has no tile at this index.
+  Empty,
+  /// Chunk holding this index is not loaded (yet).
+  Unloaded,
+}
+
+impl GlobalTile{
+  pub fn entity(&self)->Option<Entity>{
+    match self{
+      GlobalTile::Tile(entity) => Some(*entity),
+      _ => None,
+    }
+  }
+
+  pub fn is_loaded(&self)->bool{
+    *self != GlobalTile::Unloaded
+  }
+}
+
+/// Direction in global tile space, where `y` grows downwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction{
+  North,
+  NorthEast,
+  East,
+  SouthEast,
+  South,
+  SouthWest,
+  West,
+  NorthWest,
+}
+
+impl Direction{
+  pub const ALL: [Direction; 8] = [
+    Direction::North,
+    Direction::NorthEast,
+    Direction::East,
+    Direction::SouthEast,
+    Direction::South,
+    Direction::SouthWest,
+    Direction::West,
+    Direction::NorthWest,
+  ];
+
+  pub const CARDINAL: [Direction; 4] = [
+    Direction::North,
+    Direction::East,
+    Direction::South,
+    Direction::West,
+  ];
+
+  pub fn offset(&self)->IVec2{
+    match self{
+      Direction::North => IVec2::new(0, -1),
+      Direction::NorthEast => IVec2::new(1, -1),
+      Direction::East => IVec2::new(1, 0),
+      Direction::SouthEast => IVec2::new(1, 1),
+      Direction::South => IVec2::new(0, 1),
+      Direction::SouthWest => IVec2::new(-1, 1),
+      Direction::West => IVec2::new(-1, 0),
+      Direction::NorthWest => IVec2::new(-1, -1),
+    }
+  }
+
+  pub fn is_diagonal(&self)->bool{
+    let offset = self.offset();
+    offset.x != 0 && offset.y != 0
+  }
+}
+
+/// Neighbors of a tile, indexed by `Direction`. Diagonal entries are `None` unless requested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileNeighbors<T>(pub [Option<T>; 8]);
+
+impl<T> TileNeighbors<T>{
+  pub fn get(&self, direction: Direction)->Option<&T>{
+    self.0[direction as usize].as_ref()
+  }
+
+  pub fn iter(&self)->impl Iterator<Item=(Direction, &T)>{
+    Direction::ALL.into_iter().zip(self.0.iter()).filter_map(|(direction, item)| item.as_ref().map(|item| (direction, item)))
+  }
+
+  pub fn map<U>(self, mut f: impl FnMut(Direction, T)->U)->TileNeighbors<U>{
+    let mut mapped = [None, None, None, None, None, None, None, None];
+    for (direction, item) in Direction::ALL.into_iter().zip(self.0.into_iter()){
+      mapped[direction as usize] = item.map(|item| f(direction, item));
+    }
+    TileNeighbors(mapped)
+  }
+}
+
+pub fn get_neighbor_indexes(global_tile_index: IVec2, diagonal: bool)->TileNeighbors<IVec2>{
+  let mut neighbors = [None; 8];
+  for direction in Direction::ALL{
+    if diagonal || !direction.is_diagonal(){
+      neighbors[direction as usize] = Some(global_tile_index + direction.offset());
+    }
+  }
+  TileNeighbors(neighbors)
+}
+
+/// Access to tiles of `ChunkedTilemap`s by global tile index, regardless of the chunk they live in.
+#[derive(SystemParam)]
+pub struct ChunkedTiles<'w, 's>{
+  q_tilemaps: Query<'w, 's, (&'static ChunkedTilemap, Option<&'static GlobalTransform>)>,
+  q_storages: Query<'w, 's, &'static TileStorage, With<TilemapChunk>>,
+}
+
+impl<'w, 's> ChunkedTiles<'w, 's>{
+  pub fn tilemap(&self, tilemap_entity: Entity)->Option<&ChunkedTilemap>{
+    self.q_tilemaps.get(tilemap_entity).ok().map(|(tilemap, _)| tilemap)
+  }
+
+  /// World position of the tilemap origin, tilemap transforms are expected to only translate.
+  pub fn tilemap_offset(&self, tilemap_entity: Entity)->Vec2{
+    match self.q_tilemaps.get(tilemap_entity){
+      Ok((_, Some(transform))) => transform.translation().truncate(),
+      _ => Vec2::ZERO,
+    }
+  }
+
+  /// World position of the center of a tile.
+  pub fn tile_position(&self, tilemap_entity: Entity, global_tile_index: IVec2)->Option<Vec2>{
+    let tilemap = self.tilemap(tilemap_entity)?;
+    Some(tilemap.tile_position(global_tile_index) + self.tilemap_offset(tilemap_entity))
+  }
+
+  pub fn chunk_entity(&self, tilemap_entity: Entity, chunk_index: IVec2)->Option<Entity>{
+    self.tilemap(tilemap_entity)?.chunk_entities.get(&chunk_index).copied()
+  }
+
+  pub fn get(&self, tilemap_entity: Entity, global_tile_index: IVec2)->GlobalTile{
+    let tilemap = match self.tilemap(tilemap_entity){
+      Some(tilemap) => tilemap,
+      None => return GlobalTile::Unloaded
+    };
+    let (chunk_index, local_tile_index) = match tilemap.loaded_tile(global_tile_index){
+      Some(tile) => tile,
+      None => return GlobalTile::Unloaded
+    };
+    let storage = match tilemap.chunk_entities.get(&chunk_index).and_then(|&chunk| self.q_storages.get(chunk).ok()){
+      Some(storage) => storage,
+      None => return GlobalTile::Unloaded
+    };
+    match storage.get(&TilePos{x: local_tile_index.x as u32, y: local_tile_index.y as u32}){
+      Some(entity) => GlobalTile::Tile(entity),
+      None => GlobalTile::Empty,
+    }
+  }
+
+  pub fn neighbors(&self, tilemap_entity: Entity, global_tile_index: IVec2, diagonal: bool)->TileNeighbors<GlobalTile>{
+    get_neighbor_indexes(global_tile_index, diagonal).map(|_, index| self.get(tilemap_entity, index))
+  }
+
+  /// Tiles whose centers are inside of a world space `area`, as `(global tile index, tile entity, world position)`.
+  /// Empty tiles and tiles of unloaded chunks are skipped.
+  pub fn iter_area(&self, tilemap_entity: Entity, area: TileArea)->impl Iterator<Item=(IVec2, Entity, Vec2)> + '_{
+    let offset = self.tilemap_offset(tilemap_entity);
+    let indexes = match self.tilemap(tilemap_entity){
+      Some(tilemap) => get_tile_indexes_in_area(area.translate(-offset), tilemap.chunk_size, tilemap.tile_size, tilemap.anchor)
+        .into_iter()
+        .map(|index| index + tilemap.origin_tile())
+        .collect(),
+      None => vec![]
+    };
+    indexes.into_iter().filter_map(move |index|{
+      let entity = self.get(tilemap_entity, index).entity()?;
+      Some((index, entity, self.tile_position(tilemap_entity, index)?))
+    })
+  }
+
+  pub fn iter_rect(&self, tilemap_entity: Entity, min: Vec2, max: Vec2)->impl Iterator<Item=(IVec2, Entity, Vec2)> + '_{
+    self.iter_area(tilemap_entity, TileArea::rect(min, max))
+  }
+
+  pub fn iter_circle(&self, tilemap_entity: Entity, center: Vec2, radius: f32)->impl Iterator<Item=(IVec2, Entity, Vec2)> + '_{
+    self.iter_area(tilemap_entity, TileArea::circle(center, radius))
+  }
+}
+
+/// Sets the tile at a global tile index of a `ChunkedTilemap`, `None` removes it.
+/// Edits of chunks that are not filled yet are dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetTileEvent{
+  pub tilemap_entity: Entity,
+  pub global_tile_index: IVec2,
+  pub tile: Option<u32>,
+}
+
+pub fn set_tiles(
+  mut commands: Commands,
+  mut er_set_tile: EventReader<SetTileEvent>,
+  mut q_tilemaps: Query<&mut ChunkedTilemap>,
+  mut q_storages: Query<&mut TileStorage, With<TilemapChunk>>,
+  mut q_textures: Query<&mut TileTexture>,
+){
+  for event in er_set_tile.iter(){
+    let mut tilemap = match q_tilemaps.get_mut(event.tilemap_entity){
+      Ok(tilemap) => tilemap,
+      Err(_) => continue
+    };
+    // a wrapping world may show the tile in several loaded chunks
+    let loaded: Vec<(IVec2, IVec2)> = tilemap.loaded_tiles(event.global_tile_index).collect();
+    for (chunk_index, local_tile_index) in loaded{
+      let chunk_entity = match tilemap.chunk_entities.get(&chunk_index){
+        Some(&chunk_entity) => chunk_entity,
+        None => continue
+      };
+      match tilemap.generated.get_mut(&chunk_index){
+        Some(data) => data.set(local_tile_index.as_uvec2(), event.tile),
+        None => continue
+      };
+      let mut storage = match q_storages.get_mut(chunk_entity){
+        Ok(storage) => storage,
+        Err(_) => continue
+      };
+      let position = TilePos{x: local_tile_index.x as u32, y: local_tile_index.y as u32};
+      match (storage.get(&position), event.tile){
+        (Some(entity), Some(tile)) => {
+          if let Ok(mut texture) = q_textures.get_mut(entity) {
+            texture.0 = tile;
+          }
+        }
+        (Some(entity), None) => {
+          commands.entity(entity).despawn_recursive();
+          storage.set(&position, None);
+        }
+        (None, Some(tile)) => {
+          commands.spawn().insert_bundle(TileBundle{
+            position,
+            texture: TileTexture(tile),
+            tilemap_id: TilemapId(chunk_entity),
+            ..Default::default()
+          });
+        }
+        (None, None) => {}
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use rstest::rstest;
+  use super::{get_neighbor_indexes, Direction};
+
+  #[rstest]
+  #[case(false, 4)]
+  #[case(true, 8)]
+  fn get_neighbor_indexes_test(
+    #[case] diagonal: bool,
+    #[case] expected_count: usize,
+  ){
+    let neighbors = get_neighbor_indexes(IVec2::new(2, -3), diagonal);
+    assert_eq!(neighbors.iter().count(), expected_count);
+    assert_eq!(neighbors.get(Direction::North), Some(&IVec2::new(2, -4)));
+    assert_eq!(neighbors.get(Direction::East), Some(&IVec2::new(3, -3)));
+    assert_eq!(neighbors.get(Direction::South), Some(&IVec2::new(2, -2)));
+    assert_eq!(neighbors.get(Direction::West), Some(&IVec2::new(1, -3)));
+    assert_eq!(neighbors.get(Direction::SouthEast).is_some(), diagonal);
+  }
+}
@@ -0,0 +1,201 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{random::SeededRng, tiles::Direction};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(from = "WeightedTileDef")]
+pub struct WeightedTile{
+  pub tile: u32,
+  pub weight: f64,
+}
+
+impl WeightedTile{
+  pub fn new(tile: u32, weight: f64)->WeightedTile{
+    WeightedTile{tile, weight}
+  }
+}
+
+fn one()->f64{
+  1.
+}
+
+/// A weighted tile is written either as `(tile: 3, weight: 2.)` or, with a weight of 1, just as `3`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WeightedTileDef{
+  Tile(u32),
+  Weighted{
+    tile: u32,
+    #[serde(default = "one")]
+    weight: f64,
+  },
+}
+
+impl From<WeightedTileDef> for WeightedTile{
+  fn from(definition: WeightedTileDef)->WeightedTile{
+    match definition{
+      WeightedTileDef::Tile(tile) => WeightedTile::new(tile, 1.),
+      WeightedTileDef::Weighted{tile, weight} => WeightedTile::new(tile, weight),
+    }
+  }
+}
+
+/// Table of interchangeable tiles, picked at random by weight.
+///
+/// With `avoid_neighbors` no two cardinally adjacent tiles picked from the same table get the same variant,
+/// given at least five variants. Every tile belongs to one of four classes by the parity of its index and only
+/// avoids the variants of its neighbors of lower classes, so a pick never depends on more than a few
+/// surrounding tiles, nor on the chunk it is made from.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(from = "TileVariantsDef")]
+pub struct TileVariants{
+  pub variants: Vec<WeightedTile>,
+  pub avoid_neighbors: bool,
+}
+
+/// Variants are written either as a plain list or as `(tiles: [..], avoid_neighbors: true)`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TileVariantsDef{
+  List(Vec<WeightedTile>),
+  Table{
+    tiles: Vec<WeightedTile>,
+    #[serde(default)]
+    avoid_neighbors: bool,
+  },
+}
+
+impl From<TileVariantsDef> for TileVariants{
+  fn from(definition: TileVariantsDef)->TileVariants{
+    match definition{
+      TileVariantsDef::List(variants) => TileVariants::new(variants),
+      TileVariantsDef::Table{tiles, avoid_neighbors} => TileVariants::new(tiles).with_avoid_neighbors(avoid_neighbors),
+    }
+  }
+}
+
+fn tile_class(global_tile_index: IVec2)->i32{
+  global_tile_index.x.rem_euclid(2) + 2*global_tile_index.y.rem_euclid(2)
+}
+
+impl TileVariants{
+  pub fn new(variants: Vec<WeightedTile>)->TileVariants{
+    TileVariants{
+      variants,
+      avoid_neighbors: false,
+    }
+  }
+
+  /// Variants with equal weights.
+  pub fn uniform(tiles: impl IntoIterator<Item=u32>)->TileVariants{
+    TileVariants::new(tiles.into_iter().map(|tile| WeightedTile::new(tile, 1.)).collect())
+  }
+
+  pub fn with_avoid_neighbors(mut self, avoid_neighbors: bool)->TileVariants{
+    self.avoid_neighbors = avoid_neighbors;
+    self
+  }
+
+  pub fn is_empty(&self)->bool{
+    self.variants.is_empty()
+  }
+
+  /// Picks a variant by weight, skipping `excluded` tiles unless nothing else is left.
+  pub fn pick_with(&self, rng: &mut impl Rng, excluded: &[u32])->Option<u32>{
+    let allowed = |variant: &&WeightedTile| variant.weight > 0. && !excluded.contains(&variant.tile);
+    if !excluded.is_empty() && !self.variants.iter().any(|variant| allowed(&variant)) {
+      return self.pick_with(rng, &[]);
+    }
+    let total: f64 = self.variants.iter().filter(allowed).map(|variant| variant.weight).sum();
+    if total <= 0. {
+      return None;
+    }
+    let mut roll = rng.gen::<f64>()*total;
+    let mut last = None;
+    for variant in self.variants.iter().filter(allowed){
+      if roll < variant.weight {
+        return Some(variant.tile);
+      }
+      roll -= variant.weight;
+      last = Some(variant.tile);
+    }
+    last
+  }
+
+  /// Picks the variant of a tile, deterministically from its per-tile seed.
+  pub fn pick(&self, world_seed: u64, layer_id: u64, global_tile_index: IVec2)->Option<u32>{
+    let mut rng = SeededRng::for_tile(world_seed, layer_id, global_tile_index);
+    if !self.avoid_neighbors {
+      return self.pick_with(&mut rng, &[]);
+    }
+    let class = tile_class(global_tile_index);
+    let excluded: Vec<u32> = Direction::CARDINAL.iter()
+      .map(|direction| global_tile_index + direction.offset())
+      .filter(|&neighbor| tile_class(neighbor) < class)
+      .filter_map(|neighbor| self.pick(world_seed, layer_id, neighbor))
+      .collect();
+    self.pick_with(&mut rng, &excluded)
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::prelude::*;
+  use crate::tiles::Direction;
+  use super::{TileVariants, WeightedTile};
+
+  #[test]
+  fn weights_test(){
+    let variants = TileVariants::new(vec![WeightedTile::new(1, 1.), WeightedTile::new(2, 3.), WeightedTile::new(3, 0.)]);
+    let picks: Vec<u32> = (0..4000).map(|i| variants.pick(7, 0, IVec2::new(i % 64, i/64)).unwrap()).collect();
+    let twos = picks.iter().filter(|&&tile| tile == 2).count() as f64/picks.len() as f64;
+    assert!((twos - 0.75).abs() < 0.03, "{twos}");
+    assert!(!picks.contains(&3));
+  }
+
+  #[test]
+  fn deterministic_test(){
+    let variants = TileVariants::uniform(0..10).with_avoid_neighbors(true);
+    for i in -20..20{
+      let tile = IVec2::new(i*7, -i*3);
+      assert_eq!(variants.pick(1, 2, tile), variants.clone().pick(1, 2, tile));
+    }
+    assert!((0..20).any(|i| variants.pick(1, 2, IVec2::new(i, 0)) != variants.pick(2, 2, IVec2::new(i, 0))));
+  }
+
+  #[rstest::rstest]
+  #[case(5)]
+  #[case(6)]
+  #[case(12)]
+  fn avoid_neighbors_test(
+    #[case] count: u32,
+  ){
+    let variants = TileVariants::uniform(0..count).with_avoid_neighbors(true);
+    for y in -15..15{
+      for x in -15..15{
+        let tile = IVec2::new(x, y);
+        let variant = variants.pick(3, 4, tile);
+        for direction in Direction::CARDINAL{
+          assert_ne!(variant, variants.pick(3, 4, tile + direction.offset()), "{tile} {direction:?}");
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn too_few_variants_test(){
+    let variants = TileVariants::uniform([5]).with_avoid_neighbors(true);
+    assert_eq!(variants.pick(0, 0, IVec2::new(1, 1)), Some(5));
+    assert_eq!(TileVariants::default().pick(0, 0, IVec2::ZERO), None);
+  }
+
+  #[test]
+  fn deserialize_test(){
+    let list: TileVariants = ron::de::from_str("[1, (tile: 4), (tile: 2, weight: 3.)]").unwrap();
+    assert_eq!(list, TileVariants::new(vec![WeightedTile::new(1, 1.), WeightedTile::new(4, 1.), WeightedTile::new(2, 3.)]));
+    let table: TileVariants = ron::de::from_str("(tiles: [1, 2], avoid_neighbors: true)").unwrap();
+    assert_eq!(table, TileVariants::uniform([1, 2]).with_avoid_neighbors(true));
+  }
+}
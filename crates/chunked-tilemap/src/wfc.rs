@@ -0,0 +1,413 @@
+use bevy::{prelude::*, utils::HashMap};
+use rand::Rng;
+
+use crate::{generation::{ChunkGenerator, GenerationContext}, random::SeededRng, tiles::Direction};
+
+fn side(direction: Direction)->usize{
+  Direction::CARDINAL.iter().position(|&cardinal| cardinal == direction).expect("only cardinal directions have adjacency rules")
+}
+
+fn opposite(side: usize)->usize{
+  (side + 2) % 4
+}
+
+/// Which tiles may be next to each other, for `WfcGenerator`. At most `WfcRules::MAX_TILES` different tiles
+/// are supported, the builder methods skip any further tile with a warning.
+#[derive(Debug, Clone, Default)]
+pub struct WfcRules{
+  tiles: Vec<u32>,
+  weights: Vec<f64>,
+  /// Per side (in `Direction::CARDINAL` order) and tile, bits of the tiles allowed on that side of it.
+  allowed: [Vec<u128>; 4],
+}
+
+impl WfcRules{
+  pub const MAX_TILES: usize = 128;
+
+  pub fn new()->WfcRules{
+    WfcRules::default()
+  }
+
+  /// Learns adjacencies and tile frequencies from an example map, given as rows from north to south.
+  /// `None` if the example has more than `MAX_TILES` different tiles.
+  pub fn from_example(rows: &[Vec<u32>])->Option<WfcRules>{
+    let mut rules = WfcRules::new();
+    let mut counts: HashMap<u32, f64> = HashMap::default();
+    for (y, row) in rows.iter().enumerate(){
+      for (x, &tile) in row.iter().enumerate(){
+        *counts.entry(tile).or_default() += 1.;
+        rules.index_or_insert(tile)?;
+        if let Some(&east) = row.get(x + 1) {
+          rules.allow(tile, Direction::East, east).then_some(())?;
+        }
+        if let Some(&south) = rows.get(y + 1).and_then(|row| row.get(x)) {
+          rules.allow(tile, Direction::South, south).then_some(())?;
+        }
+      }
+    }
+    for (tile, count) in counts{
+      let index = rules.index(tile)?;
+      rules.weights[index] = count;
+    }
+    Some(rules)
+  }
+
+  /// Allows `neighbor` on the `direction` side of `tile` (and `tile` on the opposite side of `neighbor`).
+  /// Returns `false` if there is no room left for a new tile.
+  pub fn allow(&mut self, tile: u32, direction: Direction, neighbor: u32)->bool{
+    let side = side(direction);
+    let (tile, neighbor) = match (self.index_or_insert(tile), self.index_or_insert(neighbor)){
+      (Some(tile), Some(neighbor)) => (tile, neighbor),
+      _ => return false
+    };
+    self.allowed[side][tile] |= 1 << neighbor;
+    self.allowed[opposite(side)][neighbor] |= 1 << tile;
+    true
+  }
+
+  /// Allows two tiles next to each other on every side.
+  pub fn with_neighbors(mut self, tile: u32, neighbor: u32)->WfcRules{
+    for direction in Direction::CARDINAL{
+      if !self.allow(tile, direction, neighbor) {
+        warn!("wave function collapse supports at most {} different tiles, {} next to {} is skipped", WfcRules::MAX_TILES, tile, neighbor);
+        break;
+      }
+    }
+    self
+  }
+
+  /// Relative frequency of a tile, 1 by default.
+  pub fn with_weight(mut self, tile: u32, weight: f64)->WfcRules{
+    match self.index_or_insert(tile){
+      Some(index) => self.weights[index] = weight,
+      None => warn!("wave function collapse supports at most {} different tiles, {} is skipped", WfcRules::MAX_TILES, tile),
+    }
+    self
+  }
+
+  pub fn allows(&self, tile: u32, direction: Direction, neighbor: u32)->bool{
+    match (self.index(tile), self.index(neighbor)){
+      (Some(tile), Some(neighbor)) => self.allowed[side(direction)][tile] & 1 << neighbor != 0,
+      _ => false
+    }
+  }
+
+  pub fn tiles(&self)->&[u32]{
+    &self.tiles
+  }
+
+  fn index(&self, tile: u32)->Option<usize>{
+    self.tiles.iter().position(|&known| known == tile)
+  }
+
+  /// Index of a tile, added if it is new, `None` if there are `MAX_TILES` tiles already.
+  fn index_or_insert(&mut self, tile: u32)->Option<usize>{
+    if let Some(index) = self.index(tile) {
+      return Some(index);
+    }
+    if self.tiles.len() >= WfcRules::MAX_TILES {
+      return None;
+    }
+    self.tiles.push(tile);
+    self.weights.push(1.);
+    for allowed in self.allowed.iter_mut(){
+      allowed.push(0);
+    }
+    Some(self.tiles.len() - 1)
+  }
+
+  fn all(&self)->u128{
+    u128::MAX.checked_shr((WfcRules::MAX_TILES - self.tiles.len()) as u32).unwrap_or(0)
+  }
+
+  /// Tiles allowed on `side` of any of the `tiles`.
+  fn next(&self, tiles: u128, side: usize)->u128{
+    (0..self.tiles.len())
+      .filter(|&tile| tiles & 1 << tile != 0)
+      .fold(0, |allowed, tile| allowed | self.allowed[side][tile])
+  }
+
+  fn pick(&self, tiles: u128, rng: &mut SeededRng)->usize{
+    let candidates = || (0..self.tiles.len()).filter(move |&tile| tiles & 1 << tile != 0);
+    let total: f64 = candidates().map(|tile| self.weights[tile].max(0.)).sum();
+    let mut roll = rng.gen::<f64>()*total;
+    let mut last = 0;
+    for tile in candidates(){
+      if roll < self.weights[tile].max(0.) {
+        return tile;
+      }
+      roll -= self.weights[tile].max(0.);
+      last = tile;
+    }
+    last
+  }
+}
+
+/// Wave function collapse over chunks, as a pass of a `GenerationPipeline`.
+///
+/// Every chunk owns its north-west corner tile, the rest of its north row and of its west column. Corners are picked
+/// from per-tile seeds and rows and columns are sampled between them, so the borders of any chunk can be worked out
+/// without generating its neighbors. The inside of a chunk is then collapsed within its own borders and those of
+/// its east and south neighbors, which makes the result the same in any load order. When a chunk can't be solved
+/// in `attempts`, it gets filled tile by tile, leaving some mismatches.
+pub struct WfcGenerator{
+  pub rules: WfcRules,
+  pub attempts: usize,
+}
+
+impl WfcGenerator{
+  pub fn new(rules: WfcRules)->WfcGenerator{
+    WfcGenerator{
+      rules,
+      attempts: 10,
+    }
+  }
+
+  pub fn with_attempts(mut self, attempts: usize)->WfcGenerator{
+    self.attempts = attempts;
+    self
+  }
+
+  fn corner(&self, context: &GenerationContext, global_tile_index: IVec2)->usize{
+    self.rules.pick(self.rules.all(), &mut context.tile_rng(global_tile_index))
+  }
+
+  /// `length` tiles going in `direction` from the corner `start`, fitting between the corner tiles `from` and `to`.
+  fn segment(&self, context: &GenerationContext, start: IVec2, direction: Direction, length: i32, from: usize, to: usize)->Vec<usize>{
+    if length <= 0 {
+      return vec![];
+    }
+    let side = side(direction);
+    // tiles from which `to` can still be reached
+    let mut reachable = vec![0; length as usize];
+    let mut next = 1 << to;
+    for reach in reachable.iter_mut().rev(){
+      *reach = self.rules.next(next, opposite(side));
+      next = *reach;
+    }
+    let mut rng = context.tile_rng(start + direction.offset());
+    let mut previous = from;
+    reachable.into_iter().map(|reach|{
+      let allowed = self.rules.allowed[side][previous];
+      let candidates = [allowed & reach, allowed, self.rules.all()].into_iter().find(|&tiles| tiles != 0).unwrap_or_default();
+      previous = self.rules.pick(candidates, &mut rng);
+      previous
+    }).collect()
+  }
+
+  fn collapse(&self, rng: &mut SeededRng, min: IVec2, size: IVec2, fixed: &HashMap<IVec2, usize>)->Option<Vec<usize>>{
+    let cell = |index: usize| IVec2::new(index as i32 % size.x, index as i32 / size.x);
+    let index = |cell: IVec2| (cell.y*size.x + cell.x) as usize;
+    let inside = |cell: IVec2| cell.cmpge(IVec2::ZERO).all() && cell.cmplt(size).all();
+    let mut domains = vec![self.rules.all(); (size.x*size.y) as usize];
+    for (i, domain) in domains.iter_mut().enumerate(){
+      for (side, direction) in Direction::CARDINAL.iter().enumerate(){
+        if let Some(&tile) = fixed.get(&(min + cell(i) + direction.offset())) {
+          *domain &= self.rules.allowed[opposite(side)][tile];
+        }
+      }
+    }
+    let mut queue: Vec<usize> = (0..domains.len()).collect();
+    loop{
+      while let Some(i) = queue.pop(){
+        if domains[i] == 0 {
+          return None;
+        }
+        for (side, direction) in Direction::CARDINAL.iter().enumerate(){
+          let neighbor = cell(i) + direction.offset();
+          if !inside(neighbor) {
+            continue;
+          }
+          let j = index(neighbor);
+          let domain = domains[j] & self.rules.next(domains[i], side);
+          if domain != domains[j] {
+            domains[j] = domain;
+            queue.push(j);
+          }
+        }
+      }
+      let lowest = domains.iter().map(|domain| domain.count_ones()).filter(|&count| count > 1).min();
+      let lowest = match lowest{
+        Some(lowest) => lowest,
+        None => break
+      };
+      let candidates: Vec<usize> = (0..domains.len()).filter(|&i| domains[i].count_ones() == lowest).collect();
+      let i = candidates[rng.gen_range(0..candidates.len())];
+      domains[i] = 1 << self.rules.pick(domains[i], rng);
+      queue.push(i);
+    }
+    Some(domains.into_iter().map(|domain| domain.trailing_zeros() as usize).collect())
+  }
+
+  /// Fallback for unsolvable chunks, every tile only looks at the tiles already placed around it.
+  fn fill(&self, rng: &mut SeededRng, min: IVec2, size: IVec2, fixed: &HashMap<IVec2, usize>)->Vec<usize>{
+    let mut placed = fixed.clone();
+    let mut tiles = vec![];
+    for y in 0..size.y{
+      for x in 0..size.x{
+        let global_tile_index = min + IVec2::new(x, y);
+        let mut candidates = self.rules.all();
+        for (side, direction) in Direction::CARDINAL.iter().enumerate(){
+          if let Some(&tile) = placed.get(&(global_tile_index + direction.offset())) {
+            let allowed = candidates & self.rules.allowed[opposite(side)][tile];
+            if allowed != 0 {
+              candidates = allowed;
+            }
+          }
+        }
+        let tile = self.rules.pick(candidates, rng);
+        placed.insert(global_tile_index, tile);
+        tiles.push(tile);
+      }
+    }
+    tiles
+  }
+}
+
+impl ChunkGenerator for WfcGenerator{
+  fn generate(&self, context: &mut GenerationContext){
+    if self.rules.tiles.is_empty() {
+      return;
+    }
+    let size = context.chunk_size.as_ivec2();
+    let north_west = context.local_to_global(UVec2::new(0, context.chunk_size.y - 1));
+    let north_east = north_west + IVec2::new(size.x, 0);
+    let south_west = north_west + IVec2::new(0, size.y);
+    let south_east = north_west + size;
+
+    let mut fixed = HashMap::default();
+    for corner in [north_west, north_east, south_west, south_east]{
+      fixed.insert(corner, self.corner(context, corner));
+    }
+    for (start, end, direction, length) in [
+      (north_west, north_east, Direction::East, size.x - 1),
+      (north_west, south_west, Direction::South, size.y - 1),
+      (north_east, south_east, Direction::South, size.y - 1),
+      (south_west, south_east, Direction::East, size.x - 1),
+    ]{
+      let segment = self.segment(context, start, direction, length, fixed[&start], fixed[&end]);
+      for (i, tile) in segment.into_iter().enumerate(){
+        fixed.insert(start + direction.offset()*(i as i32 + 1), tile);
+      }
+    }
+
+    let (min, inner_size) = (north_west + IVec2::ONE, size - IVec2::ONE);
+    let mut rng = context.rng();
+    let inside = (0..self.attempts)
+      .find_map(|_| self.collapse(&mut rng, min, inner_size, &fixed))
+      .unwrap_or_else(|| self.fill(&mut rng, min, inner_size, &fixed));
+    for (i, tile) in inside.into_iter().enumerate(){
+      fixed.insert(min + IVec2::new(i as i32 % inner_size.x, i as i32 / inner_size.x), tile);
+    }
+    // tiles of the neighbors get clipped
+    for (global_tile_index, tile) in fixed{
+      context.set_global(global_tile_index, Some(self.rules.tiles[tile]));
+    }
+  }
+}
+
+#[cfg(test)]
+mod test{
+  use bevy::{prelude::*, utils::HashMap};
+  use crate::{bundle::ChunkAnchor, generation::GenerationPipeline, random::layer_id, spawn_around::generate_chunk_indexes, tiles::Direction};
+  use super::{WfcGenerator, WfcRules};
+
+  const WATER: u32 = 0;
+  const SAND: u32 = 1;
+  const GRASS: u32 = 2;
+  const CHUNK_SIZE: UVec2 = UVec2{x: 6, y: 5};
+
+  fn rules()->WfcRules{
+    WfcRules::new()
+      .with_neighbors(WATER, WATER)
+      .with_neighbors(WATER, SAND)
+      .with_neighbors(SAND, SAND)
+      .with_neighbors(SAND, GRASS)
+      .with_neighbors(GRASS, GRASS)
+      .with_weight(SAND, 0.3)
+  }
+
+  fn pipeline(rules: WfcRules)->GenerationPipeline{
+    GenerationPipeline::new()
+      .with_seed(9, layer_id("ground"))
+      .with_pass(WfcGenerator::new(rules))
+  }
+
+  /// Generates chunks one by one, each from a fresh cache, into a map of global tiles.
+  fn generate(rules: WfcRules, chunks: &[IVec2])->HashMap<IVec2, u32>{
+    let mut pipeline = pipeline(rules);
+    let mut tiles = HashMap::default();
+    for &chunk_index in chunks{
+      pipeline.clear();
+      for (local_tile_index, tile) in pipeline.generate(chunk_index, CHUNK_SIZE, ChunkAnchor::Center).iter(){
+        let global = crate::chunks::local_tile_index_to_global(chunk_index, CHUNK_SIZE, local_tile_index.as_ivec2(), ChunkAnchor::Center);
+        tiles.insert(global, tile);
+      }
+    }
+    tiles
+  }
+
+  #[test]
+  fn from_example_test(){
+    let rules = WfcRules::from_example(&[
+      vec![WATER, WATER, SAND],
+      vec![WATER, SAND, GRASS],
+    ]).unwrap();
+    assert_eq!(rules.tiles(), &[WATER, SAND, GRASS]);
+    assert!(rules.allows(WATER, Direction::East, SAND));
+    assert!(rules.allows(SAND, Direction::West, WATER));
+    assert!(rules.allows(WATER, Direction::South, SAND));
+    assert!(!rules.allows(SAND, Direction::South, WATER));
+    assert!(!rules.allows(WATER, Direction::East, GRASS));
+    assert_eq!(rules.weights, vec![3., 2., 1.]);
+  }
+
+  #[test]
+  fn chunks_match_across_borders_test(){
+    let rules = rules();
+    let tiles = generate(rules.clone(), &generate_chunk_indexes(IVec2::new(1, -1), 2));
+    assert_eq!(tiles.len(), 25*30);
+    for (&global_tile_index, &tile) in tiles.iter(){
+      for direction in Direction::CARDINAL{
+        if let Some(&neighbor) = tiles.get(&(global_tile_index + direction.offset())) {
+          assert!(rules.allows(tile, direction, neighbor), "{global_tile_index} {direction:?}: {tile} next to {neighbor}");
+        }
+      }
+    }
+    assert!([WATER, SAND, GRASS].iter().all(|tile| tiles.values().any(|placed| placed == tile)));
+  }
+
+  #[test]
+  fn load_order_does_not_matter_test(){
+    let mut chunks = generate_chunk_indexes(IVec2::ZERO, 2);
+    let forward = generate(rules(), &chunks);
+    chunks.reverse();
+    assert_eq!(forward, generate(rules(), &chunks));
+    // a chunk generated on its own, with none of its neighbors around
+    let alone = generate(rules(), &[IVec2::new(1, 1)]);
+    assert!(alone.iter().all(|(global_tile_index, tile)| forward[global_tile_index] == *tile));
+  }
+
+  #[test]
+  fn tile_limit_test(){
+    let row: Vec<u32> = (0..WfcRules::MAX_TILES as u32).collect();
+    let rules = WfcRules::from_example(std::slice::from_ref(&row)).unwrap();
+    assert_eq!(rules.tiles().len(), WfcRules::MAX_TILES);
+    assert_eq!(rules.all(), u128::MAX);
+    assert!(rules.allows(126, Direction::East, 127));
+    assert_eq!(generate(rules.clone(), &[IVec2::ZERO]).len(), 30);
+    assert!(WfcRules::from_example(&[row, vec![1000]]).is_none());
+    let mut full = rules.with_weight(1000, 2.);
+    assert!(!full.allow(0, Direction::North, 1000));
+    assert_eq!(full.tiles().len(), WfcRules::MAX_TILES);
+    assert_eq!(WfcRules::new().all(), 0);
+  }
+
+  #[test]
+  fn unsolvable_chunks_are_filled_test(){
+    // nothing may be next to anything
+    let rules = WfcRules::new().with_weight(WATER, 1.).with_weight(SAND, 1.);
+    let tiles = generate(rules, &[IVec2::ZERO]);
+    assert_eq!(tiles.len(), 30);
+  }
+}
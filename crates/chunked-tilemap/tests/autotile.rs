@@ -0,0 +1,117 @@
+use bevy::{prelude::*, winit::WinitPlugin, log::LogPlugin};
+use bevy_ecs_tilemap::tiles::{TileBundle, TilePos, TileStorage, TileTexture};
+use chunked_tilemap::{ChunkedTilemapPlugin, autotile::{Autotile, AutotileMode}, bundle::{ChunkedTilemap, ChunkedTilemapBundle}, fill_chunk::FillChunkEvent, spawn_chunk::PrepareChunkEvent, tiles::SetTileEvent};
+
+const CHUNK_SIZE: u32 = 5;
+const TILE_SIZE: f32 = 32.;
+const GRASS: u32 = 1;
+const TILESET: u32 = 100;
+
+/// Chunks east of the origin are held back until this is set, and their west column is left empty.
+struct FillEast(bool);
+
+fn fill_grass(
+  mut er_prepare_chunk: EventReader<PrepareChunkEvent>,
+  mut ew_fill_chunk: EventWriter<FillChunkEvent>,
+  mut held: Local<Vec<PrepareChunkEvent>>,
+  fill_east: Res<FillEast>,
+){
+  held.extend(er_prepare_chunk.iter().cloned());
+  let events: Vec<PrepareChunkEvent> = held.drain(..).collect();
+  for event in events{
+    if event.chunk_index.x > 0 && !fill_east.0 {
+      held.push(event);
+      continue;
+    }
+    let mut bundles = vec![];
+    for x in 0..CHUNK_SIZE{
+      for y in 0..CHUNK_SIZE{
+        if event.chunk_index.x > 0 && x == 0 {
+          continue;
+        }
+        bundles.push(TileBundle {
+          position: TilePos { x, y },
+          texture: TileTexture(GRASS),
+          ..Default::default()
+        });
+      }
+    }
+    ew_fill_chunk.send(FillChunkEvent{
+      bundles,
+      chunk_entity: event.chunk_entity,
+      chunk_index: event.chunk_index
+    })
+  }
+}
+
+fn get_app()->(App, Entity){
+  let mut app = App::new();
+  app
+    .add_plugins_with(DefaultPlugins, |group| {
+      group.disable::<WinitPlugin>();
+      group.disable::<LogPlugin>()
+    })
+    .add_plugin(ChunkedTilemapPlugin)
+    .insert_resource(FillEast(false))
+    .add_system(fill_grass);
+  let tilemap = app.world.spawn().insert_bundle(ChunkedTilemapBundle{
+    chunked_tilemap: ChunkedTilemap{
+      chunk_size: UVec2::new(CHUNK_SIZE, CHUNK_SIZE),
+      tile_size: Vec2::new(TILE_SIZE, TILE_SIZE),
+      range: 1,
+      ..Default::default()
+    },
+    ..Default::default()
+  }).insert(Autotile::new(AutotileMode::FourBit).with_terrain(GRASS, TILESET)).id();
+  update(&mut app);
+  (app, tilemap)
+}
+
+fn update(app: &mut App){
+  for _ in 0..5{
+    app.update();
+  }
+}
+
+fn texture(app: &App, tilemap: Entity, global_tile_index: IVec2)->Option<u32>{
+  let tilemap = app.world.get::<ChunkedTilemap>(tilemap).unwrap();
+  let (chunk_index, local_tile_index) = tilemap.global_tile_index_to_local(global_tile_index);
+  let storage = app.world.get::<TileStorage>(tilemap.chunk_entities[&chunk_index]).unwrap();
+  let entity = storage.get(&TilePos{x: local_tile_index.x as u32, y: local_tile_index.y as u32})?;
+  app.world.get::<TileTexture>(entity).map(|texture| texture.0)
+}
+
+fn east_edge(app: &App, tilemap: Entity)->IVec2{
+  let tilemap = app.world.get::<ChunkedTilemap>(tilemap).unwrap();
+  tilemap.local_tile_index_to_global(IVec2::ZERO, IVec2::new(CHUNK_SIZE as i32 - 1, 2))
+}
+
+#[test]
+fn edge_tiles_are_updated_when_neighbor_chunk_is_filled(){
+  let (mut app, tilemap) = get_app();
+  let edge = east_edge(&app, tilemap);
+  // the east neighbor is not filled yet and counts as grass
+  assert_eq!(texture(&app, tilemap, edge), Some(TILESET + 15));
+  app.world.resource_mut::<FillEast>().0 = true;
+  update(&mut app);
+  // north, south and west
+  assert_eq!(texture(&app, tilemap, edge), Some(TILESET + 1 + 4 + 8));
+  assert_eq!(texture(&app, tilemap, edge - IVec2::X), Some(TILESET + 15));
+}
+
+#[test]
+fn tiles_around_edited_tile_are_updated(){
+  let (mut app, tilemap) = get_app();
+  app.world.resource_mut::<FillEast>().0 = true;
+  update(&mut app);
+  let edge = east_edge(&app, tilemap);
+  let north = edge + IVec2::new(0, -1);
+  app.world.send_event(SetTileEvent{tilemap_entity: tilemap, global_tile_index: north, tile: None});
+  // a new tile across the chunk border
+  app.world.send_event(SetTileEvent{tilemap_entity: tilemap, global_tile_index: edge + IVec2::X, tile: Some(GRASS)});
+  update(&mut app);
+  assert_eq!(texture(&app, tilemap, north), None);
+  assert_eq!(texture(&app, tilemap, edge), Some(TILESET + 2 + 4 + 8));
+  // the rest of its column is empty
+  assert_eq!(texture(&app, tilemap, edge + IVec2::X), Some(TILESET + 2 + 8));
+}
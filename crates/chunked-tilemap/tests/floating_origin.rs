@@ -0,0 +1,121 @@
+use bevy::{prelude::*, winit::WinitPlugin, log::LogPlugin};
+use bevy_ecs_tilemap::tiles::{TileBundle, TilePos, TileTexture};
+use chunked_tilemap::{
+  ChunkedTilemapPlugin,
+  TilemapChunk,
+  bundle::{ChunkedTilemap, ChunkedTilemapBundle},
+  fill_chunk::FillChunkEvent,
+  floating_origin::{FloatingOrigin, FloatingOriginPlugin, FollowsOrigin, OriginShiftedEvent},
+  spawn_chunk::PrepareChunkEvent,
+};
+
+const CHUNK_SIZE: u32 = 5;
+const TILE_SIZE: f32 = 32.;
+const CHUNK_WORLD_SIZE: f32 = CHUNK_SIZE as f32*TILE_SIZE;
+// large enough for chunks loaded before a shift to stay in range after it
+const RANGE: i32 = 7;
+
+fn fill_chunk(
+  mut er_prepare_chunk: EventReader<PrepareChunkEvent>,
+  mut ew_fill_chunk: EventWriter<FillChunkEvent>,
+){
+  for event in er_prepare_chunk.iter(){
+    ew_fill_chunk.send(FillChunkEvent{
+      bundles: vec![TileBundle {
+        position: TilePos { x: 0, y: 0 },
+        texture: TileTexture(1),
+        ..Default::default()
+      }],
+      chunk_entity: event.chunk_entity,
+      chunk_index: event.chunk_index
+    })
+  }
+}
+
+fn get_app()->App{
+  let mut app = App::new();
+  app
+    .add_plugins_with(DefaultPlugins, |group| {
+      group.disable::<WinitPlugin>();
+      group.disable::<LogPlugin>()
+    })
+    .add_plugin(ChunkedTilemapPlugin)
+    .add_plugin(FloatingOriginPlugin)
+    .insert_resource(FloatingOrigin{threshold: 1000.})
+    .add_system(fill_chunk);
+  app
+}
+
+fn spawn_far_away(app: &mut App, origin: IVec2)->(Entity, Entity){
+  let tilemap = app.world.spawn().insert_bundle(ChunkedTilemapBundle{
+    chunked_tilemap: ChunkedTilemap{
+      chunk_size: UVec2::new(CHUNK_SIZE, CHUNK_SIZE),
+      tile_size: Vec2::new(TILE_SIZE, TILE_SIZE),
+      range: RANGE,
+      origin,
+      current_chunk: origin,
+      ..Default::default()
+    },
+    ..Default::default()
+  }).id();
+  let player = app.world.spawn()
+    .insert_bundle(SpatialBundle::default())
+    .insert(FollowsOrigin)
+    .id();
+  (tilemap, player)
+}
+
+fn move_to(app: &mut App, tilemap: Entity, player: Entity, position: Vec2){
+  app.world.get_mut::<ChunkedTilemap>(tilemap).unwrap().center = position;
+  app.world.get_mut::<Transform>(player).unwrap().translation = position.extend(0.);
+}
+
+#[test]
+fn should_not_shift_inside_of_threshold(){
+  let mut app = get_app();
+  let (tilemap, player) = spawn_far_away(&mut app, IVec2::new(10_000_000, -10_000_000));
+  move_to(&mut app, tilemap, player, Vec2::new(900., 0.));
+  app.update();
+  let tilemap = app.world.get::<ChunkedTilemap>(tilemap).unwrap();
+  assert_eq!(tilemap.origin, IVec2::new(10_000_000, -10_000_000));
+  assert_eq!(tilemap.current_chunk, IVec2::new(10_000_006, -10_000_000));
+  assert!(app.world.resource::<Events<OriginShiftedEvent>>().is_empty());
+}
+
+#[test]
+fn should_recenter_keeping_chunk_indexes(){
+  let mut app = get_app();
+  let origin = IVec2::new(10_000_000, -10_000_000);
+  let (tilemap, player) = spawn_far_away(&mut app, origin);
+  for _ in 0..5{
+    app.update();
+  }
+
+  // 7 chunks east and 1 chunk south of the origin, the spawn chunk stays in range
+  move_to(&mut app, tilemap, player, Vec2::new(7.2*CHUNK_WORLD_SIZE, -1.1*CHUNK_WORLD_SIZE));
+  for _ in 0..5{
+    app.update();
+  }
+
+  assert_eq!(app.world.resource::<Events<OriginShiftedEvent>>().len(), 1);
+  let expected_translation = Vec2::new(-7., 1.)*CHUNK_WORLD_SIZE;
+  let position = Vec2::new(7.2*CHUNK_WORLD_SIZE, -1.1*CHUNK_WORLD_SIZE) + expected_translation;
+  assert!(app.world.get::<Transform>(player).unwrap().translation.truncate().distance(position) < 0.01);
+
+  let tilemap = app.world.get::<ChunkedTilemap>(tilemap).unwrap();
+  assert_eq!(tilemap.origin, origin + IVec2::new(7, 1));
+  assert!(tilemap.center.distance(position) < 0.01);
+  assert_eq!(tilemap.current_chunk, origin + IVec2::new(7, 1));
+  // the streaming went on: chunks left behind are gone, the ones loaded before the shift and after it are in range
+  assert!(!tilemap.chunks.contains(&(origin - IVec2::new(RANGE, 0))));
+  assert!(tilemap.chunks.contains(&origin) && tilemap.chunks.contains(&(origin + IVec2::new(RANGE + 7, 1))));
+  assert!(tilemap.chunks.iter().all(|&chunk_index| tilemap.in_range(chunk_index)));
+  for (&chunk_index, &chunk_entity) in tilemap.chunk_entities.iter(){
+    assert_eq!(app.world.get::<TilemapChunk>(chunk_entity).unwrap().0, chunk_index);
+    let translation = app.world.get::<Transform>(chunk_entity).unwrap().translation.truncate();
+    assert_eq!(translation, tilemap.chunk_position(chunk_index));
+    assert!(translation.abs().max_element() < 2000.);
+  }
+  let tile = tilemap.tile_at_position(tilemap.center);
+  assert_eq!(tilemap.global_tile_index_to_local(tile).0, tilemap.current_chunk);
+}
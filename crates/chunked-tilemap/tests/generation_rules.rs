@@ -0,0 +1,77 @@
+use bevy::{prelude::*, winit::WinitPlugin, log::LogPlugin};
+use chunked_tilemap::{ChunkedTilemapPlugin, bundle::{ChunkedTilemap, ChunkedTilemapBundle}, layers::LayerDependencies, rules::{GenerationRules, GenerationRulesPlugin, TilemapRules}, variants::TileVariants};
+
+const CHUNK_SIZE: u32 = 5;
+const TILE_SIZE: f32 = 32.;
+
+const RULES: &str = r#"
+  #![enable(implicit_some)]
+  (
+    variants: {
+      "grass": [(tile: 3)],
+      "trees": [(tile: 1)],
+    },
+    layers: {
+      "ground": [(variants: "grass")],
+      "trees": [(on: (layer: "ground", tiles: [3]), variants: "trees")],
+    },
+  )
+"#;
+
+fn spawn_layer(app: &mut App, handle: &Handle<GenerationRules>, layer: &str)->Entity{
+  app.world.spawn().insert_bundle(ChunkedTilemapBundle{
+    chunked_tilemap: ChunkedTilemap{
+      chunk_size: UVec2::new(CHUNK_SIZE, CHUNK_SIZE),
+      tile_size: Vec2::new(TILE_SIZE, TILE_SIZE),
+      range: 1,
+      ..Default::default()
+    },
+    ..Default::default()
+  }).insert(TilemapRules::new(handle.clone(), layer)).id()
+}
+
+fn get_app()->(App, Handle<GenerationRules>, Entity, Entity){
+  let mut app = App::new();
+  app
+    .add_plugins_with(DefaultPlugins, |group| {
+      group.disable::<WinitPlugin>();
+      group.disable::<LogPlugin>()
+    })
+    .add_plugin(ChunkedTilemapPlugin)
+    .add_plugin(GenerationRulesPlugin);
+  let rules: GenerationRules = ron::de::from_str(RULES).unwrap();
+  let handle = app.world.resource_mut::<Assets<GenerationRules>>().add(rules);
+  let ground = spawn_layer(&mut app, &handle, "ground");
+  let trees = spawn_layer(&mut app, &handle, "trees");
+  app.world.entity_mut(trees).insert(LayerDependencies::new(vec![ground]));
+  for _ in 0..5{
+    app.update();
+  }
+  (app, handle, ground, trees)
+}
+
+fn tiles(app: &App, tilemap: Entity)->Vec<u32>{
+  let tilemap = app.world.get::<ChunkedTilemap>(tilemap).unwrap();
+  assert_eq!(tilemap.generated.len(), 9);
+  tilemap.generated.values().flat_map(|data| data.iter().map(|(_, tile)| tile)).collect()
+}
+
+#[test]
+fn chunks_are_generated_from_rules(){
+  let (app, _, ground, trees) = get_app();
+  assert_eq!(tiles(&app, ground), vec![3; 9*25]);
+  assert_eq!(tiles(&app, trees), vec![1; 9*25]);
+}
+
+#[test]
+fn chunks_are_regenerated_when_rules_change(){
+  let (mut app, handle, ground, trees) = get_app();
+  app.world.resource_mut::<Assets<GenerationRules>>().get_mut(&handle).unwrap()
+    .variants.insert("grass".to_string(), TileVariants::uniform([7]));
+  for _ in 0..5{
+    app.update();
+  }
+  assert_eq!(tiles(&app, ground), vec![7; 9*25]);
+  // trees only grow on tile 3, and get regenerated along with the ground they depend on
+  assert!(tiles(&app, trees).is_empty());
+}
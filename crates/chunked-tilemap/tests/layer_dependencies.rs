@@ -0,0 +1,118 @@
+use bevy::{prelude::*, winit::WinitPlugin, log::LogPlugin};
+use bevy_ecs_tilemap::tiles::{TileBundle, TilePos, TileTexture};
+use chunked_tilemap::{ChunkedTilemapPlugin, bundle::{ChunkedTilemap, ChunkedTilemapBundle}, spawn_chunk::PrepareChunkEvent, fill_chunk::FillChunkEvent, layers::LayerDependencies};
+
+const CHUNK_SIZE: u32 = 5;
+const TILE_SIZE: f32 = 32.;
+const WATER: u32 = 2;
+const BOAT: u32 = 7;
+
+struct Layers{
+  ground: Entity,
+  boats: Entity,
+}
+
+fn fill_ground(
+  mut er_prepare_chunk: EventReader<PrepareChunkEvent>,
+  mut ew_fill_chunk: EventWriter<FillChunkEvent>,
+  layers: Res<Layers>,
+){
+  for event in er_prepare_chunk.iter().filter(|event| event.tilemap_entity == layers.ground){
+    let mut bundles = vec![];
+    for x in 0..CHUNK_SIZE{
+      for y in 0..CHUNK_SIZE{
+        bundles.push(TileBundle {
+          position: TilePos { x, y },
+          texture: TileTexture(if (x + y) % 2 == 0 { WATER } else { 1 }),
+          ..Default::default()
+        });
+      }
+    }
+    ew_fill_chunk.send(FillChunkEvent{
+      bundles,
+      chunk_entity: event.chunk_entity,
+      chunk_index: event.chunk_index
+    })
+  }
+}
+
+fn fill_boats(
+  mut er_prepare_chunk: EventReader<PrepareChunkEvent>,
+  mut ew_fill_chunk: EventWriter<FillChunkEvent>,
+  layers: Res<Layers>,
+){
+  for event in er_prepare_chunk.iter().filter(|event| event.tilemap_entity == layers.boats){
+    let ground = event.dependency(layers.ground).expect("no ground data");
+    let bundles = ground.iter()
+      .filter(|&(_, texture)| texture == WATER)
+      .map(|(position, _)| TileBundle {
+        position: TilePos { x: position.x, y: position.y },
+        texture: TileTexture(BOAT),
+        ..Default::default()
+      })
+      .collect();
+    ew_fill_chunk.send(FillChunkEvent{
+      bundles,
+      chunk_entity: event.chunk_entity,
+      chunk_index: event.chunk_index
+    })
+  }
+}
+
+fn spawn_layer(app: &mut App)->Entity{
+  app.world.spawn().insert_bundle(ChunkedTilemapBundle{
+    chunked_tilemap: ChunkedTilemap{
+      chunk_size: UVec2::new(CHUNK_SIZE, CHUNK_SIZE),
+      tile_size: Vec2::new(TILE_SIZE, TILE_SIZE),
+      range: 1,
+      ..Default::default()
+    },
+    ..Default::default()
+  }).id()
+}
+
+fn get_app(fill_ground_layer: bool)->(App, Layers){
+  let mut app = App::new();
+  app
+    .add_plugins_with(DefaultPlugins, |group| {
+      group.disable::<WinitPlugin>();
+      group.disable::<LogPlugin>()
+    })
+    .add_plugin(ChunkedTilemapPlugin)
+    .add_system(fill_boats);
+  if fill_ground_layer {
+    app.add_system(fill_ground);
+  }
+  // the dependent layer is spawned first, so its chunks are requested before the ground ones
+  let boats = spawn_layer(&mut app);
+  let ground = spawn_layer(&mut app);
+  app.world.entity_mut(boats).insert(LayerDependencies::new(vec![ground]));
+  app.insert_resource(Layers{ground, boats});
+  for _ in 0..5{
+    app.update();
+  }
+  (app, Layers{ground, boats})
+}
+
+#[test]
+fn dependent_layer_reads_dependency_data(){
+  let (app, layers) = get_app(true);
+  let ground = app.world.get::<ChunkedTilemap>(layers.ground).unwrap();
+  let boats = app.world.get::<ChunkedTilemap>(layers.boats).unwrap();
+  assert_eq!(boats.generated.len(), 9);
+  for (chunk_index, boats_data) in boats.generated.iter(){
+    let ground_data = ground.generated.get(chunk_index).unwrap();
+    for (position, _) in boats_data.iter(){
+      assert_eq!(ground_data.get(position), Some(WATER));
+    }
+    assert_eq!(boats_data.iter().count(), ground_data.iter().filter(|&(_, texture)| texture == WATER).count());
+  }
+}
+
+#[test]
+fn dependent_layer_waits_for_dependencies(){
+  let (app, layers) = get_app(false);
+  let boats = app.world.get::<ChunkedTilemap>(layers.boats).unwrap();
+  assert_eq!(boats.chunks.len(), 9);
+  assert!(boats.generated.is_empty());
+}
@@ -0,0 +1,85 @@
+use bevy::{prelude::*, winit::WinitPlugin, log::LogPlugin};
+use bevy_ecs_tilemap::tiles::{TileBundle, TilePos, TileTexture};
+use chunked_tilemap::{ChunkedTilemapPlugin, bundle::{ChunkedTilemap, ChunkedTilemapBundle}, spawn_chunk::PrepareChunkEvent, fill_chunk::FillChunkEvent, layers::{LayeredTilemap, ChunkedTilemapLayerBundle}, TilemapChunk};
+
+const CHUNK_SIZE: u32 = 5;
+const TILE_SIZE: f32 = 32.;
+
+fn fill_chunk(
+  mut er_prepare_chunk: EventReader<PrepareChunkEvent>,
+  mut ew_fill_chunk: EventWriter<FillChunkEvent>,
+){
+  for event in er_prepare_chunk.iter(){
+    ew_fill_chunk.send(FillChunkEvent{
+      bundles: vec![TileBundle {
+        position: TilePos { x: 0, y: 0 },
+        texture: TileTexture(1),
+        ..Default::default()
+      }],
+      chunk_entity: event.chunk_entity,
+      chunk_index: event.chunk_index
+    })
+  }
+}
+
+fn get_app()->(App, Entity, Vec<Entity>){
+  let mut app = App::new();
+  app
+    .add_plugins_with(DefaultPlugins, |group| {
+      group.disable::<WinitPlugin>();
+      group.disable::<LogPlugin>()
+    })
+    .add_plugin(ChunkedTilemapPlugin)
+    .add_system(fill_chunk);
+  let mut layers = vec![];
+  let root = app.world.spawn().insert_bundle(ChunkedTilemapBundle{
+    chunked_tilemap: ChunkedTilemap{
+      chunk_size: UVec2::new(CHUNK_SIZE, CHUNK_SIZE),
+      tile_size: Vec2::new(TILE_SIZE, TILE_SIZE),
+      range: 1,
+      ..Default::default()
+    },
+    ..Default::default()
+  })
+    .insert(LayeredTilemap::default())
+    .with_children(|parent|{
+      layers.push(parent.spawn_bundle(ChunkedTilemapLayerBundle::new("ground", Default::default(), 0.)).id());
+      layers.push(parent.spawn_bundle(ChunkedTilemapLayerBundle::new("trees", Default::default(), 10.)).id());
+    })
+    .id();
+  for _ in 0..5{
+    app.update();
+  }
+  (app, root, layers)
+}
+
+fn layer_chunks(app: &App, layer: Entity)->Vec<IVec2>{
+  let mut chunks: Vec<IVec2> = app.world.get::<ChunkedTilemap>(layer).unwrap().chunks.iter().copied().collect();
+  chunks.sort_by_key(|chunk_index| (chunk_index.x, chunk_index.y));
+  chunks
+}
+
+#[test]
+fn layers_share_chunks_of_the_root(){
+  let (mut app, root, layers) = get_app();
+  assert_eq!(app.world.get::<LayeredTilemap>(root).unwrap().layers, layers);
+  assert!(app.world.get::<ChunkedTilemap>(root).unwrap().chunks.is_empty());
+  assert_eq!(layer_chunks(&app, layers[0]).len(), 9);
+  assert_eq!(layer_chunks(&app, layers[0]), layer_chunks(&app, layers[1]));
+  assert_eq!(app.world.query::<&TilemapChunk>().iter(&app.world).len(), 18);
+  assert_eq!(app.world.get::<ChunkedTilemap>(layers[1]).unwrap().chunk_size, UVec2::new(CHUNK_SIZE, CHUNK_SIZE));
+}
+
+#[test]
+fn layers_follow_the_root(){
+  let (mut app, root, layers) = get_app();
+  app.world.get_mut::<ChunkedTilemap>(root).unwrap().center = Vec2::new(10.*CHUNK_SIZE as f32*TILE_SIZE, 0.);
+  // a jump this far is a teleport, the old area is unloaded in batches once the new one is filled
+  for _ in 0..10{
+    app.update();
+  }
+  let expected: Vec<IVec2> = (9..=11).flat_map(|x| (-1..=1).map(move |y| IVec2::new(x, y))).collect();
+  assert_eq!(layer_chunks(&app, layers[0]), expected);
+  assert_eq!(layer_chunks(&app, layers[1]), expected);
+  assert_eq!(app.world.query::<&TilemapChunk>().iter(&app.world).len(), 18);
+}
@@ -0,0 +1,88 @@
+use bevy::{prelude::*, winit::WinitPlugin, log::LogPlugin, ecs::system::SystemState};
+use bevy_ecs_tilemap::{tiles::{TileBundle, TilePos, TileTexture}, prelude::TilemapId};
+use chunked_tilemap::{ChunkedTilemapPlugin, bundle::{ChunkedTilemap, ChunkedTilemapBundle}, spawn_chunk::PrepareChunkEvent, fill_chunk::FillChunkEvent, tiles::{ChunkedTiles, GlobalTile, Direction}};
+
+const CHUNK_SIZE: u32 = 5;
+const TILE_SIZE: f32 = 32.;
+
+fn fill_chunk(
+  mut er_prepare_chunk: EventReader<PrepareChunkEvent>,
+  mut ew_fill_chunk: EventWriter<FillChunkEvent>,
+){
+  for event in er_prepare_chunk.iter(){
+    let mut bundles = vec![];
+    for x in 0..CHUNK_SIZE{
+      for y in 0..CHUNK_SIZE{
+        // leave the chunk's center tile empty
+        if x == CHUNK_SIZE/2 && y == CHUNK_SIZE/2 {
+          continue;
+        }
+        bundles.push(TileBundle {
+          position: TilePos { x, y },
+          texture: TileTexture(1),
+          ..Default::default()
+        });
+      }
+    }
+    ew_fill_chunk.send(FillChunkEvent{
+      bundles,
+      chunk_entity: event.chunk_entity,
+      chunk_index: event.chunk_index
+    })
+  }
+}
+
+fn get_app()->(App, Entity){
+  let mut app = App::new();
+  app
+    .add_plugins_with(DefaultPlugins, |group| {
+      group.disable::<WinitPlugin>();
+      group.disable::<LogPlugin>()
+    })
+    .add_plugin(ChunkedTilemapPlugin)
+    .add_system(fill_chunk);
+  let tilemap_entity = app.world.spawn().insert_bundle(ChunkedTilemapBundle{
+    chunked_tilemap: ChunkedTilemap{
+      chunk_size: UVec2::new(CHUNK_SIZE, CHUNK_SIZE),
+      tile_size: Vec2::new(TILE_SIZE, TILE_SIZE),
+      range: 1,
+      ..Default::default()
+    },
+    ..Default::default()
+  }).id();
+  for _ in 0..5{
+    app.update();
+  }
+  (app, tilemap_entity)
+}
+
+#[test]
+fn neighbors_cross_chunk_borders(){
+  let (mut app, tilemap_entity) = get_app();
+  let mut state: SystemState<(ChunkedTiles, Query<&TilemapId>)> = SystemState::new(&mut app.world);
+  let (tiles, q_tilemap_ids) = state.get_mut(&mut app.world);
+
+  // global tile (2, 0) is the east edge of chunk (0, 0)
+  let neighbors = tiles.neighbors(tilemap_entity, IVec2::new(2, 0), true);
+  let east = neighbors.get(Direction::East).unwrap().entity().expect("no tile east of the chunk border");
+  assert_eq!(
+    q_tilemap_ids.get(east).unwrap().0,
+    tiles.chunk_entity(tilemap_entity, IVec2::new(1, 0)).unwrap()
+  );
+  assert_eq!(neighbors.iter().filter(|(_, tile)| tile.entity().is_some()).count(), 8);
+}
+
+#[test]
+fn neighbors_report_empty_and_unloaded(){
+  let (mut app, tilemap_entity) = get_app();
+  let mut state: SystemState<ChunkedTiles> = SystemState::new(&mut app.world);
+  let tiles = state.get_mut(&mut app.world);
+
+  let neighbors = tiles.neighbors(tilemap_entity, IVec2::new(1, 0), false);
+  assert_eq!(neighbors.get(Direction::West), Some(&GlobalTile::Empty));
+
+  // global tile (7, 0) is the east edge of chunk (1, 0), chunk (2, 0) is out of range
+  let neighbors = tiles.neighbors(tilemap_entity, IVec2::new(7, 0), false);
+  assert_eq!(neighbors.get(Direction::East), Some(&GlobalTile::Unloaded));
+  assert!(neighbors.get(Direction::West).unwrap().entity().is_some());
+}
@@ -0,0 +1,73 @@
+use bevy::{prelude::*, winit::WinitPlugin, log::LogPlugin, ecs::system::SystemState};
+use bevy_ecs_tilemap::tiles::{TileBundle, TilePos, TileTexture};
+use chunked_tilemap::{ChunkedTilemapPlugin, bundle::{ChunkedTilemap, ChunkedTilemapBundle}, spawn_chunk::PrepareChunkEvent, fill_chunk::FillChunkEvent, tiles::ChunkedTiles};
+
+const CHUNK_SIZE: u32 = 5;
+const TILE_SIZE: f32 = 32.;
+const WALL: u32 = 7;
+
+// puts a single wall on global tile (5, 0), the center of chunk (1, 0)
+fn fill_chunk(
+  mut er_prepare_chunk: EventReader<PrepareChunkEvent>,
+  mut ew_fill_chunk: EventWriter<FillChunkEvent>,
+){
+  for event in er_prepare_chunk.iter(){
+    let mut bundles = vec![];
+    for x in 0..CHUNK_SIZE{
+      for y in 0..CHUNK_SIZE{
+        let is_wall = event.chunk_index == IVec2::new(1, 0) && x == 2 && y == 2;
+        bundles.push(TileBundle {
+          position: TilePos { x, y },
+          texture: TileTexture(if is_wall { WALL } else { 1 }),
+          ..Default::default()
+        });
+      }
+    }
+    ew_fill_chunk.send(FillChunkEvent{
+      bundles,
+      chunk_entity: event.chunk_entity,
+      chunk_index: event.chunk_index
+    })
+  }
+}
+
+fn get_app()->(App, Entity){
+  let mut app = App::new();
+  app
+    .add_plugins_with(DefaultPlugins, |group| {
+      group.disable::<WinitPlugin>();
+      group.disable::<LogPlugin>()
+    })
+    .add_plugin(ChunkedTilemapPlugin)
+    .add_system(fill_chunk);
+  let tilemap_entity = app.world.spawn().insert_bundle(ChunkedTilemapBundle{
+    chunked_tilemap: ChunkedTilemap{
+      chunk_size: UVec2::new(CHUNK_SIZE, CHUNK_SIZE),
+      tile_size: Vec2::new(TILE_SIZE, TILE_SIZE),
+      range: 1,
+      ..Default::default()
+    },
+    ..Default::default()
+  }).id();
+  for _ in 0..5{
+    app.update();
+  }
+  (app, tilemap_entity)
+}
+
+#[test]
+fn raycast_hits_blocking_tile_in_another_chunk(){
+  let (mut app, tilemap_entity) = get_app();
+  let mut state: SystemState<(ChunkedTiles, Query<&TileTexture>)> = SystemState::new(&mut app.world);
+  let (tiles, q_textures) = state.get_mut(&mut app.world);
+  let is_wall = |_: IVec2, entity: Entity| q_textures.get(entity).map_or(false, |texture: &TileTexture| texture.0 == WALL);
+
+  let hit = tiles.raycast(tilemap_entity, Vec2::ZERO, Vec2::X, 1000., is_wall).expect("wall not hit");
+  assert_eq!(hit.global_tile_index, IVec2::new(5, 0));
+  assert_eq!(hit.distance, 4.5*TILE_SIZE);
+  assert_eq!(hit.normal, IVec2::new(-1, 0));
+
+  assert!(tiles.raycast(tilemap_entity, Vec2::ZERO, Vec2::Y, 1000., is_wall).is_none());
+  assert!(tiles.line_of_sight(tilemap_entity, Vec2::ZERO, Vec2::new(120., 0.), is_wall));
+  assert!(!tiles.line_of_sight(tilemap_entity, Vec2::ZERO, Vec2::new(200., 0.), is_wall));
+}
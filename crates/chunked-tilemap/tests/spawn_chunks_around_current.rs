@@ -1,5 +1,5 @@
 use bevy::{prelude::*, winit::WinitPlugin, log::LogPlugin, utils::HashSet};
-use chunked_tilemap::{ChunkedTilemapPlugin, bundle::{ChunkedTilemap, ChunkedTilemapBundle}, spawn_chunk::PrepareChunkEvent};
+use chunked_tilemap::{ChunkedTilemapPlugin, bundle::{ChunkBounds, ChunkedTilemap, ChunkedTilemapBundle, OutsideBounds}, spawn_chunk::PrepareChunkEvent};
 
 const CHUNK_SIZE: u32 = 5;
 const TILE_SIZE: f32 = 32.;
@@ -36,4 +36,48 @@ fn should_emit_events(){
   
   let er = app.world.resource::<Events<PrepareChunkEvent>>();
   assert_eq!(er.len(), 8);
-}
\ No newline at end of file
+}
+
+fn spawn_bounded(app: &mut App, center: Vec2, outside: OutsideBounds)->Entity{
+  app.world.spawn().insert_bundle(ChunkedTilemapBundle{
+    chunked_tilemap: ChunkedTilemap{
+      chunk_size: UVec2::new(CHUNK_SIZE, CHUNK_SIZE),
+      tile_size: Vec2::new(TILE_SIZE, TILE_SIZE),
+      range: 1,
+      center,
+      current_chunk: (Vec2::new(center.x, -center.y)/(TILE_SIZE*CHUNK_SIZE as f32)).round().as_ivec2(),
+      bounds: Some(ChunkBounds::new(IVec2::ZERO, IVec2::new(3, 3)).with_outside(outside)),
+      ..Default::default()
+    },
+    ..Default::default()
+  }).id()
+}
+
+#[test]
+fn should_not_spawn_chunks_outside_of_bounds(){
+  let mut app = get_app();
+  let tilemap = spawn_bounded(&mut app, Vec2::ZERO, OutsideBounds::Empty);
+  app.update();
+  app.update();
+  let tilemap = app.world.get::<ChunkedTilemap>(tilemap).unwrap();
+  assert_eq!(tilemap.current_chunk, IVec2::ZERO);
+  assert_eq!(tilemap.chunks.len(), 4);
+  assert!(tilemap.chunks.iter().all(|&chunk_index| tilemap.contains_chunk(chunk_index)));
+}
+
+#[test]
+fn should_keep_current_chunk_inside_of_clamped_bounds(){
+  let mut app = get_app();
+  let far = Vec2::new(100., -100.)*TILE_SIZE*CHUNK_SIZE as f32;
+  let empty = spawn_bounded(&mut app, far, OutsideBounds::Empty);
+  let clamped = spawn_bounded(&mut app, far, OutsideBounds::Clamp);
+  for _ in 0..3{
+    app.update();
+  }
+  let empty = app.world.get::<ChunkedTilemap>(empty).unwrap();
+  assert_eq!(empty.current_chunk, IVec2::new(100, 100));
+  assert!(empty.chunks.is_empty());
+  let clamped = app.world.get::<ChunkedTilemap>(clamped).unwrap();
+  assert_eq!(clamped.current_chunk, IVec2::new(3, 3));
+  assert_eq!(clamped.chunks.len(), 4);
+}
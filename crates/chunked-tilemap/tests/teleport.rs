@@ -0,0 +1,148 @@
+use bevy::{prelude::*, winit::WinitPlugin, log::LogPlugin, ecs::event::ManualEventReader};
+use bevy_ecs_tilemap::tiles::{TileBundle, TilePos, TileTexture};
+use chunked_tilemap::{
+  ChunkedTilemapPlugin,
+  bundle::{ChunkedTilemap, ChunkedTilemapBundle},
+  spawn_chunk::PrepareChunkEvent,
+  fill_chunk::FillChunkEvent,
+  teleport::{StreamingSettled, TeleportEvent, TeleportSettings},
+};
+
+const CHUNK_SIZE: u32 = 5;
+const TILE_SIZE: f32 = 32.;
+const CHUNK_WORLD_SIZE: f32 = CHUNK_SIZE as f32*TILE_SIZE;
+
+/// Stops `fill_chunk`, chunks spawned after it is inserted never fill.
+struct StopFilling;
+
+fn fill_chunk(
+  mut er_prepare_chunk: EventReader<PrepareChunkEvent>,
+  mut ew_fill_chunk: EventWriter<FillChunkEvent>,
+  stop_filling: Option<Res<StopFilling>>,
+){
+  if stop_filling.is_some() {
+    return;
+  }
+  for event in er_prepare_chunk.iter(){
+    ew_fill_chunk.send(FillChunkEvent{
+      bundles: vec![TileBundle {
+        position: TilePos { x: 0, y: 0 },
+        texture: TileTexture(1),
+        ..Default::default()
+      }],
+      chunk_entity: event.chunk_entity,
+      chunk_index: event.chunk_index
+    })
+  }
+}
+
+fn get_app(range: i32)->(App, Entity){
+  let mut app = App::new();
+  app
+    .add_plugins_with(DefaultPlugins, |group| {
+      group.disable::<WinitPlugin>();
+      group.disable::<LogPlugin>()
+    })
+    .add_plugin(ChunkedTilemapPlugin)
+    .insert_resource(TeleportSettings{unload_batch: 2, ..Default::default()})
+    .add_system(fill_chunk);
+  let tilemap = app.world.spawn().insert_bundle(ChunkedTilemapBundle{
+    chunked_tilemap: ChunkedTilemap{
+      chunk_size: UVec2::new(CHUNK_SIZE, CHUNK_SIZE),
+      tile_size: Vec2::new(TILE_SIZE, TILE_SIZE),
+      range,
+      ..Default::default()
+    },
+    ..Default::default()
+  }).id();
+  for _ in 0..5{
+    app.update();
+  }
+  (app, tilemap)
+}
+
+fn old_chunks(app: &App, tilemap: Entity)->usize{
+  let tilemap = app.world.get::<ChunkedTilemap>(tilemap).unwrap();
+  tilemap.chunks.iter().filter(|chunk_index| chunk_index.x < 10).count()
+}
+
+#[test]
+fn should_detect_far_jumps(){
+  let (mut app, tilemap) = get_app(1);
+  app.world.get_mut::<ChunkedTilemap>(tilemap).unwrap().center = Vec2::new(2.*CHUNK_WORLD_SIZE, 0.);
+  app.update();
+  assert_eq!(app.world.get::<ChunkedTilemap>(tilemap).unwrap().teleport, None);
+  app.world.get_mut::<ChunkedTilemap>(tilemap).unwrap().center = Vec2::new(5.*CHUNK_WORLD_SIZE, 0.);
+  app.update();
+  let teleport = app.world.get::<ChunkedTilemap>(tilemap).unwrap().teleport.unwrap();
+  assert_eq!(teleport.from, IVec2::new(2, 0));
+}
+
+#[test]
+fn should_settle_before_unloading_the_old_area(){
+  let (mut app, tilemap) = get_app(2);
+  assert_eq!(old_chunks(&app, tilemap), 25);
+  app.world.resource_mut::<Events<TeleportEvent>>().send(TeleportEvent{
+    tilemap_entity: tilemap,
+    center: Vec2::new(20.*CHUNK_WORLD_SIZE, 0.),
+  });
+
+  let mut reader = ManualEventReader::<StreamingSettled>::default();
+  let mut settled = vec![];
+  for _ in 0..10{
+    app.update();
+    settled.extend(reader.iter(app.world.resource::<Events<StreamingSettled>>()).cloned());
+    if !settled.is_empty() {
+      break;
+    }
+  }
+  assert_eq!(settled, vec![StreamingSettled{tilemap_entity: tilemap, chunk_index: IVec2::new(20, 0), timed_out: false}]);
+  let state = app.world.get::<ChunkedTilemap>(tilemap).unwrap();
+  assert!(state.teleport.unwrap().settled);
+  assert_eq!(state.generated.keys().filter(|chunk_index| chunk_index.x >= 10).count(), 25);
+  assert_eq!(old_chunks(&app, tilemap), 25);
+
+  // unloaded a batch at a time
+  app.update();
+  assert_eq!(old_chunks(&app, tilemap), 23);
+  for _ in 0..20{
+    app.update();
+  }
+  assert_eq!(old_chunks(&app, tilemap), 0);
+  assert_eq!(app.world.get::<ChunkedTilemap>(tilemap).unwrap().teleport, None);
+  assert!(reader.iter(app.world.resource::<Events<StreamingSettled>>()).next().is_none());
+}
+
+#[test]
+fn should_unload_the_old_area_after_the_settle_timeout(){
+  let (mut app, tilemap) = get_app(1);
+  app.insert_resource(TeleportSettings{unload_batch: 2, settle_timeout: Some(5), ..Default::default()});
+  app.insert_resource(StopFilling);
+  assert_eq!(old_chunks(&app, tilemap), 9);
+  app.world.resource_mut::<Events<TeleportEvent>>().send(TeleportEvent{
+    tilemap_entity: tilemap,
+    center: Vec2::new(20.*CHUNK_WORLD_SIZE, 0.),
+  });
+
+  let mut reader = ManualEventReader::<StreamingSettled>::default();
+  for _ in 0..3{
+    app.update();
+  }
+  assert!(!app.world.get::<ChunkedTilemap>(tilemap).unwrap().teleport.unwrap().settled);
+  assert_eq!(old_chunks(&app, tilemap), 9);
+  assert!(reader.iter(app.world.resource::<Events<StreamingSettled>>()).next().is_none());
+
+  for _ in 0..3{
+    app.update();
+  }
+  let state = app.world.get::<ChunkedTilemap>(tilemap).unwrap();
+  assert!(state.teleport.unwrap().settled);
+  assert!(state.generated.keys().all(|chunk_index| chunk_index.x < 10));
+  let settled: Vec<StreamingSettled> = reader.iter(app.world.resource::<Events<StreamingSettled>>()).cloned().collect();
+  assert_eq!(settled, vec![StreamingSettled{tilemap_entity: tilemap, chunk_index: IVec2::new(20, 0), timed_out: true}]);
+  for _ in 0..10{
+    app.update();
+  }
+  assert_eq!(old_chunks(&app, tilemap), 0);
+  assert_eq!(app.world.get::<ChunkedTilemap>(tilemap).unwrap().teleport, None);
+}
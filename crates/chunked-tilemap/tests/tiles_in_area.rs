@@ -0,0 +1,88 @@
+use bevy::{prelude::*, winit::WinitPlugin, log::LogPlugin, ecs::system::SystemState};
+use bevy_ecs_tilemap::tiles::{TileBundle, TilePos, TileTexture};
+use chunked_tilemap::{ChunkedTilemapPlugin, bundle::{ChunkedTilemap, ChunkedTilemapBundle}, spawn_chunk::PrepareChunkEvent, fill_chunk::FillChunkEvent, tiles::ChunkedTiles};
+
+const CHUNK_SIZE: u32 = 5;
+const TILE_SIZE: f32 = 32.;
+
+fn fill_chunk(
+  mut er_prepare_chunk: EventReader<PrepareChunkEvent>,
+  mut ew_fill_chunk: EventWriter<FillChunkEvent>,
+){
+  for event in er_prepare_chunk.iter(){
+    let mut bundles = vec![];
+    for x in 0..CHUNK_SIZE{
+      for y in 0..CHUNK_SIZE{
+        bundles.push(TileBundle {
+          position: TilePos { x, y },
+          texture: TileTexture(1),
+          ..Default::default()
+        });
+      }
+    }
+    ew_fill_chunk.send(FillChunkEvent{
+      bundles,
+      chunk_entity: event.chunk_entity,
+      chunk_index: event.chunk_index
+    })
+  }
+}
+
+fn get_app()->(App, Entity){
+  let mut app = App::new();
+  app
+    .add_plugins_with(DefaultPlugins, |group| {
+      group.disable::<WinitPlugin>();
+      group.disable::<LogPlugin>()
+    })
+    .add_plugin(ChunkedTilemapPlugin)
+    .add_system(fill_chunk);
+  let tilemap_entity = app.world.spawn().insert_bundle(ChunkedTilemapBundle{
+    chunked_tilemap: ChunkedTilemap{
+      chunk_size: UVec2::new(CHUNK_SIZE, CHUNK_SIZE),
+      tile_size: Vec2::new(TILE_SIZE, TILE_SIZE),
+      range: 1,
+      ..Default::default()
+    },
+    spatial: SpatialBundle{
+      transform: Transform::from_xyz(0., 0., 10.),
+      ..Default::default()
+    },
+    ..Default::default()
+  }).id();
+  for _ in 0..5{
+    app.update();
+  }
+  (app, tilemap_entity)
+}
+
+#[test]
+fn iter_rect_skips_unloaded_chunks(){
+  let (mut app, tilemap_entity) = get_app();
+  let mut state: SystemState<ChunkedTiles> = SystemState::new(&mut app.world);
+  let tiles = state.get_mut(&mut app.world);
+
+  // loaded chunks -1..=1 cover global tiles -7..=7
+  let indexes: Vec<IVec2> = tiles.iter_rect(tilemap_entity, Vec2::new(-300., -1.), Vec2::new(300., 1.))
+    .map(|(index, _, _)| index)
+    .collect();
+  assert_eq!(indexes, (-7..=7).map(|x| IVec2::new(x, 0)).collect::<Vec<_>>());
+}
+
+#[test]
+fn iter_circle_yields_world_positions(){
+  let (mut app, tilemap_entity) = get_app();
+  let mut state: SystemState<ChunkedTiles> = SystemState::new(&mut app.world);
+  let tiles = state.get_mut(&mut app.world);
+
+  let found: Vec<(IVec2, Vec2)> = tiles.iter_circle(tilemap_entity, Vec2::new(32., 0.), 32.)
+    .map(|(index, _, position)| (index, position))
+    .collect();
+  assert_eq!(found, vec![
+    (IVec2::new(1, -1), Vec2::new(32., 32.)),
+    (IVec2::new(0, 0), Vec2::new(0., 0.)),
+    (IVec2::new(1, 0), Vec2::new(32., 0.)),
+    (IVec2::new(2, 0), Vec2::new(64., 0.)),
+    (IVec2::new(1, 1), Vec2::new(32., -32.)),
+  ]);
+}
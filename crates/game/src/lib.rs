@@ -1,5 +1,4 @@
 use bevy::{prelude::{Component, Handle, HandleUntyped, Entity}, sprite::TextureAtlas};
-use perlin2d::PerlinNoise2D;
 pub mod states;
 pub mod player;
 
@@ -31,8 +30,6 @@ pub struct AssetsLoading(pub Vec<HandleUntyped>);
 #[derive(Component)]
 pub struct DefaultCamera;
 
-pub struct WorldNoise(pub PerlinNoise2D);
-
 pub struct AppConfig{
   pub tile_size: i32,
   pub chunk_size: i32